@@ -0,0 +1,7 @@
+import { followSession, syncSession } from "~/utils/cookie";
+
+// Runs before any page mounts, so their commands already see the stored login
+export default defineNuxtPlugin(() => {
+    syncSession();
+    followSession();
+});
@@ -0,0 +1,374 @@
+use chrono::{Datelike, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::store::JsonLinesStore;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Named {
+    /// 0 for local files without a Netease id.
+    #[serde(default)]
+    pub id: u64,
+    pub name: String,
+}
+
+impl Named {
+    fn key(&self) -> String {
+        match self.id {
+            0 => self.name.to_lowercase(),
+            id => id.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TrackSource {
+    Online,
+    Cloud,
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ContextKind {
+    Playlist,
+    Album,
+    Artist,
+    Fm,
+    Search,
+    Daily,
+    Other,
+}
+
+/// Where playback was started from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayContext {
+    pub kind: ContextKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PlayEnd {
+    Finished,
+    Skipped,
+    /// Playback stopped without moving on, e.g. the app was closed.
+    Stopped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayRecord {
+    pub track: Named,
+    pub artists: Vec<Named>,
+    pub album: Option<Named>,
+    pub source: TrackSource,
+    pub context: PlayContext,
+    /// Unix time in milliseconds.
+    pub started_at: i64,
+    pub listened_ms: u64,
+    pub duration_ms: u64,
+    pub end: PlayEnd,
+}
+
+/// A half-open `[from, to)` range of unix milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Period {
+    pub from: i64,
+    pub to: i64,
+}
+
+impl Period {
+    /// The calendar year in local time.
+    pub fn year(year: i32) -> Self {
+        let start = |year| {
+            Local
+                .with_ymd_and_hms(year, 1, 1, 0, 0, 0)
+                .earliest()
+                .map(|time| time.timestamp_millis())
+                .unwrap_or_default()
+        };
+        Period {
+            from: start(year),
+            to: start(year + 1),
+        }
+    }
+
+    fn contains(&self, time: i64) -> bool {
+        self.from <= time && time < self.to
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ranked {
+    pub item: Named,
+    pub plays: usize,
+    pub listened_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyListening {
+    /// Local date, `YYYY-MM-DD`.
+    pub date: String,
+    pub listened_ms: u64,
+    pub plays: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodStats {
+    pub plays: usize,
+    pub listened_ms: u64,
+    /// Share of plays that were skipped, `0.0` without plays.
+    pub skip_rate: f64,
+    pub top_tracks: Vec<Ranked>,
+    pub top_artists: Vec<Ranked>,
+    pub top_albums: Vec<Ranked>,
+    pub daily: Vec<DailyListening>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YearReport {
+    pub year: i32,
+    pub stats: PeriodStats,
+    pub distinct_tracks: usize,
+    pub distinct_artists: usize,
+    /// Listening time per month, January first.
+    pub monthly_ms: Vec<u64>,
+    pub busiest_day: Option<DailyListening>,
+    pub first_play: Option<PlayRecord>,
+    pub plays_by_context: BTreeMap<String, usize>,
+}
+
+/// Every play, appended to a JSON Lines file and kept in memory for the statistics.
+pub struct PlayHistory {
+    store: Mutex<JsonLinesStore<PlayRecord>>,
+}
+
+impl PlayHistory {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        PlayHistory {
+            store: Mutex::new(JsonLinesStore::open(path)),
+        }
+    }
+
+    pub fn record(&self, record: PlayRecord) -> io::Result<()> {
+        self.store.lock().unwrap().append(record)
+    }
+
+    /// Most recent plays first.
+    pub fn recent(&self, limit: usize) -> Vec<PlayRecord> {
+        let store = self.store.lock().unwrap();
+        store.get().iter().rev().take(limit).cloned().collect()
+    }
+
+    fn in_period(&self, period: Period) -> Vec<PlayRecord> {
+        let store = self.store.lock().unwrap();
+        store
+            .get()
+            .iter()
+            .filter(|record| period.contains(record.started_at))
+            .cloned()
+            .collect()
+    }
+
+    pub fn stats(&self, period: Period, limit: usize) -> PeriodStats {
+        stats_of(&self.in_period(period), limit)
+    }
+
+    pub fn year_report(&self, year: i32, limit: usize) -> YearReport {
+        let records = self.in_period(Period::year(year));
+        let stats = stats_of(&records, limit);
+
+        let mut monthly_ms = vec![0; 12];
+        let mut plays_by_context = BTreeMap::new();
+        for record in &records {
+            if let Some(date) = local_date(record.started_at) {
+                monthly_ms[date.month0() as usize] += record.listened_ms;
+            }
+            let kind = serde_json::to_value(record.context.kind)
+                .ok()
+                .and_then(|kind| kind.as_str().map(str::to_string))
+                .unwrap_or_default();
+            *plays_by_context.entry(kind).or_insert(0) += 1;
+        }
+        let tracks: HashSet<String> = records.iter().map(|r| r.track.key()).collect();
+        let artists: HashSet<String> = records
+            .iter()
+            .flat_map(|r| r.artists.iter().map(Named::key))
+            .collect();
+
+        YearReport {
+            year,
+            distinct_tracks: tracks.len(),
+            distinct_artists: artists.len(),
+            monthly_ms,
+            busiest_day: stats
+                .daily
+                .iter()
+                .max_by_key(|day| day.listened_ms)
+                .cloned(),
+            first_play: records.iter().min_by_key(|r| r.started_at).cloned(),
+            plays_by_context,
+            stats,
+        }
+    }
+}
+
+fn local_date(millis: i64) -> Option<NaiveDate> {
+    Local
+        .timestamp_millis_opt(millis)
+        .single()
+        .map(|time| time.date_naive())
+}
+
+fn stats_of(records: &[PlayRecord], limit: usize) -> PeriodStats {
+    let skipped = records
+        .iter()
+        .filter(|record| record.end == PlayEnd::Skipped)
+        .count();
+
+    let mut daily: BTreeMap<NaiveDate, DailyListening> = BTreeMap::new();
+    for record in records {
+        if let Some(date) = local_date(record.started_at) {
+            let day = daily.entry(date).or_insert_with(|| DailyListening {
+                date: date.format("%Y-%m-%d").to_string(),
+                listened_ms: 0,
+                plays: 0,
+            });
+            day.listened_ms += record.listened_ms;
+            day.plays += 1;
+        }
+    }
+
+    PeriodStats {
+        plays: records.len(),
+        listened_ms: records.iter().map(|record| record.listened_ms).sum(),
+        skip_rate: match records.len() {
+            0 => 0.0,
+            plays => skipped as f64 / plays as f64,
+        },
+        top_tracks: rank(records, limit, |record| vec![record.track.clone()]),
+        top_artists: rank(records, limit, |record| record.artists.clone()),
+        top_albums: rank(records, limit, |record| {
+            record.album.iter().cloned().collect()
+        }),
+        daily: daily.into_values().collect(),
+    }
+}
+
+/// Counts plays per item, most played first and longer listening breaking ties.
+fn rank(
+    records: &[PlayRecord],
+    limit: usize,
+    items: impl Fn(&PlayRecord) -> Vec<Named>,
+) -> Vec<Ranked> {
+    let mut ranked: HashMap<String, Ranked> = HashMap::new();
+    for record in records {
+        for item in items(record) {
+            let entry = ranked.entry(item.key()).or_insert_with(|| Ranked {
+                item,
+                plays: 0,
+                listened_ms: 0,
+            });
+            entry.plays += 1;
+            entry.listened_ms += record.listened_ms;
+        }
+    }
+    let mut ranked: Vec<Ranked> = ranked.into_values().collect();
+    ranked.sort_by(|a, b| {
+        (b.plays, b.listened_ms, &a.item.name).cmp(&(a.plays, a.listened_ms, &b.item.name))
+    });
+    ranked.truncate(limit);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ContextKind, Named, Period, PlayContext, PlayEnd, PlayHistory, PlayRecord, TrackSource,
+    };
+    use crate::store::TempDir;
+    use chrono::{Local, TimeZone};
+
+    fn play(track: u64, artist: &str, day: u32, listened_ms: u64, end: PlayEnd) -> PlayRecord {
+        PlayRecord {
+            track: Named {
+                id: track,
+                name: format!("track {}", track),
+            },
+            artists: vec![Named {
+                id: 0,
+                name: artist.to_string(),
+            }],
+            album: None,
+            source: TrackSource::Online,
+            context: PlayContext {
+                kind: ContextKind::Playlist,
+                id: Some("1".to_string()),
+            },
+            started_at: Local
+                .with_ymd_and_hms(2024, 3, day, 12, 0, 0)
+                .unwrap()
+                .timestamp_millis(),
+            listened_ms,
+            duration_ms: 200_000,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_play_history_stats() {
+        let dir = TempDir::new("history");
+        let path = dir.join("history.jsonl");
+        let history = PlayHistory::open(&path);
+        history
+            .record(play(1, "A", 1, 200_000, PlayEnd::Finished))
+            .unwrap();
+        history
+            .record(play(1, "A", 1, 200_000, PlayEnd::Finished))
+            .unwrap();
+        history
+            .record(play(2, "a", 2, 10_000, PlayEnd::Skipped))
+            .unwrap();
+        history
+            .record(play(3, "B", 2, 90_000, PlayEnd::Stopped))
+            .unwrap();
+        std::fs::write(
+            &path,
+            std::fs::read_to_string(&path).unwrap() + "{ truncated\n",
+        )
+        .unwrap();
+
+        let history = PlayHistory::open(&path);
+        let stats = history.stats(Period::year(2024), 10);
+        assert_eq!(stats.plays, 4);
+        assert_eq!(stats.skip_rate, 0.25);
+        assert_eq!(stats.top_tracks[0].item.id, 1);
+        assert_eq!(stats.top_tracks[0].plays, 2);
+        // artists without ids are merged by name
+        assert_eq!(
+            (stats.top_artists[0].plays, stats.top_artists.len()),
+            (3, 2)
+        );
+        assert_eq!(stats.daily.len(), 2);
+        assert_eq!(stats.daily[1].date, "2024-03-02");
+        assert_eq!(stats.daily[1].listened_ms, 100_000);
+        assert_eq!(history.stats(Period::year(2023), 10).plays, 0);
+
+        let report = history.year_report(2024, 5);
+        assert_eq!(report.distinct_tracks, 3);
+        assert_eq!(report.monthly_ms[2], 500_000);
+        assert_eq!(report.busiest_day.unwrap().date, "2024-03-01");
+        assert_eq!(report.plays_by_context["playlist"], 4);
+        assert_eq!(history.recent(1)[0].track.id, 3);
+    }
+}
@@ -0,0 +1,20 @@
+use serde_json::Value;
+
+use crate::history::Named;
+
+/// The string at `key`, or an empty string when it is missing or not a string.
+pub(crate) fn text(value: &Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// An id and name pair read from `id_key` and `name_key`; `None` without a numeric id.
+pub(crate) fn named(value: &Value, id_key: &str, name_key: &str) -> Option<Named> {
+    Some(Named {
+        id: value.get(id_key)?.as_u64()?,
+        name: text(value, name_key),
+    })
+}
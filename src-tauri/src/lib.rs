@@ -1,4 +1,8 @@
+pub mod history;
+pub mod json;
+pub mod mpris;
 pub mod netease;
+pub mod store;
 
 use serde::{Deserialize, Serialize};
 
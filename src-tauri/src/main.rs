@@ -1,8 +1,154 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+use std::sync::Arc;
+use tauri::Manager;
+
 fn main() {
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![greet, get_params])
+        .setup(|app| {
+            let data_dir = app
+                .path_resolver()
+                .app_data_dir()
+                .expect("no app data directory");
+            // the one login every command and background job runs with
+            let handle = app.app_handle();
+            let session = NeteaseClient::new("").on_refresh(Arc::new(move |cookie| {
+                let _ = handle.emit_all("session://cookie", cookie);
+            }));
+            app.manage(session.clone());
+            app.manage(likes::LikeMirror::open(data_dir.join("likes.json")));
+            app.manage(PlayHistory::open(data_dir.join("history.jsonl")));
+            app.manage(weblog::PlayReporter::open(data_dir.join("weblog.json")));
+            let daily = Arc::new(checkin::CheckIn::open(data_dir.join("checkin.json")));
+            tauri::async_runtime::spawn(checkin::schedule(daily.clone(), session.clone()));
+            app.manage(daily);
+            app.manage(cloud::CloudUploader::open(
+                data_dir.join("cloud-uploads.json"),
+            ));
+            let catalogue = Arc::new(toplist::ToplistCatalogue::open(
+                data_dir.join("toplists.json"),
+            ));
+            let charts = Arc::new(toplist::ToplistHistory::open(data_dir.join("charts.jsonl")));
+            tauri::async_runtime::spawn(toplist::schedule(charts.clone(), catalogue.clone()));
+            app.manage(catalogue);
+            app.manage(charts);
+            app.manage(video::VideoResolver::default());
+            app.manage(social::SocialSnapshots::open(data_dir.join("social.json")));
+            app.manage(recommend::DailyRecommendations::open(
+                data_dir.join("recommend.json"),
+            ));
+            app.manage(podcast::PodcastLibrary::open(
+                data_dir.join("podcasts.json"),
+            ));
+            let releases = Arc::new(artist::ReleaseWatcher::open(data_dir.join("releases.json")));
+            let handle = app.app_handle();
+            let notify: artist::Notify = Arc::new(move |found| {
+                let _ = handle.emit_all("releases://new", found);
+            });
+            tauri::async_runtime::spawn(artist::schedule(
+                releases.clone(),
+                session.clone(),
+                notify,
+            ));
+            app.manage(releases);
+            let messages = Arc::new(message::MessagePoller::default());
+            let handle = app.app_handle();
+            let notify: message::Notify = Arc::new(move |update| {
+                let _ = handle.emit_all("messages://update", update);
+            });
+            tauri::async_runtime::spawn(message::schedule(
+                messages.clone(),
+                session.clone(),
+                notify,
+            ));
+            app.manage(messages);
+            let handle = app.app_handle();
+            let control: mpris::Control = Arc::new(move |command| {
+                let _ = handle.emit_all("mpris://command", command);
+            });
+            app.manage(tauri::async_runtime::block_on(mpris::Mpris::start(control)));
+            app.manage(tauri::async_runtime::block_on(feed::FeedServer::start(
+                feed::FEED_PORT,
+                session,
+            ))?);
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            get_params,
+            session_set_cookie,
+            playlist_all_tracks,
+            playlist_add_tracks,
+            playlist_remove_tracks,
+            playlist_reorder_tracks,
+            playlist_dedupe,
+            playlist_copy,
+            playlist_export,
+            playlist_import_match,
+            playlist_import_create,
+            playlist_import,
+            likes_sync,
+            likes_replay,
+            likes_set,
+            likes_is_liked,
+            likes_ids,
+            likes_songs,
+            cloud_upload,
+            cloud_suggest_match,
+            cloud_rebind,
+            cloud_match_library,
+            history_record,
+            history_recent,
+            history_stats,
+            history_year_report,
+            weblog_report,
+            weblog_flush,
+            weblog_set_enabled,
+            weblog_enabled,
+            checkin_set_enabled,
+            checkin_state,
+            checkin_run,
+            mpris_update,
+            comments_open,
+            comments_more,
+            comments_replies,
+            comment_send,
+            toplist_all,
+            toplist_find,
+            toplist_movement,
+            toplist_timeline,
+            video_detail,
+            video_resolve,
+            video_download,
+            podcast_sync,
+            podcast_load_older,
+            podcast_radios,
+            podcast_episodes,
+            podcast_unplayed,
+            podcast_resume_position,
+            podcast_set_position,
+            podcast_set_played,
+            feed_url,
+            feed_export,
+            artist_discography,
+            artist_songs,
+            releases_feed,
+            releases_mark_read,
+            releases_check,
+            messages_conversations,
+            messages_history,
+            messages_unread,
+            messages_send,
+            events_timeline,
+            events_user,
+            events_publish,
+            social_snapshot,
+            social_export,
+            recommend_today,
+            recommend_dislike,
+            recommend_history_dates,
+            recommend_history_day,
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -12,169 +158,794 @@ fn greet(name: &str) -> String {
     format!("Hello, {}!", name)
 }
 
-use app::{netease::*, FormatParams, Options};
+use app::history::{Period, PlayHistory, PlayRecord};
+use app::mpris;
+use app::{netease::client::NeteaseClient, netease::*, FormatParams, Options};
 
 #[tauri::command]
 fn get_params(options: Options) -> FormatParams {
-    match options.url {
-        "/album/detail/dynamic" => music_api::index_album_detail_dynamic(options),
-        "/album/newest" => music_api::index_album_newest(options),
-        "/album/sub" => music_api::index_album_sub(options),
-        "/album/sublist" => music_api::index_album_sublist(options),
-        "/album" => music_api::index_album(options),
-        "/artist/detail" => music_api::index_artist_detail(options),
-        "/artist/album" => music_api::index_artist_album(options),
-        "/artist/desc" => music_api::index_artist_desc(options),
-        "/artist/list" => music_api::index_artist_list(options),
-        "/artist/mv" => music_api::index_artist_mv(options),
-        "/artist/sub" => music_api::index_artist_sub(options),
-        "/artist/sublist" => music_api::index_artist_sublist(options),
-        "/artist/top/song" => music_api::index_artist_top_song(options),
-        "/artists" => music_api::index_artists(options),
-        "/banner" => music_api::index_banner(options),
-        "/check/music" => music_api::index_check_music(options),
-        "/comment/album" => music_api::index_comment_album(options),
-        "/comment/dj" => music_api::index_comment_dj(options),
-        "/comment/event" => music_api::index_comment_event(options),
-        "/comment/hot" => music_api::index_comment_hot(options),
-        "/comment/hotwall/list" => music_api::index_comment_hotwall_list(options),
-        "/comment/like" => music_api::index_comment_like(options),
-        "/comment/music" => music_api::index_comment_music(options),
-        "/comment/mv" => music_api::index_comment_mv(options),
-        "/comment/playlist" => music_api::index_comment_playlist(options),
-        "/comment" => music_api::index_comment(options),
-        "/daily_signin" => music_api::index_daily_sigin(options),
-        "/digitalAlbum/purchased" => music_api::index_digitalAlbum_purchased(options),
-        "/dj/banner" => music_api::index_dj_banner(options),
-        "/dj/category/excludehot" => music_api::index_dj_category_excludehot(options),
-        "/dj/category/recommend" => music_api::index_dj_category_recommend(options),
-        "/dj/catelist" => music_api::index_dj_catelist(options),
-        "/dj/detail" => music_api::index_dj_detail(options),
-        "/dj/hot" => music_api::index_dj_hot(options),
-        "/dj/paygift" => music_api::index_dj_paygift(options),
-        "/dj/program/detail" => music_api::index_dj_program_detail(options),
-        "/dj/program/toplist/hours" => music_api::index_dj_program_toplist_hours(options),
-        "/dj/program/toplist" => music_api::index_dj_program_toplist(options),
-        "/dj/program" => music_api::index_dj_program(options),
-        "/dj/radio/hot" => music_api::index_dj_radio_hot(options),
-        "/dj/recommend/type" => music_api::index_dj_recommend_type(options),
-        "/dj/recommend" => music_api::index_dj_recommend(options),
-        "/dj/sub" => music_api::index_dj_sub(options),
-        "/dj/sublist" => music_api::index_dj_sublist(options),
-        "/dj/today/perfered" => music_api::index_dj_today_perfered(options),
-        "/dj/toplist/hours" => music_api::index_dj_toplist_hours(options),
-        "/dj/toplist/newcomer" => music_api::index_dj_toplist_newcomer(options),
-        "/dj/toplist/pay" => music_api::index_dj_toplist_pay(options),
-        "/dj/toplist/popular" => music_api::index_dj_toplist_popular(options),
-        "/dj/toplist" => music_api::index_dj_toplist(options),
-        "/event/del" => music_api::index_event_del(options),
-        "/event/forward" => music_api::index_event_forward(options),
-        "/event" => music_api::index_event(options),
-        "/fm/trash" => music_api::index_fm_trash(options),
-        "/follow" => music_api::index_follow(options),
-        "/hot/topic" => music_api::index_hot_topic(options),
-        "/like" => music_api::index_like(options),
-        "/likelist" => music_api::index_likelist(options),
-        "/login/cellphone" => music_api::index_login_cellphone(options),
-        "/login/qr/key" => music_api::index_login_qr_key(options),
-        "/login/qr/create" => music_api::index_login_qr_create(options),
-        "/login/qr/check" => music_api::index_login_qr_check(options),
-        "/login/status" => music_api::index_login_status(options),
-        "/login/refresh" => music_api::index_login_refresh(options),
-        "/logout" => music_api::index_logout(options),
-        "/lyric" => music_api::index_lyric(options),
-        "/msg/comments" => music_api::index_msg_comments(options),
-        "/msg/forwards" => music_api::index_msg_forwards(options),
-        "/msg/notices" => music_api::index_msg_notices(options),
-        "/msg/private/history" => music_api::index_msg_private_history(options),
-        "/msg/private" => music_api::index_msg_private(options),
-        "/mv/all" => music_api::index_mv_all(options),
-        "/mv/detail" => music_api::index_mv_detail(options),
-        "/mv/exclusive/rcmd" => music_api::index_mv_exclusive_rcmd(options),
-        "/mv/first" => music_api::index_mv_first(options),
-        "/mv/sub" => music_api::index_mv_sub(options),
-        "/mv/sublist" => music_api::index_mv_sublist(options),
-        "/mv/url" => music_api::index_mv_url(options),
-        "/personal/fm" => music_api::index_personal_fm(options),
-        "/personalized/djprogram" => music_api::index_personalized_djprogram(options),
-        "/personalized/mv" => music_api::index_personalized_mv(options),
-        "/personalized/newsong" => music_api::index_personalized_newsong(options),
-        "/personalized/privatecontent" => music_api::index_personalized_privatecontent(options),
-        "/personalized" => music_api::index_personalized(options),
-        "/playlist/catlist" => music_api::index_playlist_catlist(options),
-        "/playlist/create" => music_api::index_playlist_create(options),
-        "/playlist/delete" => music_api::index_playlist_delete(options),
-        "/playlist/desc_update" => music_api::index_playlist_desc_update(options),
-        "/playlist/detail" => music_api::index_playlist_detail(options),
-        "/playlist/hot" => music_api::index_playlist_hot(options),
-        "/playlist/name/update" => music_api::index_playlist_name_update(options),
-        "/playlist/subscribe" => music_api::index_playlist_subscribe(options),
-        "/playlist/subscribers" => music_api::index_playlist_subscribers(options),
-        "/playlist/tags/update" => music_api::index_playlist_tags_update(options),
-        "/playlist/tracks" => music_api::index_playlist_tracks(options),
-        "/playlist/update" => music_api::index_playlist_update(options),
-        "/playmode/intelligence/list" => music_api::index_playmode_intelligence_list(options),
-        "/program/recommend" => music_api::index_program_recommend(options),
-        "/rebind" => music_api::index_rebind(options),
-        "/recommend/resource" => music_api::index_recommend_resource(options),
-        "/recommend/songs" => music_api::index_recommend_songs(options),
-        "/register/cellphone" => music_api::index_register_cellphone(options),
-        "/related/allvideo" => music_api::index_related_allvideo(options),
-        "/related/playlist" => music_api::index_related_playlist(options),
-        "/resource/like" => music_api::index_resource_like(options),
-        "/search/default" => music_api::index_search_default(options),
-        "/search/hot/detail" => music_api::index_search_hot_detail(options),
-        "/search/hot" => music_api::index_search_hot(options),
-        "/search/multimatch" => music_api::index_search_multimatch(options),
-        "/search/suggest" => music_api::index_search_suggest(options),
-        "/search" => music_api::index_search(options),
-        "/send/playlist" => music_api::index_send_playlist(options),
-        "/send/text" => music_api::index_send_text(options),
-        "/setting" => music_api::index_setting(options),
-        "/share/resource" => music_api::index_share_resource(options),
-        "/simi/artist" => music_api::index_simi_artist(options),
-        "/simi/mv" => music_api::index_simi_mv(options),
-        "/simi/playlist" => music_api::index_simi_playlist(options),
-        "/simi/song" => music_api::index_simi_song(options),
-        "/simi/user" => music_api::index_simi_user(options),
-        "/song/detail" => music_api::index_song_detail(options),
-        "/song/url" => music_api::index_song_url(options),
-        "/song/url/v1" => music_api::index_song_url_v1(options),
-        "/top/album" => music_api::index_top_album(options),
-        "/top/artist" => music_api::index_top_artist(options),
-        "/top/list" => music_api::index_top_list(options),
-        "/top/mv" => music_api::index_top_mv(options),
-        "/top/playlist/highquality" => music_api::index_top_playlist_highquality(options),
-        "/top/playlist" => music_api::index_top_playlist(options),
-        "/top/song" => music_api::index_top_song(options),
-        "/toplist/artist" => music_api::index_toplist_artist(options),
-        "/toplist/detail" => music_api::index_toplist_detail(options),
-        "/toplist" => music_api::index_toplist(options),
-        "/user/audio" => music_api::index_user_audio(options),
-        "/user/cloud/del" => music_api::index_user_cloud_del(options),
-        "/user/cloud/detail" => music_api::index_user_cloud_detail(options),
-        "/user/cloud" => music_api::index_user_cloud(options),
-        "/user/detail" => music_api::index_user_detail(options),
-        "/user/dj" => music_api::index_user_dj(options),
-        "/user/event" => music_api::index_user_event(options),
-        "/user/followeds" => music_api::index_user_followeds(options),
-        "/user/follows" => music_api::index_user_follows(options),
-        "/user/playlist" => music_api::index_user_playlist(options),
-        "/user/record" => music_api::index_user_record(options),
-        "/user/account" => music_api::index_user_account(options),
-        "/user/subcount" => music_api::index_user_subcount(options),
-        "/user/update" => music_api::index_user_update(options),
-        "/video/detail" => music_api::index_video_detail(options),
-        "/video/group/list" => music_api::index_video_group_list(options),
-        "/video/group" => music_api::index_video_group(options),
-        "/video/sub" => music_api::index_video_sub(options),
-        "/video/url" => music_api::index_video_url(options),
-        "/weblog" => music_api::index_weblog(options),
-        _ => FormatParams {
-            url: "".to_string(),
-            headers: vec![],
-            body: "".to_string(),
-            method: "POST".to_string()
-        },
+    music_api::dispatch(options)
+}
+
+/// Hands the login cookie to the commands, background jobs and feed server; `None` after
+/// logout. A cookie renewed by a login refresh comes back as `session://cookie`.
+#[tauri::command]
+fn session_set_cookie(
+    cookie: Option<String>,
+    session: tauri::State<NeteaseClient>,
+    messages: tauri::State<Arc<message::MessagePoller>>,
+) {
+    let cookie = cookie.unwrap_or_default();
+    if session.cookie() != cookie {
+        messages.reset();
     }
+    session.set_cookie(&cookie);
+}
+
+#[tauri::command]
+async fn playlist_all_tracks(
+    client: tauri::State<'_, NeteaseClient>,
+    id: String,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<playlist::PlaylistTracks, String> {
+    playlist::playlist_all_tracks(&client, &id, limit, offset.unwrap_or(0))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn playlist_add_tracks(
+    client: tauri::State<'_, NeteaseClient>,
+    pid: String,
+    ids: Vec<u64>,
+) -> Result<playlist::EditReport, String> {
+    playlist::PlaylistEditor::new(&client)
+        .add(&pid, &ids)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn playlist_remove_tracks(
+    client: tauri::State<'_, NeteaseClient>,
+    pid: String,
+    ids: Vec<u64>,
+) -> Result<playlist::EditReport, String> {
+    playlist::PlaylistEditor::new(&client)
+        .remove(&pid, &ids)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn playlist_reorder_tracks(
+    client: tauri::State<'_, NeteaseClient>,
+    pid: String,
+    ids: Vec<u64>,
+) -> Result<(), String> {
+    playlist::PlaylistEditor::new(&client)
+        .reorder(&pid, &ids)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn playlist_dedupe(
+    client: tauri::State<'_, NeteaseClient>,
+    pid: String,
+) -> Result<playlist::EditReport, String> {
+    playlist::PlaylistEditor::new(&client)
+        .dedupe(&pid)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn playlist_copy(
+    client: tauri::State<'_, NeteaseClient>,
+    source: String,
+    name: String,
+    privacy: Option<String>,
+) -> Result<playlist::EditReport, String> {
+    playlist::PlaylistEditor::new(&client)
+        .copy(&source, &name, privacy.as_deref().unwrap_or("0"))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn playlist_export(
+    client: tauri::State<'_, NeteaseClient>,
+    id: String,
+    format: export::ExportFormat,
+    path: String,
+    local_dir: Option<String>,
+) -> Result<(), String> {
+    let snapshot = export::fetch_snapshot(&client, &id)
+        .await
+        .map_err(|err| err.to_string())?;
+    let content = snapshot.render(format, local_dir.as_deref().map(std::path::Path::new));
+    std::fs::write(path, content).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn playlist_import_match(
+    client: tauri::State<'_, NeteaseClient>,
+    content: String,
+    format: import::ImportFormat,
+) -> Result<Vec<import::EntryMatch>, String> {
+    let entries = import::parse_entries(&content, format);
+    Ok(import::match_entries(&client, entries).await)
+}
+
+#[tauri::command]
+async fn playlist_import_create(
+    client: tauri::State<'_, NeteaseClient>,
+    name: String,
+    privacy: Option<String>,
+    ids: Vec<u64>,
+) -> Result<playlist::EditReport, String> {
+    import::create_from_ids(&client, &name, privacy.as_deref().unwrap_or("0"), &ids)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn playlist_import(
+    client: tauri::State<'_, NeteaseClient>,
+    name: String,
+    privacy: Option<String>,
+    content: String,
+    format: import::ImportFormat,
+    include_review: Option<bool>,
+) -> Result<import::ImportReport, String> {
+    import::import_playlist(
+        &client,
+        &name,
+        privacy.as_deref().unwrap_or("0"),
+        &content,
+        format,
+        include_review.unwrap_or(false),
+    )
+    .await
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn likes_sync(
+    client: tauri::State<'_, NeteaseClient>,
+    uid: u64,
+    mirror: tauri::State<'_, likes::LikeMirror>,
+) -> Result<likes::LikesSync, String> {
+    mirror
+        .sync(&client, uid)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Called by the frontend when it comes back online; returns the toggles still queued.
+#[tauri::command]
+async fn likes_replay(
+    client: tauri::State<'_, NeteaseClient>,
+    mirror: tauri::State<'_, likes::LikeMirror>,
+) -> Result<usize, String> {
+    mirror.replay(&client).await.map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn likes_set(
+    client: tauri::State<'_, NeteaseClient>,
+    id: u64,
+    like: bool,
+    mirror: tauri::State<'_, likes::LikeMirror>,
+) -> Result<bool, String> {
+    mirror
+        .set_liked(&client, id, like)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn likes_is_liked(id: u64, mirror: tauri::State<'_, likes::LikeMirror>) -> bool {
+    mirror.is_liked(id)
+}
+
+#[tauri::command]
+fn likes_ids(mirror: tauri::State<'_, likes::LikeMirror>) -> Vec<u64> {
+    mirror.ids()
+}
+
+#[tauri::command]
+fn likes_songs(mirror: tauri::State<'_, likes::LikeMirror>) -> Vec<serde_json::Value> {
+    mirror.songs()
+}
+
+/// Emits `cloud-upload-progress` while the file moves through the pipeline.
+#[tauri::command]
+async fn cloud_upload(
+    window: tauri::Window,
+    client: tauri::State<'_, NeteaseClient>,
+    path: String,
+    uploader: tauri::State<'_, cloud::CloudUploader>,
+) -> Result<cloud::CloudUpload, String> {
+    uploader
+        .upload(&client, std::path::Path::new(&path), |progress| {
+            let _ = window.emit("cloud-upload-progress", progress);
+        })
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn cloud_suggest_match(
+    client: tauri::State<'_, NeteaseClient>,
+    song: serde_json::Value,
+) -> Result<cloud::CloudMatch, String> {
+    cloud::suggest_match(&client, &song)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn cloud_rebind(
+    client: tauri::State<'_, NeteaseClient>,
+    uid: u64,
+    song_id: u64,
+    target: u64,
+) -> Result<(), String> {
+    cloud::rebind(&client, uid, song_id, target)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn cloud_match_library(
+    client: tauri::State<'_, NeteaseClient>,
+    uid: u64,
+    dry_run: Option<bool>,
+) -> Result<Vec<cloud::CloudMatch>, String> {
+    cloud::match_library(&client, uid, dry_run.unwrap_or(true))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn history_record(record: PlayRecord, history: tauri::State<PlayHistory>) -> Result<(), String> {
+    history.record(record).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn history_recent(limit: Option<usize>, history: tauri::State<PlayHistory>) -> Vec<PlayRecord> {
+    history.recent(limit.unwrap_or(100))
+}
+
+#[tauri::command]
+fn history_stats(
+    period: Period,
+    limit: Option<usize>,
+    history: tauri::State<PlayHistory>,
+) -> app::history::PeriodStats {
+    history.stats(period, limit.unwrap_or(10))
+}
+
+#[tauri::command]
+fn history_year_report(
+    year: i32,
+    limit: Option<usize>,
+    history: tauri::State<PlayHistory>,
+) -> app::history::YearReport {
+    history.year_report(year, limit.unwrap_or(10))
+}
+
+#[tauri::command]
+async fn weblog_report(
+    client: tauri::State<'_, NeteaseClient>,
+    log: weblog::PlayLog,
+    reporter: tauri::State<'_, weblog::PlayReporter>,
+) -> Result<(), String> {
+    reporter
+        .report(&client, log)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Sends queued play logs now, e.g. before the app quits; returns how many were sent.
+#[tauri::command]
+async fn weblog_flush(
+    client: tauri::State<'_, NeteaseClient>,
+    reporter: tauri::State<'_, weblog::PlayReporter>,
+) -> Result<usize, String> {
+    reporter.flush(&client).await.map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn weblog_set_enabled(
+    enabled: bool,
+    reporter: tauri::State<weblog::PlayReporter>,
+) -> Result<(), String> {
+    reporter.set_enabled(enabled).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn weblog_enabled(reporter: tauri::State<weblog::PlayReporter>) -> bool {
+    reporter.enabled()
+}
+
+#[tauri::command]
+fn checkin_set_enabled(
+    enabled: bool,
+    daily: tauri::State<Arc<checkin::CheckIn>>,
+) -> Result<(), String> {
+    daily.set_enabled(enabled).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn checkin_state(daily: tauri::State<Arc<checkin::CheckIn>>) -> checkin::CheckInState {
+    daily.state()
+}
+
+/// Checks in right away, even if the scheduler already did today.
+#[tauri::command]
+async fn checkin_run(
+    client: tauri::State<'_, NeteaseClient>,
+    daily: tauri::State<'_, Arc<checkin::CheckIn>>,
+) -> Result<checkin::CheckInRecord, String> {
+    daily
+        .run(&client, chrono::Local::now().date_naive())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Called by the player whenever its state changes so desktop media controls stay current.
+#[tauri::command]
+async fn mpris_update(
+    state: mpris::PlayerState,
+    mpris: tauri::State<'_, mpris::Mpris>,
+) -> Result<(), String> {
+    mpris.update(state).await
+}
+
+/// Loads the first page of a resource's comments.
+#[tauri::command]
+async fn comments_open(
+    client: tauri::State<'_, NeteaseClient>,
+    resource: comment::ResourceType,
+    id: String,
+    sort: comment::CommentSort,
+) -> Result<comment::CommentTree, String> {
+    let mut tree = comment::CommentTree::new(resource, id, sort);
+    tree.load_more(&client)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(tree)
+}
+
+#[tauri::command]
+async fn comments_more(
+    client: tauri::State<'_, NeteaseClient>,
+    tree: comment::CommentTree,
+) -> Result<comment::CommentTree, String> {
+    let mut tree = tree;
+    tree.load_more(&client)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(tree)
+}
+
+#[tauri::command]
+async fn comments_replies(
+    client: tauri::State<'_, NeteaseClient>,
+    tree: comment::CommentTree,
+    comment_id: u64,
+) -> Result<comment::CommentTree, String> {
+    let mut tree = tree;
+    tree.load_replies(&client, comment_id)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(tree)
+}
+
+#[tauri::command]
+async fn comment_send(
+    client: tauri::State<'_, NeteaseClient>,
+    resource: comment::ResourceType,
+    id: String,
+    action: comment::CommentAction,
+    content: Option<String>,
+) -> Result<comment::CommentResult, String> {
+    comment::send(
+        &client,
+        resource,
+        &id,
+        action,
+        content.as_deref().unwrap_or_default(),
+    )
+    .await
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn toplist_all(
+    client: tauri::State<'_, NeteaseClient>,
+    force: Option<bool>,
+    catalogue: tauri::State<'_, Arc<toplist::ToplistCatalogue>>,
+) -> Result<Vec<toplist::Toplist>, String> {
+    catalogue
+        .lists(&client, force.unwrap_or(false))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Finds a chart by id or name.
+#[tauri::command]
+async fn toplist_find(
+    client: tauri::State<'_, NeteaseClient>,
+    key: String,
+    catalogue: tauri::State<'_, Arc<toplist::ToplistCatalogue>>,
+) -> Result<Option<toplist::Toplist>, String> {
+    catalogue
+        .find(&client, &key)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Rank changes of a chart's latest snapshot against its previous edition.
+#[tauri::command]
+fn toplist_movement(
+    toplist_id: u64,
+    charts: tauri::State<Arc<toplist::ToplistHistory>>,
+) -> Vec<toplist::TrackMovement> {
+    charts.movement(toplist_id)
+}
+
+#[tauri::command]
+fn toplist_timeline(
+    track_id: u64,
+    charts: tauri::State<Arc<toplist::ToplistHistory>>,
+) -> Vec<toplist::ChartPoint> {
+    charts.timeline(track_id)
+}
+
+#[tauri::command]
+async fn video_detail(
+    client: tauri::State<'_, NeteaseClient>,
+    kind: video::VideoKind,
+    id: String,
+) -> Result<video::VideoDetail, String> {
+    video::detail(&client, kind, &id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Picks the best resolution up to `max_resolution` and follows redirects to the stream.
+#[tauri::command]
+async fn video_resolve(
+    client: tauri::State<'_, NeteaseClient>,
+    kind: video::VideoKind,
+    id: String,
+    max_resolution: Option<u32>,
+    resolver: tauri::State<'_, video::VideoResolver>,
+) -> Result<video::VideoSource, String> {
+    resolver
+        .resolve(&client, kind, &id, max_resolution)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn video_download(
+    window: tauri::Window,
+    source: video::VideoSource,
+    path: String,
+    resolver: tauri::State<'_, video::VideoResolver>,
+) -> Result<u64, String> {
+    resolver
+        .download(&source, std::path::Path::new(&path), |progress| {
+            let _ = window.emit("video-download-progress", progress);
+        })
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Mirrors the subscribed radios and fetches the episodes published since the last sync.
+#[tauri::command]
+async fn podcast_sync(
+    client: tauri::State<'_, NeteaseClient>,
+    library: tauri::State<'_, podcast::PodcastLibrary>,
+) -> Result<podcast::PodcastSync, String> {
+    library.sync(&client).await.map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn podcast_load_older(
+    client: tauri::State<'_, NeteaseClient>,
+    radio_id: u64,
+    library: tauri::State<'_, podcast::PodcastLibrary>,
+) -> Result<usize, String> {
+    library
+        .load_older(&client, radio_id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn podcast_radios(library: tauri::State<podcast::PodcastLibrary>) -> Vec<podcast::Radio> {
+    library.radios()
+}
+
+#[tauri::command]
+fn podcast_episodes(
+    radio_id: u64,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    library: tauri::State<podcast::PodcastLibrary>,
+) -> Vec<podcast::EpisodeView> {
+    library.episodes(radio_id, offset.unwrap_or(0), limit.unwrap_or(30))
+}
+
+/// Unplayed episodes across every subscription, newest first.
+#[tauri::command]
+fn podcast_unplayed(
+    limit: Option<usize>,
+    library: tauri::State<podcast::PodcastLibrary>,
+) -> Vec<podcast::EpisodeView> {
+    library.unplayed(limit.unwrap_or(50))
+}
+
+#[tauri::command]
+fn podcast_resume_position(episode_id: u64, library: tauri::State<podcast::PodcastLibrary>) -> u64 {
+    library.resume_position(episode_id)
+}
+
+#[tauri::command]
+fn podcast_set_position(
+    episode_id: u64,
+    position_ms: u64,
+    library: tauri::State<podcast::PodcastLibrary>,
+) -> Result<podcast::EpisodeProgress, String> {
+    library
+        .set_position(episode_id, position_ms)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn podcast_set_played(
+    episode_id: u64,
+    played: bool,
+    library: tauri::State<podcast::PodcastLibrary>,
+) -> Result<podcast::EpisodeProgress, String> {
+    library
+        .set_played(episode_id, played)
+        .map_err(|err| err.to_string())
+}
+
+/// Address of a radio's feed on the local feed server, for podcast apps to subscribe to.
+#[tauri::command]
+fn feed_url(radio_id: u64, feeds: tauri::State<feed::FeedServer>) -> String {
+    feeds.url(radio_id)
+}
+
+/// Writes a standalone feed whose enclosures are resolved stream URLs.
+#[tauri::command]
+async fn feed_export(
+    client: tauri::State<'_, NeteaseClient>,
+    radio_id: u64,
+    path: String,
+) -> Result<(), String> {
+    let rss = feed::radio_feed(&client, radio_id, feed::Enclosures::Direct)
+        .await
+        .map_err(|err| err.to_string())?;
+    std::fs::write(path, rss).map_err(|err| err.to_string())
+}
+
+/// All releases of an artist, oldest first, with re-releases folded together.
+#[tauri::command]
+async fn artist_discography(
+    client: tauri::State<'_, NeteaseClient>,
+    id: u64,
+    with_tracks: Option<bool>,
+) -> Result<artist::Discography, String> {
+    artist::discography(&client, id, with_tracks.unwrap_or(false))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn artist_songs(
+    client: tauri::State<'_, NeteaseClient>,
+    id: u64,
+    order: artist::SongOrder,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<artist::SongPage, String> {
+    artist::songs(
+        &client,
+        id,
+        order,
+        offset.unwrap_or(0),
+        limit.unwrap_or(100),
+    )
+    .await
+    .map_err(|err| err.to_string())
+}
+
+/// New albums and MVs of followed artists, newest first.
+#[tauri::command]
+fn releases_feed(
+    limit: Option<usize>,
+    releases: tauri::State<Arc<artist::ReleaseWatcher>>,
+) -> Vec<artist::NewRelease> {
+    releases.feed(limit.unwrap_or(100))
+}
+
+#[tauri::command]
+fn releases_mark_read(releases: tauri::State<Arc<artist::ReleaseWatcher>>) -> Result<(), String> {
+    releases.mark_read().map_err(|err| err.to_string())
+}
+
+/// Checks the next batch of followed artists now instead of waiting for the schedule.
+#[tauri::command]
+async fn releases_check(
+    client: tauri::State<'_, NeteaseClient>,
+    releases: tauri::State<'_, Arc<artist::ReleaseWatcher>>,
+) -> Result<Vec<artist::NewRelease>, String> {
+    releases
+        .run(&client, chrono::Utc::now().timestamp_millis())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn messages_conversations(
+    client: tauri::State<'_, NeteaseClient>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<message::ConversationPage, String> {
+    message::conversations(&client, offset.unwrap_or(0), limit.unwrap_or(30))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Pages back through a conversation with the `before` cursor of the previous page.
+#[tauri::command]
+async fn messages_history(
+    client: tauri::State<'_, NeteaseClient>,
+    user_id: u64,
+    before: Option<i64>,
+    limit: Option<usize>,
+) -> Result<message::HistoryPage, String> {
+    message::history(&client, user_id, before, limit.unwrap_or(30))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn messages_unread(
+    client: tauri::State<'_, NeteaseClient>,
+) -> Result<message::UnreadCounts, String> {
+    message::unread_counts(&client)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn messages_send(
+    client: tauri::State<'_, NeteaseClient>,
+    user_ids: Vec<u64>,
+    text: String,
+    attachment: Option<message::Outgoing>,
+) -> Result<message::SendReport, String> {
+    message::send(&client, &user_ids, &text, attachment)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// The timeline of followed users, continued with the `lasttime` of the previous page.
+#[tauri::command]
+async fn events_timeline(
+    client: tauri::State<'_, NeteaseClient>,
+    lasttime: Option<i64>,
+    limit: Option<usize>,
+) -> Result<event::EventPage, String> {
+    event::timeline(&client, lasttime, limit.unwrap_or(20))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn events_user(
+    client: tauri::State<'_, NeteaseClient>,
+    user_id: u64,
+    lasttime: Option<i64>,
+    limit: Option<usize>,
+) -> Result<event::EventPage, String> {
+    event::user_events(&client, user_id, lasttime, limit.unwrap_or(30))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn events_publish(
+    client: tauri::State<'_, NeteaseClient>,
+    text: String,
+    shared: Option<event::Shared>,
+) -> Result<Option<event::Event>, String> {
+    event::publish(&client, &text, shared)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Walks the full follow and follower lists and reports what changed since the last snapshot.
+#[tauri::command]
+async fn social_snapshot(
+    client: tauri::State<'_, NeteaseClient>,
+    user_id: u64,
+    snapshots: tauri::State<'_, social::SocialSnapshots>,
+) -> Result<social::SocialReport, String> {
+    snapshots
+        .take(&client, user_id, chrono::Utc::now().timestamp_millis())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Writes the latest snapshot of a user as CSV.
+#[tauri::command]
+fn social_export(
+    user_id: u64,
+    path: String,
+    snapshots: tauri::State<social::SocialSnapshots>,
+) -> Result<(), String> {
+    let graph = snapshots
+        .latest(user_id)
+        .ok_or_else(|| format!("no snapshot of user {}", user_id))?;
+    std::fs::write(path, graph.to_csv()).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn recommend_today(
+    client: tauri::State<'_, NeteaseClient>,
+    force: Option<bool>,
+    daily: tauri::State<'_, recommend::DailyRecommendations>,
+) -> Result<recommend::DailyList, String> {
+    daily
+        .today(
+            &client,
+            chrono::Local::now().date_naive(),
+            force.unwrap_or(false),
+        )
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Dislikes a recommended song and returns today's list with its replacement in place.
+#[tauri::command]
+async fn recommend_dislike(
+    client: tauri::State<'_, NeteaseClient>,
+    id: u64,
+    daily: tauri::State<'_, recommend::DailyRecommendations>,
+) -> Result<recommend::DailyList, String> {
+    daily
+        .dislike(&client, id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn recommend_history_dates(
+    client: tauri::State<'_, NeteaseClient>,
+) -> Result<Vec<String>, String> {
+    recommend::history_dates(&client)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn recommend_history_day(
+    client: tauri::State<'_, NeteaseClient>,
+    date: String,
+) -> Result<recommend::DailyList, String> {
+    recommend::history_day(&client, &date)
+        .await
+        .map_err(|err| err.to_string())
 }
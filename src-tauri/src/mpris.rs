@@ -0,0 +1,617 @@
+//! Publishes the player on the session bus as `org.mpris.MediaPlayer2.onelisten` so media keys,
+//! desktop media widgets and `playerctl` can see and control it.
+//!
+//! Playback itself runs in the frontend: it reports its state through [`Mpris::update`] and
+//! receives the calls made over D-Bus as [`PlayerCommand`]s. On other platforms both are no-ops.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LoopStatus {
+    None,
+    Track,
+    Playlist,
+}
+
+/// What the frontend player reports whenever something changes.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PlayerState {
+    pub status: PlaybackStatus,
+    /// The current track as returned by `song/detail`.
+    pub song: Option<Value>,
+    pub position_ms: u64,
+    /// `0.0..=1.0`.
+    pub volume: f64,
+    pub loop_status: LoopStatus,
+    pub shuffle: bool,
+    pub can_go_next: bool,
+    pub can_go_previous: bool,
+}
+
+impl Default for PlayerState {
+    fn default() -> Self {
+        PlayerState {
+            status: PlaybackStatus::Stopped,
+            song: None,
+            position_ms: 0,
+            volume: 1.0,
+            loop_status: LoopStatus::None,
+            shuffle: false,
+            can_go_next: false,
+            can_go_previous: false,
+        }
+    }
+}
+
+/// A request made over D-Bus that the frontend player has to carry out.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "action")]
+pub enum PlayerCommand {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    #[serde(rename_all = "camelCase")]
+    Seek {
+        offset_ms: i64,
+    },
+    #[serde(rename_all = "camelCase")]
+    SetPosition {
+        position_ms: u64,
+    },
+    Volume {
+        value: f64,
+    },
+    LoopStatus {
+        value: LoopStatus,
+    },
+    Shuffle {
+        value: bool,
+    },
+    Raise,
+    Quit,
+}
+
+pub type Control = Arc<dyn Fn(PlayerCommand) + Send + Sync>;
+
+pub struct Mpris {
+    #[cfg(target_os = "linux")]
+    server: Option<dbus::Server>,
+}
+
+impl Mpris {
+    /// Registers the service on the session bus; without a session bus the handle does nothing.
+    pub async fn start(control: Control) -> Self {
+        #[cfg(target_os = "linux")]
+        {
+            let server = match zbus::connection::Builder::session() {
+                Ok(builder) => dbus::Server::start(builder, control).await.ok(),
+                Err(_) => None,
+            };
+            Mpris { server }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = control;
+            Mpris {}
+        }
+    }
+
+    pub async fn update(&self, state: PlayerState) -> Result<(), String> {
+        #[cfg(target_os = "linux")]
+        if let Some(server) = &self.server {
+            return server.update(state).await.map_err(|err| err.to_string());
+        }
+        let _ = state;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod dbus {
+    use super::{Control, LoopStatus, PlaybackStatus, PlayerCommand, PlayerState};
+    use serde_json::Value as Json;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use zbus::object_server::SignalContext;
+    use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+    use zbus::{connection, fdo, interface, Connection};
+
+    const BUS_NAME: &str = "org.mpris.MediaPlayer2.onelisten";
+    const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+    const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+    type Shared = Arc<Mutex<PlayerState>>;
+
+    pub struct Server {
+        connection: Connection,
+        state: Shared,
+    }
+
+    impl Server {
+        pub async fn start(
+            builder: connection::Builder<'_>,
+            control: Control,
+        ) -> zbus::Result<Self> {
+            let state = Shared::default();
+            let connection = builder
+                .name(BUS_NAME)?
+                .serve_at(
+                    OBJECT_PATH,
+                    Root {
+                        control: control.clone(),
+                    },
+                )?
+                .serve_at(
+                    OBJECT_PATH,
+                    Player {
+                        control,
+                        state: state.clone(),
+                    },
+                )?
+                .build()
+                .await?;
+            Ok(Server { connection, state })
+        }
+
+        /// Stores the new state and signals the properties that changed.
+        pub async fn update(&self, state: PlayerState) -> zbus::Result<()> {
+            let old = std::mem::replace(&mut *self.state.lock().unwrap(), state.clone());
+            let iface = self
+                .connection
+                .object_server()
+                .interface::<_, Player>(OBJECT_PATH)
+                .await?;
+            let player = iface.get().await;
+            let ctxt = iface.signal_context();
+            if old.status != state.status {
+                player.playback_status_changed(ctxt).await?;
+            }
+            if old.song != state.song {
+                player.metadata_changed(ctxt).await?;
+            }
+            if old.volume != state.volume {
+                player.volume_changed(ctxt).await?;
+            }
+            if old.loop_status != state.loop_status {
+                player.loop_status_changed(ctxt).await?;
+            }
+            if old.shuffle != state.shuffle {
+                player.shuffle_changed(ctxt).await?;
+            }
+            if old.can_go_next != state.can_go_next {
+                player.can_go_next_changed(ctxt).await?;
+            }
+            if old.can_go_previous != state.can_go_previous {
+                player.can_go_previous_changed(ctxt).await?;
+            }
+            Ok(())
+        }
+    }
+
+    struct Root {
+        control: Control,
+    }
+
+    #[interface(name = "org.mpris.MediaPlayer2")]
+    impl Root {
+        fn raise(&self) {
+            (self.control)(PlayerCommand::Raise);
+        }
+
+        fn quit(&self) {
+            (self.control)(PlayerCommand::Quit);
+        }
+
+        #[zbus(property)]
+        fn can_quit(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_raise(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn has_track_list(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn identity(&self) -> &str {
+            "OneListen"
+        }
+
+        #[zbus(property)]
+        fn desktop_entry(&self) -> &str {
+            "onelisten"
+        }
+
+        #[zbus(property)]
+        fn supported_uri_schemes(&self) -> Vec<String> {
+            vec![]
+        }
+
+        #[zbus(property)]
+        fn supported_mime_types(&self) -> Vec<String> {
+            vec![]
+        }
+    }
+
+    struct Player {
+        control: Control,
+        state: Shared,
+    }
+
+    impl Player {
+        fn state(&self) -> PlayerState {
+            self.state.lock().unwrap().clone()
+        }
+
+        fn track_id(&self) -> Option<u64> {
+            let state = self.state.lock().unwrap();
+            state.song.as_ref()?.get("id")?.as_u64()
+        }
+    }
+
+    #[interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl Player {
+        fn next(&self) {
+            (self.control)(PlayerCommand::Next);
+        }
+
+        fn previous(&self) {
+            (self.control)(PlayerCommand::Previous);
+        }
+
+        fn pause(&self) {
+            (self.control)(PlayerCommand::Pause);
+        }
+
+        fn play_pause(&self) {
+            (self.control)(PlayerCommand::PlayPause);
+        }
+
+        fn stop(&self) {
+            (self.control)(PlayerCommand::Stop);
+        }
+
+        fn play(&self) {
+            (self.control)(PlayerCommand::Play);
+        }
+
+        /// `offset` is in microseconds, like every MPRIS time value.
+        async fn seek(
+            &self,
+            offset: i64,
+            #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        ) -> fdo::Result<()> {
+            let position = {
+                let mut state = self.state.lock().unwrap();
+                let position = (state.position_ms as i64 + offset / 1000).max(0) as u64;
+                state.position_ms = position;
+                position
+            };
+            (self.control)(PlayerCommand::Seek {
+                offset_ms: offset / 1000,
+            });
+            Player::seeked(&ctxt, position as i64 * 1000).await?;
+            Ok(())
+        }
+
+        /// Ignored unless `track_id` still names the current track, as the spec asks.
+        async fn set_position(
+            &self,
+            track_id: ObjectPath<'_>,
+            position: i64,
+            #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        ) -> fdo::Result<()> {
+            let current = self.track_id().map(track_path);
+            if position < 0 || current.as_deref() != Some(track_id.as_str()) {
+                return Ok(());
+            }
+            let position_ms = position as u64 / 1000;
+            self.state.lock().unwrap().position_ms = position_ms;
+            (self.control)(PlayerCommand::SetPosition { position_ms });
+            Player::seeked(&ctxt, position).await?;
+            Ok(())
+        }
+
+        fn open_uri(&self, _uri: &str) -> fdo::Result<()> {
+            Err(fdo::Error::NotSupported("OpenUri".to_string()))
+        }
+
+        #[zbus(signal)]
+        async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+        #[zbus(property)]
+        fn playback_status(&self) -> &str {
+            match self.state().status {
+                PlaybackStatus::Playing => "Playing",
+                PlaybackStatus::Paused => "Paused",
+                PlaybackStatus::Stopped => "Stopped",
+            }
+        }
+
+        #[zbus(property)]
+        fn loop_status(&self) -> &str {
+            match self.state().loop_status {
+                LoopStatus::None => "None",
+                LoopStatus::Track => "Track",
+                LoopStatus::Playlist => "Playlist",
+            }
+        }
+
+        #[zbus(property)]
+        fn set_loop_status(&mut self, value: &str) -> fdo::Result<()> {
+            let value = match value {
+                "None" => LoopStatus::None,
+                "Track" => LoopStatus::Track,
+                "Playlist" => LoopStatus::Playlist,
+                _ => return Err(fdo::Error::InvalidArgs(value.to_string())),
+            };
+            self.state.lock().unwrap().loop_status = value;
+            (self.control)(PlayerCommand::LoopStatus { value });
+            Ok(())
+        }
+
+        #[zbus(property)]
+        fn rate(&self) -> f64 {
+            1.0
+        }
+
+        #[zbus(property)]
+        fn set_rate(&mut self, _rate: f64) {}
+
+        #[zbus(property)]
+        fn shuffle(&self) -> bool {
+            self.state().shuffle
+        }
+
+        #[zbus(property)]
+        fn set_shuffle(&mut self, value: bool) {
+            self.state.lock().unwrap().shuffle = value;
+            (self.control)(PlayerCommand::Shuffle { value });
+        }
+
+        #[zbus(property)]
+        fn metadata(&self) -> HashMap<String, OwnedValue> {
+            match self.state().song {
+                Some(song) => metadata(&song),
+                None => {
+                    HashMap::from([("mpris:trackid".to_string(), owned(object_path(NO_TRACK)))])
+                }
+            }
+        }
+
+        #[zbus(property)]
+        fn volume(&self) -> f64 {
+            self.state().volume
+        }
+
+        #[zbus(property)]
+        fn set_volume(&mut self, value: f64) {
+            let value = value.clamp(0.0, 1.0);
+            self.state.lock().unwrap().volume = value;
+            (self.control)(PlayerCommand::Volume { value });
+        }
+
+        /// Read on demand; players do not signal position changes.
+        #[zbus(property(emits_changed_signal = "false"))]
+        fn position(&self) -> i64 {
+            self.state().position_ms as i64 * 1000
+        }
+
+        #[zbus(property)]
+        fn minimum_rate(&self) -> f64 {
+            1.0
+        }
+
+        #[zbus(property)]
+        fn maximum_rate(&self) -> f64 {
+            1.0
+        }
+
+        #[zbus(property)]
+        fn can_go_next(&self) -> bool {
+            self.state().can_go_next
+        }
+
+        #[zbus(property)]
+        fn can_go_previous(&self) -> bool {
+            self.state().can_go_previous
+        }
+
+        #[zbus(property)]
+        fn can_play(&self) -> bool {
+            self.state().song.is_some()
+        }
+
+        #[zbus(property)]
+        fn can_pause(&self) -> bool {
+            self.state().song.is_some()
+        }
+
+        #[zbus(property)]
+        fn can_seek(&self) -> bool {
+            self.state().song.is_some()
+        }
+
+        #[zbus(property(emits_changed_signal = "const"))]
+        fn can_control(&self) -> bool {
+            true
+        }
+    }
+
+    fn track_path(id: u64) -> String {
+        format!("/org/onelisten/track/{}", id)
+    }
+
+    fn object_path(path: &str) -> ObjectPath<'static> {
+        ObjectPath::try_from(path.to_string()).unwrap()
+    }
+
+    fn owned<'a>(value: impl Into<Value<'a>>) -> OwnedValue {
+        // only fds can fail to convert and none are sent here
+        OwnedValue::try_from(value.into()).unwrap()
+    }
+
+    /// Maps a `song/detail` entry onto the xesam/mpris metadata keys.
+    fn metadata(song: &Json) -> HashMap<String, OwnedValue> {
+        let text = |pointer: &str| {
+            song.pointer(pointer)
+                .and_then(Json::as_str)
+                .unwrap_or_default()
+        };
+        let artists: Vec<String> = song
+            .get("ar")
+            .and_then(Json::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|artist| artist.get("name").and_then(Json::as_str))
+            .map(str::to_string)
+            .collect();
+        let id = song.get("id").and_then(Json::as_u64).unwrap_or_default();
+        let length = song.get("dt").and_then(Json::as_i64).unwrap_or_default() * 1000;
+
+        let mut metadata = HashMap::from([
+            (
+                "mpris:trackid".to_string(),
+                owned(object_path(&track_path(id))),
+            ),
+            ("mpris:length".to_string(), owned(length)),
+            ("xesam:title".to_string(), owned(text("/name"))),
+            ("xesam:artist".to_string(), owned(artists)),
+            ("xesam:album".to_string(), owned(text("/al/name"))),
+            (
+                "xesam:url".to_string(),
+                owned(format!("https://music.163.com/song?id={}", id)),
+            ),
+        ]);
+        let cover = text("/al/picUrl");
+        if !cover.is_empty() {
+            metadata.insert("mpris:artUrl".to_string(), owned(cover));
+        }
+        metadata
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{Server, BUS_NAME, OBJECT_PATH};
+        use crate::mpris::{PlaybackStatus, PlayerCommand, PlayerState};
+        use serde_json::json;
+        use std::collections::HashMap;
+        use std::io::{BufRead, BufReader};
+        use std::process::{Command, Stdio};
+        use std::sync::{Arc, Mutex};
+        use zbus::proxy::CacheProperties;
+        use zbus::zvariant::OwnedValue;
+        use zbus::{connection, Proxy};
+
+        #[tokio::test]
+        #[ignore = "needs dbus-daemon; run with `cargo test -- --ignored`"]
+        async fn test_mpris_on_private_bus() {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address=1"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("dbus-daemon not available");
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            let address = address.trim();
+
+            let commands = Arc::new(Mutex::new(vec![]));
+            let sink = commands.clone();
+            let control = Arc::new(move |command| sink.lock().unwrap().push(command));
+            let server = Server::start(connection::Builder::address(address).unwrap(), control)
+                .await
+                .unwrap();
+
+            let client = connection::Builder::address(address)
+                .unwrap()
+                .build()
+                .await
+                .unwrap();
+            let player: Proxy = zbus::proxy::Builder::new(&client)
+                .destination(BUS_NAME)
+                .unwrap()
+                .path(OBJECT_PATH)
+                .unwrap()
+                .interface("org.mpris.MediaPlayer2.Player")
+                .unwrap()
+                .cache_properties(CacheProperties::No)
+                .build()
+                .await
+                .unwrap();
+
+            server
+                .update(PlayerState {
+                    status: PlaybackStatus::Playing,
+                    song: Some(json!({
+                        "id": 5, "name": "Song", "dt": 200000,
+                        "ar": [{ "name": "A" }, { "name": "B" }],
+                        "al": { "name": "Album", "picUrl": "https://p1.music.126.net/x.jpg" }
+                    })),
+                    position_ms: 1000,
+                    can_go_next: true,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+
+            let status: String = player.get_property("PlaybackStatus").await.unwrap();
+            assert_eq!(status, "Playing");
+            let metadata: HashMap<String, OwnedValue> =
+                player.get_property("Metadata").await.unwrap();
+            assert_eq!(<&str>::try_from(&metadata["xesam:title"]).unwrap(), "Song");
+            assert_eq!(
+                i64::try_from(&metadata["mpris:length"]).unwrap(),
+                200_000_000
+            );
+            let artists: Vec<String> = metadata["xesam:artist"]
+                .try_clone()
+                .unwrap()
+                .try_into()
+                .unwrap();
+            assert_eq!(artists, vec!["A", "B"]);
+            assert!(metadata.contains_key("mpris:artUrl"));
+
+            let _: () = player.call("PlayPause", &()).await.unwrap();
+            let _: () = player.call("Seek", &(5_000_000i64)).await.unwrap();
+            player.set_property("Volume", 0.5f64).await.unwrap();
+            player.set_property("LoopStatus", "Playlist").await.unwrap();
+            let position: i64 = player.get_property("Position").await.unwrap();
+            assert_eq!(position, 6_000_000);
+
+            let commands = commands.lock().unwrap().clone();
+            assert_eq!(commands[0], PlayerCommand::PlayPause);
+            assert_eq!(commands[1], PlayerCommand::Seek { offset_ms: 5000 });
+            assert_eq!(commands[2], PlayerCommand::Volume { value: 0.5 });
+            assert_eq!(
+                commands[3],
+                PlayerCommand::LoopStatus {
+                    value: crate::mpris::LoopStatus::Playlist
+                }
+            );
+            let _ = daemon.kill();
+            let _ = daemon.wait();
+        }
+    }
+}
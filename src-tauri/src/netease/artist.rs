@@ -0,0 +1,760 @@
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::history::Named;
+use crate::json::{named, text};
+use crate::netease::client::{ApiError, ApiResult, NeteaseClient};
+use crate::netease::import::normalize;
+use crate::store::JsonStore;
+
+/// Albums requested per `/artist/album` page.
+const ALBUM_PAGE: usize = 100;
+/// Songs requested per `/artist/songs` page.
+const SONG_PAGE: usize = 100;
+/// Releases of this many tracks or fewer count as singles.
+const SINGLE_TRACKS: u64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReleaseKind {
+    Album,
+    Ep,
+    Single,
+    Compilation,
+}
+
+impl ReleaseKind {
+    /// Classifies from the album `type`/`subType` labels, which are in Chinese or English
+    /// depending on the catalogue, falling back to the track count.
+    pub fn classify(kind: &str, sub_type: &str, size: u64) -> Self {
+        let labels = format!("{} {}", kind, sub_type).to_lowercase();
+        if ["合集", "精选", "compilation", "best of", "greatest hits"]
+            .iter()
+            .any(|label| labels.contains(label))
+        {
+            ReleaseKind::Compilation
+        } else if labels
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| word == "ep" || word == "single")
+        {
+            if size > SINGLE_TRACKS && !kind.eq_ignore_ascii_case("single") {
+                ReleaseKind::Ep
+            } else {
+                ReleaseKind::Single
+            }
+        } else if size > 0 && size <= SINGLE_TRACKS {
+            ReleaseKind::Single
+        } else {
+            ReleaseKind::Album
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseTrack {
+    pub id: u64,
+    pub name: String,
+    pub artists: Vec<Named>,
+    pub duration_ms: u64,
+    pub disc: String,
+    pub no: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Release {
+    pub id: u64,
+    pub name: String,
+    pub kind: ReleaseKind,
+    /// Netease's own label, e.g. live or remix editions.
+    pub sub_type: String,
+    /// Unix time in milliseconds.
+    pub publish_time: i64,
+    pub cover_url: String,
+    pub company: String,
+    pub size: u64,
+    /// Ids of re-releases folded into this one.
+    pub editions: Vec<u64>,
+    /// Empty unless the discography was walked with tracks.
+    pub tracks: Vec<ReleaseTrack>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Discography {
+    pub artist: Named,
+    /// Oldest first.
+    pub releases: Vec<Release>,
+}
+
+impl Discography {
+    /// Every track once, in release order; compilations only add songs found nowhere else.
+    pub fn tracks(&self) -> Vec<&ReleaseTrack> {
+        let mut seen = HashSet::new();
+        let mut tracks = vec![];
+        let originals = self
+            .releases
+            .iter()
+            .filter(|release| release.kind != ReleaseKind::Compilation);
+        let compilations = self
+            .releases
+            .iter()
+            .filter(|release| release.kind == ReleaseKind::Compilation);
+        for release in originals.chain(compilations) {
+            for track in &release.tracks {
+                if seen.insert(normalize(&track.name)) {
+                    tracks.push(track);
+                }
+            }
+        }
+        tracks
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SongOrder {
+    Hot,
+    Time,
+}
+
+impl SongOrder {
+    fn code(self) -> &'static str {
+        match self {
+            SongOrder::Hot => "hot",
+            SongOrder::Time => "time",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistSong {
+    pub id: u64,
+    pub name: String,
+    pub artists: Vec<Named>,
+    pub album: Option<Named>,
+    pub duration_ms: u64,
+    /// Unix time in milliseconds.
+    pub publish_time: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SongPage {
+    pub songs: Vec<ArtistSong>,
+    pub total: u64,
+    pub more: bool,
+}
+
+fn artists_of(value: Option<&Value>) -> Vec<Named> {
+    value
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|artist| named(artist, "id", "name"))
+        .collect()
+}
+
+pub(crate) fn release_of(album: &Value) -> Option<Release> {
+    let size = album
+        .get("size")
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+    let sub_type = text(album, "subType");
+    Some(Release {
+        id: album.get("id")?.as_u64()?,
+        name: text(album, "name"),
+        kind: ReleaseKind::classify(&text(album, "type"), &sub_type, size),
+        sub_type,
+        publish_time: album
+            .get("publishTime")
+            .and_then(Value::as_i64)
+            .unwrap_or_default(),
+        cover_url: text(album, "picUrl"),
+        company: text(album, "company"),
+        size,
+        editions: vec![],
+        tracks: vec![],
+    })
+}
+
+fn track_of(song: &Value) -> Option<ReleaseTrack> {
+    Some(ReleaseTrack {
+        id: song.get("id")?.as_u64()?,
+        name: text(song, "name"),
+        artists: artists_of(song.get("ar")),
+        duration_ms: song.get("dt").and_then(Value::as_u64).unwrap_or_default(),
+        disc: text(song, "cd"),
+        no: song.get("no").and_then(Value::as_u64).unwrap_or_default(),
+    })
+}
+
+/// Every album of an artist as listed by `/artist/album`, newest first.
+pub async fn albums(client: &NeteaseClient, artist_id: u64) -> ApiResult<(Named, Vec<Release>)> {
+    let id = artist_id.to_string();
+    let limit = ALBUM_PAGE.to_string();
+    let mut artist = None;
+    let mut releases: Vec<Release> = vec![];
+    loop {
+        let offset = releases.len().to_string();
+        let body = client
+            .call(
+                "/artist/album",
+                &[("id", &id), ("limit", &limit), ("offset", &offset)],
+            )
+            .await?;
+        if artist.is_none() {
+            artist = body.get("artist").and_then(|a| named(a, "id", "name"));
+        }
+        let page: Vec<Release> = body
+            .get("hotAlbums")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(release_of)
+            .collect();
+        let more = body.get("more").and_then(Value::as_bool).unwrap_or(false);
+        let empty = page.is_empty();
+        releases.extend(page);
+        if !more || empty {
+            break;
+        }
+    }
+    let artist = artist.ok_or_else(|| ApiError::Malformed(format!("no artist {}", artist_id)))?;
+    Ok((artist, releases))
+}
+
+/// Folds releases sharing a normalized title and kind into the earliest one.
+pub fn dedupe(releases: Vec<Release>) -> Vec<Release> {
+    let mut releases = releases;
+    releases.sort_by_key(|release| (release.publish_time, release.id));
+    let mut index: HashMap<(String, ReleaseKind), usize> = HashMap::new();
+    let mut kept: Vec<Release> = vec![];
+    for release in releases {
+        let key = (normalize(&release.name), release.kind);
+        match index.get(&key) {
+            Some(&at) if !key.0.is_empty() => kept[at].editions.push(release.id),
+            _ => {
+                index.insert(key, kept.len());
+                kept.push(release);
+            }
+        }
+    }
+    kept
+}
+
+/// The complete discography, oldest first, with re-releases folded in; `with_tracks`
+/// also fetches the tracks of every remaining release.
+pub async fn discography(
+    client: &NeteaseClient,
+    artist_id: u64,
+    with_tracks: bool,
+) -> ApiResult<Discography> {
+    let (artist, releases) = albums(client, artist_id).await?;
+    let mut releases = dedupe(releases);
+    if with_tracks {
+        for release in &mut releases {
+            let body = client
+                .call("/album", &[("id", &release.id.to_string())])
+                .await?;
+            release.tracks = body
+                .get("songs")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(track_of)
+                .collect();
+        }
+    }
+    Ok(Discography { artist, releases })
+}
+
+/// One page of an artist's songs from `/artist/songs`.
+pub async fn songs(
+    client: &NeteaseClient,
+    artist_id: u64,
+    order: SongOrder,
+    offset: usize,
+    limit: usize,
+) -> ApiResult<SongPage> {
+    let body = client
+        .call(
+            "/artist/songs",
+            &[
+                ("id", &artist_id.to_string()),
+                ("order", order.code()),
+                ("offset", &offset.to_string()),
+                ("limit", &limit.to_string()),
+            ],
+        )
+        .await?;
+    let songs: Vec<ArtistSong> = body
+        .get("songs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|song| {
+            Some(ArtistSong {
+                id: song.get("id")?.as_u64()?,
+                name: text(song, "name"),
+                artists: artists_of(song.get("ar")),
+                album: song.get("al").and_then(|al| named(al, "id", "name")),
+                duration_ms: song.get("dt").and_then(Value::as_u64).unwrap_or_default(),
+                publish_time: song
+                    .get("publishTime")
+                    .and_then(Value::as_i64)
+                    .unwrap_or_default(),
+            })
+        })
+        .collect();
+    Ok(SongPage {
+        total: body
+            .get("total")
+            .and_then(Value::as_u64)
+            .unwrap_or(songs.len() as u64),
+        more: body.get("more").and_then(Value::as_bool).unwrap_or(false),
+        songs,
+    })
+}
+
+/// Pages through `/artist/songs` until the end.
+pub async fn all_songs(
+    client: &NeteaseClient,
+    artist_id: u64,
+    order: SongOrder,
+) -> ApiResult<Vec<ArtistSong>> {
+    let mut songs = vec![];
+    loop {
+        let page = self::songs(client, artist_id, order, songs.len(), SONG_PAGE).await?;
+        let empty = page.songs.is_empty();
+        songs.extend(page.songs);
+        if !page.more || empty {
+            break;
+        }
+    }
+    Ok(songs)
+}
+
+/// How often the release watcher wakes up.
+const WATCH_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Artists checked per tick, so hundreds of follows are spread over many ticks.
+const WATCH_BATCH: usize = 20;
+/// Pause between two artists of a batch.
+const WATCH_PAUSE: Duration = Duration::from_millis(300);
+/// An artist is checked again at most this often.
+const WATCH_RECHECK_MS: i64 = 6 * 3600 * 1000;
+/// The followed artists are listed again at most this often.
+const FOLLOWED_REFRESH_MS: i64 = 6 * 3600 * 1000;
+/// Releases kept in the local feed.
+const WATCH_FEED: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WatchKind {
+    Album,
+    Ep,
+    Single,
+    Compilation,
+    Mv,
+}
+
+impl From<ReleaseKind> for WatchKind {
+    fn from(kind: ReleaseKind) -> Self {
+        match kind {
+            ReleaseKind::Album => WatchKind::Album,
+            ReleaseKind::Ep => WatchKind::Ep,
+            ReleaseKind::Single => WatchKind::Single,
+            ReleaseKind::Compilation => WatchKind::Compilation,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewRelease {
+    pub artist: Named,
+    pub kind: WatchKind,
+    /// Album or MV id, depending on `kind`.
+    pub id: u64,
+    pub name: String,
+    pub cover_url: String,
+    /// Unix time in milliseconds.
+    pub publish_time: i64,
+    /// Unix time in milliseconds.
+    pub found_at: i64,
+    pub read: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeenArtist {
+    pub albums: HashSet<u64>,
+    pub mvs: HashSet<u64>,
+    /// When the artist was last checked, successfully or not.
+    pub checked_at: i64,
+    /// Whether a check succeeded yet; until then a check only records what is there.
+    pub baselined: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchState {
+    pub followed: Vec<Named>,
+    pub followed_at: Option<i64>,
+    pub seen: HashMap<u64, SeenArtist>,
+    /// Newest first.
+    pub feed: Vec<NewRelease>,
+}
+
+/// Called with the releases found by a scheduled check.
+pub type Notify = Arc<dyn Fn(&[NewRelease]) + Send + Sync>;
+
+/// Watches the followed artists for new albums and MVs.
+///
+/// The first check of an artist only records what is there; later checks report the
+/// difference.
+pub struct ReleaseWatcher {
+    store: Mutex<JsonStore<WatchState>>,
+}
+
+impl ReleaseWatcher {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        ReleaseWatcher {
+            store: Mutex::new(JsonStore::open(path)),
+        }
+    }
+
+    pub fn feed(&self, limit: usize) -> Vec<NewRelease> {
+        let store = self.store.lock().unwrap();
+        store.get().feed.iter().take(limit).cloned().collect()
+    }
+
+    pub fn mark_read(&self) -> ApiResult<()> {
+        self.store.lock().unwrap().update(|state| {
+            for release in &mut state.feed {
+                release.read = true;
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Checks the next batch of due artists and returns the releases found, newest first.
+    pub async fn run(&self, client: &NeteaseClient, now: i64) -> ApiResult<Vec<NewRelease>> {
+        let followed_at = self.store.lock().unwrap().get().followed_at;
+        let refresh = followed_at.map_or(true, |at| now - at >= FOLLOWED_REFRESH_MS);
+        if refresh {
+            let followed = followed_artists(client).await?;
+            self.store.lock().unwrap().update(|state| {
+                let ids: HashSet<u64> = followed.iter().map(|artist| artist.id).collect();
+                state.seen.retain(|id, _| ids.contains(id));
+                state.followed = followed;
+                state.followed_at = Some(now);
+            })?;
+        }
+
+        let mut due: Vec<(i64, Named)> = {
+            let store = self.store.lock().unwrap();
+            let state = store.get();
+            state
+                .followed
+                .iter()
+                .map(|artist| {
+                    let checked = state.seen.get(&artist.id).map(|seen| seen.checked_at);
+                    (checked.unwrap_or(i64::MIN), artist.clone())
+                })
+                .filter(|(checked, _)| now.saturating_sub(*checked) >= WATCH_RECHECK_MS)
+                .collect()
+        };
+        due.sort_by_key(|(checked, _)| *checked);
+        due.truncate(WATCH_BATCH);
+
+        let mut found = vec![];
+        for (index, (_, artist)) in due.into_iter().enumerate() {
+            if index > 0 {
+                tokio::time::sleep(WATCH_PAUSE).await;
+            }
+            let latest = match latest_releases(client, &artist, now).await {
+                Ok(latest) => latest,
+                // a removed, blocked or rate-limited artist should not hold up the others
+                Err(ApiError::Status { .. }) => {
+                    self.store.lock().unwrap().update(|state| {
+                        state.seen.entry(artist.id).or_default().checked_at = now;
+                    })?;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let fresh = self.store.lock().unwrap().update(|state| {
+                let seen = state.seen.entry(artist.id).or_default();
+                let first = !seen.baselined;
+                seen.checked_at = now;
+                seen.baselined = true;
+                let mut fresh = vec![];
+                for release in latest {
+                    let known = match release.kind {
+                        WatchKind::Mv => &mut seen.mvs,
+                        _ => &mut seen.albums,
+                    };
+                    if known.insert(release.id) && !first {
+                        fresh.push(release);
+                    }
+                }
+                for release in fresh.iter().rev() {
+                    state.feed.insert(0, release.clone());
+                }
+                state.feed.truncate(WATCH_FEED);
+                fresh
+            })?;
+            found.extend(fresh);
+        }
+        found.sort_by_key(|release| Reverse(release.publish_time));
+        Ok(found)
+    }
+}
+
+/// Checks a batch every `WATCH_INTERVAL` while `client` is logged in.
+pub async fn schedule(watcher: Arc<ReleaseWatcher>, client: NeteaseClient, notify: Notify) {
+    loop {
+        if client.logged_in() {
+            let now = Utc::now().timestamp_millis();
+            if let Ok(found) = watcher.run(&client, now).await {
+                if !found.is_empty() {
+                    notify(&found);
+                }
+            }
+        }
+        tokio::time::sleep(WATCH_INTERVAL).await;
+    }
+}
+
+async fn followed_artists(client: &NeteaseClient) -> ApiResult<Vec<Named>> {
+    let mut artists = vec![];
+    loop {
+        let offset = artists.len().to_string();
+        let body = client
+            .call("/artist/sublist", &[("limit", "100"), ("offset", &offset)])
+            .await?;
+        let page: Vec<Named> = body
+            .get("data")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|artist| named(artist, "id", "name"))
+            .collect();
+        let more = body
+            .get("hasMore")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let empty = page.is_empty();
+        artists.extend(page);
+        if !more || empty {
+            break;
+        }
+    }
+    Ok(artists)
+}
+
+/// The first page of albums and MVs; new releases always show up there.
+async fn latest_releases(
+    client: &NeteaseClient,
+    artist: &Named,
+    now: i64,
+) -> ApiResult<Vec<NewRelease>> {
+    let id = artist.id.to_string();
+    let albums = client
+        .call("/artist/album", &[("id", &id), ("limit", "30")])
+        .await?;
+    let mvs = client
+        .call("/artist/mv", &[("id", &id), ("limit", "25")])
+        .await?;
+    let mut releases: Vec<NewRelease> = albums
+        .get("hotAlbums")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(release_of)
+        .map(|release| NewRelease {
+            artist: artist.clone(),
+            kind: release.kind.into(),
+            id: release.id,
+            name: release.name,
+            cover_url: release.cover_url,
+            publish_time: release.publish_time,
+            found_at: now,
+            read: false,
+        })
+        .collect();
+    for mv in mvs
+        .get("mvs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let id = match mv.get("id").and_then(Value::as_u64) {
+            Some(id) => id,
+            None => continue,
+        };
+        // MVs carry a `YYYY-MM-DD` date instead of a timestamp
+        let publish_time = mv
+            .get("publishTime")
+            .and_then(Value::as_str)
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map_or(0, |time| time.and_utc().timestamp_millis());
+        let cover = mv.get("imgurl16v9").or_else(|| mv.get("imgurl"));
+        releases.push(NewRelease {
+            artist: artist.clone(),
+            kind: WatchKind::Mv,
+            id,
+            name: text(mv, "name"),
+            cover_url: cover
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            publish_time,
+            found_at: now,
+            read: false,
+        });
+    }
+    Ok(releases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{discography, ReleaseKind, ReleaseWatcher, WatchKind, WATCH_RECHECK_MS};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::{NeteaseClient, RetryPolicies, RetryPolicy};
+    use crate::store::TempDir;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_discography_walk() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/artist/album", |params| {
+                    let albums = match param(params, "offset") {
+                        "0" => json!([
+                            { "id": 4, "name": "Hits", "type": "合集", "size": 3, "publishTime": 400 },
+                            { "id": 3, "name": "First (Remastered)", "type": "专辑", "size": 10, "publishTime": 300 },
+                        ]),
+                        _ => json!([
+                            { "id": 2, "name": "Lead", "type": "EP/Single", "size": 1, "publishTime": 200 },
+                            { "id": 1, "name": "First", "type": "专辑", "size": 10, "publishTime": 100 },
+                        ]),
+                    };
+                    let more = param(params, "offset") == "0";
+                    json!({ "code": 200, "artist": { "id": 9, "name": "Band" }, "hotAlbums": albums, "more": more })
+                })
+                .on("/album", |params| {
+                    let songs = match param(params, "id") {
+                        "1" => json!([{ "id": 11, "name": "Lead" }, { "id": 12, "name": "Deep Cut" }]),
+                        "2" => json!([{ "id": 21, "name": "Lead (Radio Edit)" }]),
+                        _ => json!([{ "id": 41, "name": "Lead" }, { "id": 42, "name": "Bonus" }]),
+                    };
+                    json!({ "code": 200, "songs": songs })
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        assert_eq!(
+            ReleaseKind::classify("专辑", "录音室版", 10),
+            ReleaseKind::Album
+        );
+        assert_eq!(ReleaseKind::classify("EP/Single", "", 5), ReleaseKind::Ep);
+        assert_eq!(
+            ReleaseKind::classify("EP/Single", "", 1),
+            ReleaseKind::Single
+        );
+        assert_eq!(ReleaseKind::classify("Single", "", 4), ReleaseKind::Single);
+        assert_eq!(
+            ReleaseKind::classify("专辑", "精选集", 20),
+            ReleaseKind::Compilation
+        );
+        assert_eq!(
+            ReleaseKind::classify("合集", "", 12),
+            ReleaseKind::Compilation
+        );
+
+        let discography = discography(&client, 9, true).await.unwrap();
+        assert_eq!(discography.artist.name, "Band");
+        let releases: Vec<(u64, ReleaseKind, Vec<u64>)> = discography
+            .releases
+            .iter()
+            .map(|r| (r.id, r.kind, r.editions.clone()))
+            .collect();
+        assert_eq!(
+            releases,
+            vec![
+                (1, ReleaseKind::Album, vec![3]),
+                (2, ReleaseKind::Single, vec![]),
+                (4, ReleaseKind::Compilation, vec![]),
+            ]
+        );
+        // the remastered edition is folded in, so its tracks are never fetched
+        assert_eq!(transport.calls_to("/album").len(), 3);
+        let tracks: Vec<u64> = discography.tracks().iter().map(|t| t.id).collect();
+        assert_eq!(tracks, vec![11, 12, 42]);
+    }
+
+    #[tokio::test]
+    async fn test_release_watcher() {
+        let dir = TempDir::new("releases");
+        let path = dir.join("releases.json");
+        // album ids the mock lists for artist 9; the second check adds one
+        let latest = Arc::new(AtomicU64::new(1));
+        let albums = latest.clone();
+        // artist 8 is rate limited on its first check only
+        let limited = Arc::new(AtomicBool::new(true));
+        let busy = limited.clone();
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/artist/sublist", |_| {
+                    json!({ "code": 200, "hasMore": false, "data": [{ "id": 9, "name": "Band" }, { "id": 8, "name": "Busy" }] })
+                })
+                .on("/artist/album", move |params| {
+                    if param(params, "id") == "8" && busy.load(Ordering::SeqCst) {
+                        return json!({ "code": 405, "message": "too frequent" });
+                    }
+                    let hot: Vec<_> = (1..=albums.load(Ordering::SeqCst))
+                        .rev()
+                        .map(|id| json!({ "id": id, "name": format!("LP{}", id), "type": "专辑", "size": 9, "publishTime": id }))
+                        .collect();
+                    json!({ "code": 200, "hotAlbums": hot })
+                })
+                .on("/artist/mv", |_| {
+                    json!({ "code": 200, "mvs": [{ "id": 70, "name": "Video", "publishTime": "2024-05-01" }] })
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "")
+            .with_retry(RetryPolicies::new(RetryPolicy::none()));
+        let watcher = ReleaseWatcher::open(&path);
+
+        assert!(watcher.run(&client, 0).await.unwrap().is_empty());
+        limited.store(false, Ordering::SeqCst);
+        // nothing is due again before the recheck interval
+        assert!(watcher.run(&client, 1000).await.unwrap().is_empty());
+        assert_eq!(transport.calls_to("/artist/album").len(), 2);
+
+        // the first successful check of artist 8 is its baseline, so only 9's album is new
+        latest.store(2, Ordering::SeqCst);
+        let found = watcher.run(&client, WATCH_RECHECK_MS).await.unwrap();
+        let found: Vec<(u64, u64, WatchKind)> =
+            found.iter().map(|r| (r.artist.id, r.id, r.kind)).collect();
+        assert_eq!(found, vec![(9, 2, WatchKind::Album)]);
+        assert_eq!(transport.calls_to("/artist/album").len(), 4);
+        let watcher = ReleaseWatcher::open(&path);
+        assert_eq!(watcher.feed(10).len(), 1);
+        assert!(!watcher.feed(10)[0].read);
+        watcher.mark_read().unwrap();
+        assert!(watcher.feed(10)[0].read);
+    }
+}
@@ -0,0 +1,321 @@
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::netease::client::{ApiError, ApiOutcome, ApiResult, NeteaseClient};
+use crate::store::JsonStore;
+
+/// How often the scheduler checks whether today's check-in is still due.
+const CHECKIN_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// Days of results kept locally.
+const CHECKIN_HISTORY: usize = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Platform {
+    /// `type` 0.
+    Android,
+    /// `type` 1.
+    Pc,
+}
+
+impl Platform {
+    fn sign_type(&self) -> &'static str {
+        match self {
+            Platform::Android => "0",
+            Platform::Pc => "1",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum SignResult {
+    Signed { point: i64 },
+    AlreadySigned,
+    Failed { message: String },
+}
+
+impl SignResult {
+    fn succeeded(&self) -> bool {
+        !matches!(self, SignResult::Failed { .. })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskClaim {
+    pub user_task_id: String,
+    pub name: String,
+    pub point: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckInRecord {
+    /// Local date, `YYYY-MM-DD`.
+    pub date: String,
+    pub android: SignResult,
+    pub pc: SignResult,
+    pub tasks: Vec<TaskClaim>,
+}
+
+impl CheckInRecord {
+    fn signed(&self) -> bool {
+        self.android.succeeded() || self.pc.succeeded()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckInState {
+    pub enabled: bool,
+    /// Consecutive days with at least one successful sign-in.
+    pub streak: u32,
+    pub last_signed: Option<String>,
+    /// Newest last.
+    pub history: Vec<CheckInRecord>,
+}
+
+impl Default for CheckInState {
+    fn default() -> Self {
+        CheckInState {
+            enabled: true,
+            streak: 0,
+            last_signed: None,
+            history: vec![],
+        }
+    }
+}
+
+/// Signs in on both platforms and claims finished Yunbei tasks once per day.
+pub struct CheckIn {
+    store: Mutex<JsonStore<CheckInState>>,
+}
+
+impl CheckIn {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        CheckIn {
+            store: Mutex::new(JsonStore::open(path)),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) -> ApiResult<()> {
+        self.store
+            .lock()
+            .unwrap()
+            .update(|state| state.enabled = enabled)?;
+        Ok(())
+    }
+
+    pub fn state(&self) -> CheckInState {
+        self.store.lock().unwrap().get().clone()
+    }
+
+    /// A day whose sign-ins all failed stays due, so the scheduler tries again.
+    fn due(&self, today: NaiveDate) -> bool {
+        let store = self.store.lock().unwrap();
+        let state = store.get();
+        let today = today.to_string();
+        state.enabled
+            && !state
+                .history
+                .iter()
+                .any(|record| record.date == today && record.signed())
+    }
+
+    /// Runs the check-in unless it already signed in on `today`.
+    pub async fn run_if_due(
+        &self,
+        client: &NeteaseClient,
+        today: NaiveDate,
+    ) -> ApiResult<Option<CheckInRecord>> {
+        if !self.due(today) {
+            return Ok(None);
+        }
+        self.run(client, today).await.map(Some)
+    }
+
+    /// Signs in on both platforms, claims Yunbei tasks and records the outcome for `today`.
+    pub async fn run(&self, client: &NeteaseClient, today: NaiveDate) -> ApiResult<CheckInRecord> {
+        let android = sign_in(client, Platform::Android).await?;
+        let pc = sign_in(client, Platform::Pc).await?;
+        let tasks = claim_tasks(client).await?;
+        let record = CheckInRecord {
+            date: today.to_string(),
+            android,
+            pc,
+            tasks,
+        };
+
+        self.store.lock().unwrap().update(|state| {
+            if record.signed() {
+                let yesterday = today.pred_opt().map(|day| day.to_string());
+                state.streak = match state.last_signed.as_deref() {
+                    Some(last) if last == record.date => state.streak,
+                    Some(last) if Some(last) == yesterday.as_deref() => state.streak + 1,
+                    _ => 1,
+                };
+                state.last_signed = Some(record.date.clone());
+            }
+            state.history.retain(|old| old.date != record.date);
+            state.history.push(record.clone());
+            let excess = state.history.len().saturating_sub(CHECKIN_HISTORY);
+            state.history.drain(..excess);
+        })?;
+        Ok(record)
+    }
+}
+
+/// Checks every `CHECKIN_INTERVAL` and runs the check-in once a day while `client` is
+/// logged in.
+pub async fn schedule(checkin: Arc<CheckIn>, client: NeteaseClient) {
+    loop {
+        if client.logged_in() {
+            // a day without any successful sign-in stays due for the next tick
+            let _ = checkin.run_if_due(&client, Local::now().date_naive()).await;
+        }
+        tokio::time::sleep(CHECKIN_INTERVAL).await;
+    }
+}
+
+async fn sign_in(client: &NeteaseClient, platform: Platform) -> ApiResult<SignResult> {
+    match client
+        .call("/daily_signin", &[("type", platform.sign_type())])
+        .await
+    {
+        Ok(body) => Ok(SignResult::Signed {
+            point: body
+                .get("point")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+        }),
+        // `point/dailyTask` answers -2 when the platform was already signed today
+        Err(err) if err.outcome() == Some(ApiOutcome::AlreadyDone) => Ok(SignResult::AlreadySigned),
+        Err(ApiError::Status { message, .. }) => Ok(SignResult::Failed { message }),
+        Err(err) => Err(err),
+    }
+}
+
+/// Receives the points of every completed task; a task that fails to claim is reported
+/// but does not stop the others.
+async fn claim_tasks(client: &NeteaseClient) -> ApiResult<Vec<TaskClaim>> {
+    let body = client.call("/yunbei/tasks", &[]).await?;
+    let tasks = body.get("data").and_then(Value::as_array);
+    let mut claims = vec![];
+    for task in tasks.into_iter().flatten() {
+        if !task
+            .get("completed")
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        let text = |key: &str| match task.get(key) {
+            Some(Value::String(text)) => text.clone(),
+            Some(Value::Number(number)) => number.to_string(),
+            _ => String::new(),
+        };
+        let user_task_id = text("userTaskId");
+        if user_task_id.is_empty() {
+            continue;
+        }
+        let deposit_code = text("depositCode");
+        let deposit_code = if deposit_code.is_empty() {
+            "0".to_string()
+        } else {
+            deposit_code
+        };
+        let result = client
+            .call(
+                "/yunbei/task/finish",
+                &[
+                    ("userTaskId", &user_task_id),
+                    ("depositCode", &deposit_code),
+                ],
+            )
+            .await;
+        claims.push(TaskClaim {
+            user_task_id,
+            name: text("taskName"),
+            point: task
+                .get("taskPoint")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+            error: match result {
+                Ok(_) => None,
+                Err(err) if err.is_transport() => return Err(err),
+                Err(err) => Some(err.to_string()),
+            },
+        });
+    }
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckIn, SignResult};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use crate::store::TempDir;
+    use chrono::NaiveDate;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_checkin_streak() {
+        let dir = TempDir::new("checkin");
+        let path = dir.join("checkin.json");
+        let logged_in = Arc::new(AtomicBool::new(false));
+        let login = logged_in.clone();
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/daily_signin", move |params| match param(params, "type") {
+                    _ if !login.load(Ordering::SeqCst) => json!({ "code": 301 }),
+                    "0" => json!({ "code": 200, "point": 3 }),
+                    _ => json!({ "code": -2, "msg": "重复签到" }),
+                })
+                .on("/yunbei/tasks", |_| {
+                    json!({ "code": 200, "data": [
+                        { "userTaskId": 11, "taskName": "听歌", "taskPoint": 5, "completed": true, "depositCode": 0 },
+                        { "userTaskId": 12, "taskName": "分享", "taskPoint": 2, "completed": false },
+                    ] })
+                })
+                .on("/yunbei/task/finish", |_| json!({ "code": 200 })),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let checkin = CheckIn::open(&path);
+        let day = |d| NaiveDate::from_ymd_opt(2024, 5, d).unwrap();
+
+        // the first tick fails on both platforms, so the day stays due
+        let record = checkin.run_if_due(&client, day(1)).await.unwrap().unwrap();
+        assert!(matches!(record.android, SignResult::Failed { .. }));
+        assert_eq!(checkin.state().streak, 0);
+
+        logged_in.store(true, Ordering::SeqCst);
+        let record = checkin.run_if_due(&client, day(1)).await.unwrap().unwrap();
+        assert_eq!(record.android, SignResult::Signed { point: 3 });
+        assert_eq!(record.pc, SignResult::AlreadySigned);
+        assert_eq!(record.tasks.len(), 1);
+        assert_eq!(
+            param(&transport.calls_to("/yunbei/task/finish")[1], "userTaskId"),
+            "11"
+        );
+
+        assert!(checkin.run_if_due(&client, day(1)).await.unwrap().is_none());
+        checkin.run_if_due(&client, day(2)).await.unwrap();
+        assert_eq!(checkin.state().streak, 2);
+        checkin.run_if_due(&client, day(4)).await.unwrap();
+
+        let state = CheckIn::open(&path).state();
+        assert_eq!(state.streak, 1);
+        assert_eq!(state.history.len(), 3);
+        assert_eq!(state.last_signed.as_deref(), Some("2024-05-04"));
+    }
+}
@@ -0,0 +1,717 @@
+use futures::future::BoxFuture;
+use lazy_static::lazy_static;
+use rand::Rng;
+use reqwest::header::SET_COOKIE;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::netease::music_api;
+use crate::Options;
+
+lazy_static! {
+    static ref HTTP_TRANSPORT: Arc<HttpTransport> = Arc::new(HttpTransport::default());
+    static ref DEFAULT_POLICIES: Arc<RetryPolicies> = Arc::new(RetryPolicies::default());
+}
+
+/// A client identity as Netease checks it: the User-Agent together with the `os` and
+/// `appver` cookies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientProfile {
+    pub user_agent: &'static str,
+    pub os: &'static str,
+    pub appver: &'static str,
+}
+
+/// Identities a call moves through after each `-460`, in order.
+pub const PROFILES: [ClientProfile; 3] = [
+    ClientProfile {
+        user_agent: "Mozilla/5.0 (Windows NT 10.0; WOW64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.164 NeteaseMusicDesktop/2.10.13.202675",
+        os: "pc",
+        appver: "2.10.13.202675",
+    },
+    ClientProfile {
+        user_agent: "NeteaseMusic/8.10.90.230906163636(8010090);Dalvik/2.1.0 (Linux; U; Android 13; Pixel 7 Build/TQ3A.230901.001)",
+        os: "android",
+        appver: "8.10.90",
+    },
+    ClientProfile {
+        user_agent: "NeteaseMusic 8.10.90/5895 (iPhone; iOS 16.5; zh_CN)",
+        os: "iPhone OS",
+        appver: "8.10.90",
+    },
+];
+
+impl ClientProfile {
+    /// `cookie` with its `os` and `appver` replaced by the profile's, wherever the route
+    /// put them.
+    pub fn apply_to_cookie(&self, cookie: &str) -> String {
+        let mut pairs: Vec<String> = cookie_pairs(cookie)
+            .filter(|pair| !pair.starts_with("os=") && !pair.starts_with("appver="))
+            .map(str::to_string)
+            .collect();
+        pairs.push(format!("os={}", self.os));
+        pairs.push(format!("appver={}", self.appver));
+        pairs.join("; ")
+    }
+}
+
+fn cookie_pairs(cookie: &str) -> impl Iterator<Item = &str> {
+    cookie
+        .split(';')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+}
+
+/// `cookie` with the `name=value` pairs of `set` replacing those of the same name.
+fn merge_cookies(cookie: &str, set: &[String]) -> String {
+    let name = |pair: &str| pair.split('=').next().unwrap_or_default().to_string();
+    let mut pairs: Vec<String> = cookie_pairs(cookie).map(str::to_string).collect();
+    for new in set {
+        match pairs.iter().position(|pair| name(pair) == name(new)) {
+            Some(at) => pairs[at] = new.clone(),
+            None => pairs.push(new.clone()),
+        }
+    }
+    pairs.join("; ")
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// `music_api::dispatch` does not know the route or could not build a request from the
+    /// params, such as a comment `type` naming no resource.
+    UnknownRoute(String),
+    Http(reqwest::Error),
+    Json(serde_json::Error),
+    /// Netease answered, but the body carried a code other than 200.
+    Status {
+        code: i64,
+        message: String,
+    },
+    /// The body did not have the shape the caller expected.
+    Malformed(String),
+    /// The call names something the local state does not hold, so it was not sent.
+    NotFound(String),
+    /// Reading or writing local files around a call failed.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::UnknownRoute(route) => write!(f, "cannot build a request for {}", route),
+            ApiError::Http(err) => write!(f, "http error: {}", err),
+            ApiError::Json(err) => write!(f, "invalid json: {}", err),
+            ApiError::Status { code, message } => write!(f, "netease code {}: {}", code, message),
+            ApiError::Malformed(what) => write!(f, "unexpected response: {}", what),
+            ApiError::NotFound(what) => write!(f, "not found: {}", what),
+            ApiError::Io(err) => write!(f, "io error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ApiError {
+    /// True when the request never got an answer, as opposed to Netease rejecting it.
+    pub fn is_transport(&self) -> bool {
+        matches!(self, ApiError::Http(_) | ApiError::Io(_))
+    }
+
+    /// What Netease meant by the body code, when it answered at all.
+    pub fn outcome(&self) -> Option<ApiOutcome> {
+        match self {
+            ApiError::Status { code, .. } => Some(ApiOutcome::classify(*code)),
+            _ => None,
+        }
+    }
+}
+
+/// Body-level codes Netease answers with, whatever the HTTP status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "kind", content = "code")]
+pub enum ApiOutcome {
+    Ok,
+    /// 301: the cookie is missing or expired.
+    NeedsLogin,
+    /// -460: the client was flagged as cheating.
+    Cheating,
+    /// 250 or 8821: held by risk control, e.g. a captcha is wanted.
+    RiskControl,
+    /// 405: too many calls in a short time.
+    TooFrequent,
+    /// 404: no copyright, or the resource is gone.
+    NoCopyright,
+    /// -2: already done, e.g. signed in today.
+    AlreadyDone,
+    Other(i64),
+}
+
+impl ApiOutcome {
+    pub fn classify(code: i64) -> Self {
+        match code {
+            200 => ApiOutcome::Ok,
+            301 => ApiOutcome::NeedsLogin,
+            -460 => ApiOutcome::Cheating,
+            250 | 8821 => ApiOutcome::RiskControl,
+            405 => ApiOutcome::TooFrequent,
+            404 => ApiOutcome::NoCopyright,
+            -2 => ApiOutcome::AlreadyDone,
+            code => ApiOutcome::Other(code),
+        }
+    }
+
+    /// True for codes that may clear up by waiting.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ApiOutcome::TooFrequent | ApiOutcome::RiskControl)
+    }
+}
+
+/// How `NeteaseClient::call` recovers from a failed call to one route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after a transient code; the first call is not counted.
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each following one.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Outcomes worth retrying, by default the transient ones.
+    pub retry_on: Vec<ApiOutcome>,
+    /// Calls `/login/refresh` once on a 301 and retries with the cookie it sets.
+    pub refresh_login: bool,
+    /// Retries with the next of `PROFILES` on each -460 until they run out.
+    pub rotate_profile: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 2,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+            retry_on: vec![ApiOutcome::TooFrequent, ApiOutcome::RiskControl],
+            refresh_login: true,
+            rotate_profile: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Fails on the first error.
+    pub fn none() -> Self {
+        RetryPolicy {
+            retries: 0,
+            retry_on: vec![],
+            refresh_login: false,
+            rotate_profile: false,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Exponential backoff capped at `max_delay`, with up to half of it taken off at random
+    /// so clients that failed together do not retry together.
+    fn delay(&self, attempt: u32) -> Duration {
+        let full = self
+            .base_delay
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_delay);
+        full.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// A retry policy per route, falling back to a default one.
+#[derive(Debug, Clone)]
+pub struct RetryPolicies {
+    default: RetryPolicy,
+    routes: HashMap<String, RetryPolicy>,
+}
+
+impl Default for RetryPolicies {
+    fn default() -> Self {
+        RetryPolicies::new(RetryPolicy::default())
+            // 250 on a comment means its content was refused, which waiting does not change
+            .route(
+                "/comment",
+                RetryPolicy {
+                    retry_on: vec![ApiOutcome::TooFrequent],
+                    ..RetryPolicy::default()
+                },
+            )
+            .route("/login/refresh", RetryPolicy::none())
+    }
+}
+
+impl RetryPolicies {
+    pub fn new(default: RetryPolicy) -> Self {
+        RetryPolicies {
+            default,
+            routes: HashMap::new(),
+        }
+    }
+
+    pub fn route(mut self, route: &str, policy: RetryPolicy) -> Self {
+        self.routes.insert(route.to_string(), policy);
+        self
+    }
+
+    pub fn get(&self, route: &str) -> &RetryPolicy {
+        self.routes.get(route).unwrap_or(&self.default)
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(err: reqwest::Error) -> Self {
+        ApiError::Http(err)
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(err: std::io::Error) -> Self {
+        ApiError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
+        ApiError::Json(err)
+    }
+}
+
+/// What came back from a route.
+#[derive(Debug, Default)]
+pub struct Reply {
+    pub body: Value,
+    /// `name=value` of every cookie the response set.
+    pub cookies: Vec<String>,
+}
+
+/// Sends a route (e.g. `/song/detail`) with its plain parameters and returns the decoded body.
+pub trait Transport: Send + Sync {
+    /// `profile`, when given, replaces the identity the route would present.
+    fn send<'a>(
+        &'a self,
+        route: &'a str,
+        params: &'a [(String, String)],
+        cookie: &'a str,
+        profile: Option<&'a ClientProfile>,
+    ) -> BoxFuture<'a, ApiResult<Reply>>;
+}
+
+/// Builds the request through `music_api::dispatch` and performs it with reqwest.
+#[derive(Default)]
+pub struct HttpTransport {
+    client: reqwest::Client,
+}
+
+impl Transport for HttpTransport {
+    fn send<'a>(
+        &'a self,
+        route: &'a str,
+        params: &'a [(String, String)],
+        cookie: &'a str,
+        profile: Option<&'a ClientProfile>,
+    ) -> BoxFuture<'a, ApiResult<Reply>> {
+        Box::pin(async move {
+            let request = music_api::dispatch(Options {
+                method: "POST",
+                url: route,
+                params: params
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect(),
+                cookie,
+            });
+            if request.url.is_empty() {
+                return Err(ApiError::UnknownRoute(route.to_string()));
+            }
+
+            // linuxapi forwards only work with the Linux client's User-Agent
+            let linux = request.url.ends_with("/api/linux/forward");
+            let mut builder = self.client.post(&request.url).body(request.body);
+            for (name, value) in request.headers {
+                let value = match profile {
+                    Some(profile) if name == "user-agent" && !linux => {
+                        profile.user_agent.to_string()
+                    }
+                    Some(profile) if name == "cookie" => profile.apply_to_cookie(&value),
+                    _ => value,
+                };
+                builder = builder.header(name, value);
+            }
+            let response = builder.send().await?;
+            let cookies = response
+                .headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .filter_map(|value| value.split(';').next())
+                .map(|pair| pair.trim().to_string())
+                .collect();
+            let bytes = response.bytes().await?;
+            Ok(Reply {
+                body: serde_json::from_slice(&bytes)?,
+                cookies,
+            })
+        })
+    }
+}
+
+/// `QueryParams::json` pastes values verbatim, so text holding quotes or line breaks, or
+/// JSON sent as a string value, must be escaped before it is passed as a parameter.
+pub(crate) fn escape_json(text: &str) -> String {
+    let quoted = serde_json::Value::from(text).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Called with the whole cookie after a login refresh changed it.
+pub type CookieListener = Arc<dyn Fn(&str) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct NeteaseClient {
+    transport: Arc<dyn Transport>,
+    /// Shared by clones, so a refreshed login is used by all of them.
+    cookie: Arc<RwLock<String>>,
+    policies: Arc<RetryPolicies>,
+    on_refresh: Option<CookieListener>,
+}
+
+impl NeteaseClient {
+    pub fn new(cookie: &str) -> Self {
+        NeteaseClient::with_transport(HTTP_TRANSPORT.clone(), cookie)
+    }
+
+    pub fn with_transport(transport: Arc<dyn Transport>, cookie: &str) -> Self {
+        NeteaseClient {
+            transport,
+            cookie: Arc::new(RwLock::new(cookie.to_string())),
+            policies: DEFAULT_POLICIES.clone(),
+            on_refresh: None,
+        }
+    }
+
+    /// Replaces the default retry policies.
+    pub fn with_retry(self, policies: RetryPolicies) -> Self {
+        NeteaseClient {
+            policies: Arc::new(policies),
+            ..self
+        }
+    }
+
+    /// Lets whoever keeps the login know about the cookie a refresh set.
+    pub fn on_refresh(self, listener: CookieListener) -> Self {
+        NeteaseClient {
+            on_refresh: Some(listener),
+            ..self
+        }
+    }
+
+    /// The cookie calls are sent with, including what a login refresh set.
+    pub fn cookie(&self) -> String {
+        self.cookie.read().unwrap().clone()
+    }
+
+    /// Switches this client and all its clones to another login; empty after logout.
+    pub fn set_cookie(&self, cookie: &str) {
+        *self.cookie.write().unwrap() = cookie.to_string();
+    }
+
+    pub fn logged_in(&self) -> bool {
+        !self.cookie.read().unwrap().is_empty()
+    }
+
+    /// Calls a route and fails unless the body reports code 200, after recovering as the
+    /// route's retry policy allows.
+    pub async fn call(&self, route: &str, params: &[(&str, &str)]) -> ApiResult<Value> {
+        let params: Vec<(String, String)> = params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let policy = self.policies.get(route);
+        let mut profiles = PROFILES.iter();
+        let (mut profile, mut attempt, mut refreshed) = (None, 0, false);
+        loop {
+            let err = match self.send(route, &params, profile).await {
+                Ok(body) => return Ok(body),
+                Err(err) => err,
+            };
+            match err.outcome() {
+                Some(ApiOutcome::NeedsLogin) if policy.refresh_login && !refreshed => {
+                    refreshed = true;
+                    if !self.refresh_login().await {
+                        return Err(err);
+                    }
+                }
+                Some(ApiOutcome::Cheating) if policy.rotate_profile => match profiles.next() {
+                    Some(next) => profile = Some(next),
+                    None => return Err(err),
+                },
+                Some(outcome) if attempt < policy.retries && policy.retry_on.contains(&outcome) => {
+                    tokio::time::sleep(policy.delay(attempt)).await;
+                    attempt += 1;
+                }
+                _ => return Err(err),
+            }
+        }
+    }
+
+    /// Refreshes the login token and keeps the cookies it sets; false when nothing was set,
+    /// since retrying with the same cookie would fail the same way.
+    async fn refresh_login(&self) -> bool {
+        let cookie = self.cookie();
+        let reply = match self
+            .transport
+            .send("/login/refresh", &[], &cookie, None)
+            .await
+        {
+            Ok(reply) => reply,
+            Err(_) => return false,
+        };
+        if checked(reply.body).is_err() || reply.cookies.is_empty() {
+            return false;
+        }
+        let refreshed = {
+            let mut current = self.cookie.write().unwrap();
+            // a login switch or another refresh meanwhile already replaced the cookie
+            if *current != cookie {
+                return true;
+            }
+            *current = merge_cookies(&current, &reply.cookies);
+            current.clone()
+        };
+        if let Some(listener) = &self.on_refresh {
+            listener(&refreshed);
+        }
+        true
+    }
+
+    async fn send(
+        &self,
+        route: &str,
+        params: &[(String, String)],
+        profile: Option<&ClientProfile>,
+    ) -> ApiResult<Value> {
+        let cookie = self.cookie();
+        let reply = self.transport.send(route, params, &cookie, profile).await?;
+        checked(reply.body)
+    }
+}
+
+/// The body, unless its code is other than 200.
+fn checked(body: Value) -> ApiResult<Value> {
+    match body.get("code").and_then(Value::as_i64) {
+        Some(200) | None => Ok(body),
+        Some(code) => Err(ApiError::Status {
+            code,
+            message: body
+                .get("message")
+                .or_else(|| body.get("msg"))
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::{ApiError, ApiResult, ClientProfile, Reply, Transport};
+    use futures::future::BoxFuture;
+    use serde_json::Value;
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    type Handler = Box<dyn Fn(&[(String, String)]) -> Value + Send + Sync>;
+
+    pub struct Call {
+        route: String,
+        params: Vec<(String, String)>,
+        cookie: String,
+        profile: Option<ClientProfile>,
+    }
+
+    /// Answers routes from registered handlers and records every call.
+    #[derive(Default)]
+    pub struct MockTransport {
+        handlers: Vec<(String, Handler)>,
+        set_cookies: Vec<(String, String)>,
+        pub calls: Mutex<Vec<Call>>,
+        offline: AtomicBool,
+    }
+
+    impl MockTransport {
+        pub fn on(
+            mut self,
+            route: &str,
+            handler: impl Fn(&[(String, String)]) -> Value + Send + Sync + 'static,
+        ) -> Self {
+            self.handlers.push((route.to_string(), Box::new(handler)));
+            self
+        }
+
+        /// Answers every call to `route` with a `Set-Cookie` of `pair`.
+        pub fn sets_cookie(mut self, route: &str, pair: &str) -> Self {
+            self.set_cookies.push((route.to_string(), pair.to_string()));
+            self
+        }
+
+        /// Fails every following call as if the connection was down.
+        pub fn set_offline(&self, offline: bool) {
+            self.offline.store(offline, Ordering::SeqCst);
+        }
+
+        fn recorded<T>(&self, route: &str, field: impl Fn(&Call) -> T) -> Vec<T> {
+            self.calls
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|call| call.route == route)
+                .map(field)
+                .collect()
+        }
+
+        pub fn calls_to(&self, route: &str) -> Vec<Vec<(String, String)>> {
+            self.recorded(route, |call| call.params.clone())
+        }
+
+        /// The cookie each call to `route` was sent with.
+        pub fn cookies_to(&self, route: &str) -> Vec<String> {
+            self.recorded(route, |call| call.cookie.clone())
+        }
+
+        /// The profile each call to `route` presented.
+        pub fn profiles_to(&self, route: &str) -> Vec<Option<ClientProfile>> {
+            self.recorded(route, |call| call.profile)
+        }
+    }
+
+    pub fn param<'a>(params: &'a [(String, String)], key: &str) -> &'a str {
+        params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .unwrap_or_default()
+    }
+
+    impl Transport for MockTransport {
+        fn send<'a>(
+            &'a self,
+            route: &'a str,
+            params: &'a [(String, String)],
+            cookie: &'a str,
+            profile: Option<&'a ClientProfile>,
+        ) -> BoxFuture<'a, ApiResult<Reply>> {
+            self.calls.lock().unwrap().push(Call {
+                route: route.to_string(),
+                params: params.to_vec(),
+                cookie: cookie.to_string(),
+                profile: profile.copied(),
+            });
+            if self.offline.load(Ordering::SeqCst) {
+                let err = io::Error::new(io::ErrorKind::ConnectionRefused, "offline");
+                return Box::pin(async move { Err(ApiError::Io(err)) });
+            }
+            let cookies = self
+                .set_cookies
+                .iter()
+                .filter(|(r, _)| r == route)
+                .map(|(_, pair)| pair.clone())
+                .collect();
+            let result = self
+                .handlers
+                .iter()
+                .find(|(r, _)| r == route)
+                .map(|(_, handler)| Reply {
+                    body: handler(params),
+                    cookies,
+                })
+                .ok_or_else(|| ApiError::UnknownRoute(route.to_string()));
+            Box::pin(async move { result })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::MockTransport;
+    use super::{ApiOutcome, NeteaseClient, RetryPolicies, RetryPolicy, PROFILES};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_retry_policy() {
+        let counter = |answers: Vec<i64>| {
+            let calls = AtomicUsize::new(0);
+            move |_: &[(String, String)]| {
+                let at = calls.fetch_add(1, Ordering::SeqCst);
+                json!({ "code": answers.get(at).copied().unwrap_or(200) })
+            }
+        };
+        let transport = Arc::new(
+            MockTransport::default()
+                // too frequent, then flagged twice, then fine
+                .on("/song/detail", counter(vec![405, -460, -460]))
+                .on("/user/account", counter(vec![301]))
+                .on("/login/refresh", |_| json!({ "code": 200 }))
+                .sets_cookie("/login/refresh", "MUSIC_U=new")
+                .on("/comment", |_| json!({ "code": 250, "message": "refused" })),
+        );
+        let fast = RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let refreshed = Arc::new(Mutex::new(vec![]));
+        let seen = refreshed.clone();
+        let client = NeteaseClient::with_transport(transport.clone(), "MUSIC_U=old; __csrf=x")
+            .with_retry(RetryPolicies::new(fast.clone()).route(
+                "/comment",
+                RetryPolicy {
+                    retry_on: vec![ApiOutcome::TooFrequent],
+                    ..fast
+                },
+            ))
+            .on_refresh(Arc::new(move |cookie| {
+                seen.lock().unwrap().push(cookie.to_string())
+            }));
+
+        client.call("/song/detail", &[]).await.unwrap();
+        let profiles = transport.profiles_to("/song/detail");
+        assert_eq!(
+            profiles,
+            vec![None, None, Some(PROFILES[0]), Some(PROFILES[1])]
+        );
+        // the next call starts over with the route's own identity
+        client.call("/song/detail", &[]).await.unwrap();
+        assert_eq!(transport.profiles_to("/song/detail")[4], None);
+
+        // the retry after a refresh carries the cookie the refresh set
+        client.call("/user/account", &[]).await.unwrap();
+        assert_eq!(
+            transport.cookies_to("/user/account"),
+            vec!["MUSIC_U=old; __csrf=x", "MUSIC_U=new; __csrf=x"]
+        );
+        assert_eq!(client.cookie(), "MUSIC_U=new; __csrf=x");
+        assert_eq!(*refreshed.lock().unwrap(), vec!["MUSIC_U=new; __csrf=x"]);
+        // a logout reaches every clone of the session
+        client.clone().set_cookie("");
+        assert!(!client.logged_in());
+        client.set_cookie("MUSIC_U=new; __csrf=x");
+
+        let err = client.call("/comment", &[]).await.unwrap_err();
+        assert_eq!(err.outcome(), Some(ApiOutcome::RiskControl));
+        assert_eq!(transport.calls_to("/comment").len(), 1);
+
+        // a profile overrides the `os` a route appends itself
+        assert_eq!(
+            PROFILES[1].apply_to_cookie("MUSIC_U=new;os=pc;"),
+            "MUSIC_U=new; os=android; appver=8.10.90"
+        );
+    }
+}
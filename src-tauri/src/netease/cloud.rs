@@ -0,0 +1,821 @@
+use futures::stream::{self, StreamExt};
+use lofty::prelude::{Accessor, AudioFile, TaggedFileExt};
+use openssl::hash::{Hasher, MessageDigest};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::netease::client::{escape_json, ApiError, ApiResult, NeteaseClient};
+use crate::netease::import::{
+    entry_from_label, ranked_candidates, status_of, Candidate, ImportEntry, MatchStatus,
+    SEARCH_CONCURRENCY,
+};
+use crate::store::JsonStore;
+
+/// Bucket the desktop client uploads cloud disk audio into.
+const NOS_BUCKET: &str = "jd-musicrep-privatecloud-audio-public";
+/// Answers which upload hosts serve a bucket.
+const NOS_LBS: &str = "https://wanproxy.127.net/lbs";
+const NOS_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// A local audio file with the metadata sent along with the upload.
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalTrack {
+    #[serde(skip)]
+    pub path: PathBuf,
+    pub file_name: String,
+    pub ext: String,
+    pub size: u64,
+    pub md5: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Bits per second.
+    pub bitrate: Option<u64>,
+}
+
+impl LocalTrack {
+    /// Hashes the file and reads its tags; untagged files fall back to the file name.
+    ///
+    /// Blocks while it reads the whole file; async callers go through `load`.
+    pub fn read(path: &Path) -> ApiResult<Self> {
+        let mut file = File::open(path)?;
+        let mut hasher = Hasher::new(MessageDigest::md5()).map_err(std::io::Error::from)?;
+        let mut buffer = vec![0; 64 * 1024];
+        let mut size = 0;
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher
+                .update(&buffer[..read])
+                .map_err(std::io::Error::from)?;
+            size += read as u64;
+        }
+        let md5 = hex::encode(&*hasher.finish().map_err(std::io::Error::from)?);
+
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let mut track = LocalTrack {
+            path: path.to_path_buf(),
+            file_name,
+            ext,
+            size,
+            md5,
+            title: stem,
+            artist: None,
+            album: None,
+            bitrate: None,
+        };
+        if let Ok(tagged) = lofty::read_from_path(path) {
+            track.bitrate = tagged
+                .properties()
+                .audio_bitrate()
+                .map(|kbps| u64::from(kbps) * 1000);
+            if let Some(tag) = tagged.primary_tag().or_else(|| tagged.first_tag()) {
+                let text = |value: Option<std::borrow::Cow<str>>| {
+                    value
+                        .map(|value| value.trim().to_string())
+                        .filter(|value| !value.is_empty())
+                };
+                if let Some(title) = text(tag.title()) {
+                    track.title = title;
+                }
+                track.artist = text(tag.artist());
+                track.album = text(tag.album());
+            }
+        }
+        Ok(track)
+    }
+
+    /// `read` on the blocking pool, so hashing a large file does not stall the async
+    /// workers the schedulers and the feed server share.
+    pub async fn load(path: &Path) -> ApiResult<Self> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || LocalTrack::read(&path))
+            .await
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?
+    }
+}
+
+/// A NOS upload that has been allocated, kept until the file is published so that an
+/// interrupted upload continues where it stopped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NosSession {
+    bucket: String,
+    object_key: String,
+    token: String,
+    resource_id: String,
+    context: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UploadStage {
+    Hashing,
+    Checking,
+    Uploading,
+    Publishing,
+    Done,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadProgress {
+    pub md5: String,
+    pub stage: UploadStage,
+    pub sent: u64,
+    pub total: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudUpload {
+    pub song_id: String,
+    pub track: LocalTrack,
+    /// False when Netease already had the file and only the cloud entry was created.
+    pub transferred: bool,
+}
+
+/// Uploads local files into the user's cloud disk.
+pub struct CloudUploader {
+    http: reqwest::Client,
+    lbs: String,
+    chunk_size: usize,
+    sessions: Mutex<JsonStore<HashMap<String, NosSession>>>,
+}
+
+impl CloudUploader {
+    /// Keeps unfinished uploads in `path`.
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        CloudUploader {
+            http: reqwest::Client::new(),
+            lbs: NOS_LBS.to_string(),
+            chunk_size: NOS_CHUNK_SIZE,
+            sessions: Mutex::new(JsonStore::open(path)),
+        }
+    }
+
+    /// Points the NOS host lookup somewhere else, e.g. a local stand-in.
+    pub fn with_lbs(mut self, lbs: &str) -> Self {
+        self.lbs = lbs.to_string();
+        self
+    }
+
+    /// Runs the whole pipeline: check, token, NOS upload, cloud info and publish.
+    pub async fn upload(
+        &self,
+        client: &NeteaseClient,
+        path: &Path,
+        on_progress: impl Fn(UploadProgress),
+    ) -> ApiResult<CloudUpload> {
+        let progress = |md5: &str, stage, sent, total| {
+            on_progress(UploadProgress {
+                md5: md5.to_string(),
+                stage,
+                sent,
+                total,
+            })
+        };
+        progress("", UploadStage::Hashing, 0, 0);
+        let track = LocalTrack::load(path).await?;
+        let md5 = track.md5.as_str();
+        let size = track.size.to_string();
+        let bitrate = track.bitrate.unwrap_or(999_000).to_string();
+
+        progress(md5, UploadStage::Checking, 0, track.size);
+        let check = client
+            .call(
+                "/cloud/upload/check",
+                &[("md5", md5), ("length", &size), ("bitrate", &bitrate)],
+            )
+            .await?;
+        let transferred = check
+            .get("needUpload")
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+        let song_id = value_string(&check, "songId").unwrap_or_else(|| "0".to_string());
+
+        let mut session = self.session(client, &track).await?;
+        if transferred {
+            self.transfer(&track, &mut session, |sent| {
+                progress(md5, UploadStage::Uploading, sent, track.size)
+            })
+            .await?;
+        }
+
+        progress(md5, UploadStage::Publishing, track.size, track.size);
+        let title = escape_json(&track.title);
+        let artist = track.artist.as_deref().map(escape_json);
+        let album = track.album.as_deref().map(escape_json);
+        let file_name = escape_json(&track.file_name);
+        let mut params = vec![
+            ("md5", md5),
+            ("songid", song_id.as_str()),
+            ("filename", file_name.as_str()),
+            ("song", title.as_str()),
+            ("bitrate", bitrate.as_str()),
+            ("resourceId", session.resource_id.as_str()),
+        ];
+        if let Some(artist) = artist.as_deref() {
+            params.push(("artist", artist));
+        }
+        if let Some(album) = album.as_deref() {
+            params.push(("album", album));
+        }
+        let info = client.call("/upload/cloud/info/v2", &params).await?;
+        let song_id = value_string(&info, "songId")
+            .ok_or_else(|| ApiError::Malformed("cloud info without songId".to_string()))?;
+        client
+            .call("/cloud/pub/v2", &[("songid", song_id.as_str())])
+            .await?;
+
+        self.sessions
+            .lock()
+            .unwrap()
+            .update(|sessions| sessions.remove(md5))?;
+        progress(md5, UploadStage::Done, track.size, track.size);
+        Ok(CloudUpload {
+            song_id,
+            track,
+            transferred,
+        })
+    }
+
+    /// Reuses the stored session for this file or allocates a new NOS object.
+    async fn session(&self, client: &NeteaseClient, track: &LocalTrack) -> ApiResult<NosSession> {
+        if let Some(session) = self.sessions.lock().unwrap().get().get(&track.md5) {
+            return Ok(session.clone());
+        }
+        let file_name = escape_json(&track.file_name);
+        let body = client
+            .call(
+                "/nos/token/alloc",
+                &[
+                    ("filename", file_name.as_str()),
+                    ("ext", track.ext.as_str()),
+                    ("md5", track.md5.as_str()),
+                ],
+            )
+            .await?;
+        let field = |key: &str| {
+            body.get("result")
+                .and_then(|result| value_string(result, key))
+                .ok_or_else(|| ApiError::Malformed(format!("nos token without {}", key)))
+        };
+        let session = NosSession {
+            bucket: field("bucket").unwrap_or_else(|_| NOS_BUCKET.to_string()),
+            object_key: field("objectKey")?,
+            token: field("token")?,
+            resource_id: field("resourceId")?,
+            context: None,
+        };
+        self.save_session(&track.md5, &session)?;
+        Ok(session)
+    }
+
+    fn save_session(&self, md5: &str, session: &NosSession) -> ApiResult<()> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .update(|sessions| sessions.insert(md5.to_string(), session.clone()))?;
+        Ok(())
+    }
+
+    /// PUTs the file in chunks, first asking NOS how far an earlier attempt got.
+    async fn transfer(
+        &self,
+        track: &LocalTrack,
+        session: &mut NosSession,
+        on_sent: impl Fn(u64),
+    ) -> ApiResult<()> {
+        let lbs: Value = self
+            .http
+            .get(&self.lbs)
+            .query(&[("version", "1.0"), ("bucketname", session.bucket.as_str())])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let host = lbs
+            .pointer("/upload/0")
+            .and_then(Value::as_str)
+            .ok_or_else(|| ApiError::Malformed("lbs without upload host".to_string()))?;
+        let object_url = format!(
+            "{}/{}/{}",
+            host.trim_end_matches('/'),
+            session.bucket,
+            urlencoding(&session.object_key)
+        );
+
+        let mut offset = 0;
+        if let Some(context) = session.context.clone() {
+            match self.uploaded_offset(&object_url, session, &context).await {
+                Ok(uploaded) => offset = uploaded,
+                // the context expired, start over
+                Err(_) => session.context = None,
+            }
+        }
+
+        let mut file = tokio::fs::File::open(&track.path).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut buffer = vec![0; self.chunk_size];
+        on_sent(offset);
+        while offset < track.size || track.size == 0 {
+            let read = read_chunk(&mut file, &mut buffer).await?;
+            let complete = offset + read as u64 >= track.size;
+            let mut query = vec![
+                ("offset", offset.to_string()),
+                ("complete", complete.to_string()),
+                ("version", "1.0".to_string()),
+            ];
+            if let Some(context) = &session.context {
+                query.push(("context", context.clone()));
+            }
+            let body: Value = self
+                .http
+                .put(&object_url)
+                .query(&query)
+                .header("x-nos-token", &session.token)
+                .header("Content-Type", content_type(&track.ext))
+                .body(buffer[..read].to_vec())
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            offset = body
+                .get("offset")
+                .and_then(Value::as_u64)
+                .unwrap_or(offset + read as u64);
+            session.context = value_string(&body, "context").or(session.context.take());
+            self.save_session(&track.md5, session)?;
+            on_sent(offset);
+            if complete {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn uploaded_offset(
+        &self,
+        object_url: &str,
+        session: &NosSession,
+        context: &str,
+    ) -> ApiResult<u64> {
+        let body: Value = self
+            .http
+            .get(object_url)
+            .query(&[
+                ("uploadContext", ""),
+                ("version", "1.0"),
+                ("context", context),
+            ])
+            .header("x-nos-token", &session.token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        body.get("offset")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| ApiError::Malformed("upload context without offset".to_string()))
+    }
+}
+
+/// Page size used when walking the whole cloud library.
+const CLOUD_PAGE: usize = 200;
+/// Candidates offered for a manual pick.
+const CLOUD_CANDIDATES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CloudMatchAction {
+    /// The best candidate is confident and differs from the current binding.
+    Rebind,
+    AlreadyMatched,
+    Review,
+    Unmatched,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudMatch {
+    pub song_id: u64,
+    pub file_name: String,
+    pub entry: ImportEntry,
+    pub action: CloudMatchAction,
+    pub candidates: Vec<Candidate>,
+    /// Whether the rebind was sent; always false for dry runs.
+    pub applied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Reads every item of the cloud library, following `hasMore`.
+pub async fn cloud_songs(client: &NeteaseClient) -> ApiResult<Vec<Value>> {
+    let mut songs = vec![];
+    loop {
+        let offset = songs.len().to_string();
+        let limit = CLOUD_PAGE.to_string();
+        let body = client
+            .call("/user/cloud", &[("limit", &limit), ("offset", &offset)])
+            .await?;
+        let page = body
+            .get("data")
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+        let more = body
+            .get("hasMore")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if page.is_empty() {
+            break;
+        }
+        songs.extend(page);
+        if !more {
+            break;
+        }
+    }
+    Ok(songs)
+}
+
+/// Describes a `user/cloud` item the way the import matcher expects it: the tags Netease
+/// kept, or the file name when the tags are empty.
+fn cloud_entry(song: &Value) -> ImportEntry {
+    let text = |key: &str| {
+        song.get(key)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let file_name = text("fileName");
+    let duration = song
+        .pointer("/simpleSong/dt")
+        .and_then(Value::as_u64)
+        .map(|ms| ms / 1000);
+    let title = text("songName");
+    if title.is_empty() {
+        let stem = Path::new(&file_name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        return entry_from_label(0, &file_name, &stem, duration);
+    }
+    ImportEntry {
+        line: 0,
+        raw: file_name,
+        title,
+        artist: text("artist"),
+        duration,
+    }
+}
+
+/// Searches the catalogue for official songs matching a cloud item, retrying with the file
+/// name when the tags find nothing usable.
+pub async fn suggest_match(client: &NeteaseClient, song: &Value) -> ApiResult<CloudMatch> {
+    let song_id = song
+        .get("songId")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| ApiError::Malformed("cloud song without songId".to_string()))?;
+    let mut entry = cloud_entry(song);
+    let mut candidates = ranked_candidates(client, &entry).await?;
+    if status_of(candidates.first()) == MatchStatus::Unmatched {
+        let stem = Path::new(&entry.raw)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let by_name = entry_from_label(0, &entry.raw, &stem, entry.duration);
+        if !stem.is_empty() && by_name.title != entry.title {
+            let retried = ranked_candidates(client, &by_name).await?;
+            if retried.first().map(|c| c.score) > candidates.first().map(|c| c.score) {
+                entry = by_name;
+                candidates = retried;
+            }
+        }
+    }
+    candidates.truncate(CLOUD_CANDIDATES);
+
+    let action = match status_of(candidates.first()) {
+        _ if candidates.first().map(|c| c.id) == Some(song_id) => CloudMatchAction::AlreadyMatched,
+        MatchStatus::Matched => CloudMatchAction::Rebind,
+        MatchStatus::Review => CloudMatchAction::Review,
+        MatchStatus::Unmatched => CloudMatchAction::Unmatched,
+    };
+    Ok(CloudMatch {
+        song_id,
+        file_name: entry.raw.clone(),
+        entry,
+        action,
+        candidates,
+        applied: false,
+        error: None,
+    })
+}
+
+/// Binds the cloud item `song_id` to the official song `target`.
+pub async fn rebind(client: &NeteaseClient, uid: u64, song_id: u64, target: u64) -> ApiResult<()> {
+    client
+        .call(
+            "/cloud/match",
+            &[
+                ("uid", &uid.to_string()),
+                ("sid", &song_id.to_string()),
+                ("asid", &target.to_string()),
+            ],
+        )
+        .await
+        .map(|_| ())
+}
+
+/// Suggests a match for every cloud item and, unless `dry_run`, rebinds the confident ones.
+pub async fn match_library(
+    client: &NeteaseClient,
+    uid: u64,
+    dry_run: bool,
+) -> ApiResult<Vec<CloudMatch>> {
+    let songs = cloud_songs(client).await?;
+    let suggestions: Vec<(Value, ApiResult<CloudMatch>)> = stream::iter(songs)
+        .map(|song| async move {
+            let suggestion = suggest_match(client, &song).await;
+            (song, suggestion)
+        })
+        .buffered(SEARCH_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut report = vec![];
+    for (song, suggestion) in suggestions {
+        // one failed search is reported on its song rather than failing the whole library
+        let mut suggestion = match (suggestion, song.get("songId").and_then(Value::as_u64)) {
+            (Ok(suggestion), _) => suggestion,
+            (Err(err), Some(song_id)) => {
+                let entry = cloud_entry(&song);
+                report.push(CloudMatch {
+                    song_id,
+                    file_name: entry.raw.clone(),
+                    entry,
+                    action: CloudMatchAction::Unmatched,
+                    candidates: vec![],
+                    applied: false,
+                    error: Some(err.to_string()),
+                });
+                continue;
+            }
+            (Err(_), None) => continue,
+        };
+        if !dry_run && suggestion.action == CloudMatchAction::Rebind {
+            let target = suggestion.candidates[0].id;
+            match rebind(client, uid, suggestion.song_id, target).await {
+                Ok(()) => suggestion.applied = true,
+                Err(err) => suggestion.error = Some(err.to_string()),
+            }
+        }
+        report.push(suggestion);
+    }
+    Ok(report)
+}
+
+async fn read_chunk(file: &mut tokio::fs::File, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let read = file.read(&mut buffer[filled..]).await?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    Ok(filled)
+}
+
+/// Netease returns ids sometimes as numbers and sometimes as strings.
+fn value_string(body: &Value, key: &str) -> Option<String> {
+    match body.get(key)? {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+fn urlencoding(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn content_type(ext: &str) -> &'static str {
+    match ext {
+        "flac" => "audio/flac",
+        "m4a" => "audio/mp4",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        _ => "audio/mpeg",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{match_library, CloudMatchAction, CloudUploader, UploadStage};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use crate::store::TempDir;
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// A minimal NOS stand-in: LBS lookup, chunk PUTs and upload context queries.
+    /// It refuses the second chunk once to simulate a dropped upload.
+    fn nos_stand_in(received: Arc<Mutex<Vec<u8>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        let upload_host = host.clone();
+        std::thread::spawn(move || {
+            let mut refused = false;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.to_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let mut data = received.lock().unwrap();
+                let (status, reply) = if request_line.starts_with("GET /lbs") {
+                    ("200 OK", json!({ "upload": [upload_host] }))
+                } else if request_line.contains("uploadContext") {
+                    ("200 OK", json!({ "offset": data.len() }))
+                } else if !data.is_empty() && !refused {
+                    refused = true;
+                    ("500 Internal Server Error", json!({}))
+                } else {
+                    data.extend_from_slice(&body);
+                    ("200 OK", json!({ "context": "ctx", "offset": data.len() }))
+                };
+                let reply = reply.to_string();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                );
+            }
+        });
+        format!("{}/lbs", host)
+    }
+
+    #[tokio::test]
+    async fn test_cloud_upload_resumes() {
+        let dir = TempDir::new("cloud");
+        let audio = dir.join("Artist - \"Quoted\".mp3");
+        std::fs::write(&audio, b"0123456789").unwrap();
+
+        let received = Arc::new(Mutex::new(vec![]));
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/cloud/upload/check", |_| {
+                    json!({ "code": 200, "needUpload": true, "songId": "0" })
+                })
+                .on("/nos/token/alloc", |_| {
+                    json!({ "code": 200, "result": { "objectKey": "obj/1.mp3", "token": "t", "resourceId": 42 } })
+                })
+                .on("/upload/cloud/info/v2", |_| json!({ "code": 200, "songId": "900" }))
+                .on("/cloud/pub/v2", |_| json!({ "code": 200 })),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let mut uploader =
+            CloudUploader::open(dir.join("uploads.json")).with_lbs(&nos_stand_in(received.clone()));
+        uploader.chunk_size = 4;
+
+        assert!(uploader.upload(&client, &audio, |_| {}).await.is_err());
+        assert_eq!(received.lock().unwrap().len(), 4);
+
+        let stages = Mutex::new(vec![]);
+        let upload = uploader
+            .upload(&client, &audio, |progress| {
+                stages.lock().unwrap().push(progress.stage)
+            })
+            .await
+            .unwrap();
+        assert_eq!(upload.song_id, "900");
+        assert_eq!(received.lock().unwrap().as_slice(), b"0123456789");
+        // the second attempt reuses the allocated object
+        assert_eq!(transport.calls_to("/nos/token/alloc").len(), 1);
+        assert_eq!(stages.lock().unwrap().last(), Some(&UploadStage::Done));
+
+        let info = &transport.calls_to("/upload/cloud/info/v2")[0];
+        assert_eq!(param(info, "song"), "Artist - \\\"Quoted\\\"");
+        assert_eq!(param(info, "resourceId"), "42");
+        assert_eq!(
+            param(&transport.calls_to("/cloud/pub/v2")[0], "songid"),
+            "900"
+        );
+        assert!(uploader.sessions.lock().unwrap().get().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_match_library() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/user/cloud", |_| {
+                    json!({ "code": 200, "hasMore": false, "data": [
+                        { "songId": 10, "songName": "One", "artist": "A", "fileName": "one.mp3", "simpleSong": { "dt": 200000 } },
+                        { "songId": 2, "songName": "Two", "artist": "B", "fileName": "two.mp3" },
+                        { "songId": 30, "songName": "", "artist": "", "fileName": "C - Three.mp3" },
+                        { "songId": 40, "songName": "track01", "artist": "", "fileName": "D - Four.flac" },
+                        { "songId": 50, "songName": "Five", "artist": "E", "fileName": "five.mp3" },
+                    ] })
+                })
+                .on("/search", |params| {
+                    if param(params, "keywords") == "Five E" {
+                        return json!({ "code": -1, "message": "search failed" });
+                    }
+                    let (id, name, artist) = match param(params, "keywords") {
+                        "One A" => (1, "One", "A"),
+                        "Two B" => (2, "Two", "B"),
+                        "Three C" => (3, "Three", "C"),
+                        "Four D" => (4, "Four", "D"),
+                        _ => (99, "Unrelated", "Z"),
+                    };
+                    json!({ "code": 200, "result": { "songs": [
+                        { "id": id, "name": name, "artists": [{ "name": artist }], "duration": 200000 }
+                    ] } })
+                })
+                .on("/cloud/match", |_| json!({ "code": 200 })),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+
+        let report = match_library(&client, 7, true).await.unwrap();
+        let actions: Vec<_> = report.iter().map(|m| m.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                CloudMatchAction::Rebind,
+                CloudMatchAction::AlreadyMatched,
+                CloudMatchAction::Rebind,
+                CloudMatchAction::Rebind,
+                CloudMatchAction::Unmatched,
+            ]
+        );
+        assert_eq!(report[3].candidates[0].id, 4);
+        assert!(report[4]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("search failed"));
+        assert!(transport.calls_to("/cloud/match").is_empty());
+
+        let report = match_library(&client, 7, false).await.unwrap();
+        assert!(report
+            .iter()
+            .filter(|m| m.action == CloudMatchAction::Rebind)
+            .all(|m| m.applied));
+        let calls = transport.calls_to("/cloud/match");
+        assert_eq!(calls.len(), 3);
+        assert_eq!(
+            (param(&calls[0], "sid"), param(&calls[0], "asid")),
+            ("10", "1")
+        );
+        assert_eq!(param(&calls[0], "uid"), "7");
+    }
+}
@@ -0,0 +1,516 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::json::text;
+use crate::netease::client::{escape_json, ApiError, ApiOutcome, ApiResult, NeteaseClient};
+
+/// Top-level comments requested per page.
+const COMMENT_PAGE_SIZE: usize = 20;
+/// Replies requested per `comment/floor` call.
+const REPLY_PAGE_SIZE: usize = 20;
+
+/// Anything that has a comment thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResourceType {
+    Song,
+    Mv,
+    Playlist,
+    Album,
+    DjProgram,
+    Video,
+    /// Events are addressed by their full `threadId` instead of an id.
+    Event,
+}
+
+impl ResourceType {
+    /// The `type` the comment routes expect.
+    fn code(&self) -> &'static str {
+        match self {
+            ResourceType::Song => "0",
+            ResourceType::Mv => "1",
+            ResourceType::Playlist => "2",
+            ResourceType::Album => "3",
+            ResourceType::DjProgram => "4",
+            ResourceType::Video => "5",
+            ResourceType::Event => "6",
+        }
+    }
+
+    fn prefix(&self) -> &'static str {
+        match self {
+            ResourceType::Song => "R_SO_4_",
+            ResourceType::Mv => "R_MV_5_",
+            ResourceType::Playlist => "A_PL_0_",
+            ResourceType::Album => "R_AL_3_",
+            ResourceType::DjProgram => "A_DJ_1_",
+            ResourceType::Video => "R_VI_62_",
+            ResourceType::Event => "",
+        }
+    }
+
+    /// The `threadId` of the resource's comments.
+    pub fn thread_id(&self, id: &str) -> String {
+        format!("{}{}", self.prefix(), id)
+    }
+
+    /// `type`, `id` and, for events, `threadId` as the comment routes read them.
+    pub(crate) fn params<'a>(&self, id: &'a str) -> Vec<(&'static str, &'a str)> {
+        vec![("type", self.code()), ("id", id), ("threadId", id)]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommentSort {
+    Recommended,
+    Hot,
+    Time,
+}
+
+impl CommentSort {
+    pub(crate) fn sort_type(&self) -> &'static str {
+        match self {
+            CommentSort::Recommended => "1",
+            CommentSort::Hot => "2",
+            CommentSort::Time => "3",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum CommentAction {
+    Add,
+    #[serde(rename_all = "camelCase")]
+    Reply {
+        comment_id: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    Delete {
+        comment_id: u64,
+    },
+}
+
+impl CommentAction {
+    /// The `t` the `/comment` route expects.
+    fn operator(&self) -> &'static str {
+        match self {
+            CommentAction::Add => "0",
+            CommentAction::Delete { .. } => "1",
+            CommentAction::Reply { .. } => "2",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum CommentResult {
+    Posted {
+        comment: Comment,
+    },
+    Deleted,
+    /// Moderation refused the comment; other failures are errors.
+    Rejected {
+        code: i64,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentUser {
+    pub id: u64,
+    pub nickname: String,
+    pub avatar_url: String,
+}
+
+/// The comment a reply answers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepliedTo {
+    pub comment_id: u64,
+    pub user: CommentUser,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    pub id: u64,
+    pub user: CommentUser,
+    pub content: String,
+    /// Unix time in milliseconds.
+    pub time: i64,
+    pub liked_count: u64,
+    pub liked: bool,
+    pub reply_count: u64,
+    pub replied_to: Option<RepliedTo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentNode {
+    pub comment: Comment,
+    /// Replies loaded so far, oldest first as `comment/floor` returns them.
+    pub replies: Vec<Comment>,
+    pub has_more_replies: bool,
+    /// `time` to continue the replies from; `-1` before the first page.
+    pub reply_cursor: i64,
+}
+
+/// The comments of one resource, loaded a page at a time.
+///
+/// The tree is plain data so the frontend can hold it and hand it back for the next page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentTree {
+    pub resource: ResourceType,
+    pub id: String,
+    pub sort: CommentSort,
+    pub total: u64,
+    pub roots: Vec<CommentNode>,
+    pub has_more: bool,
+    /// The page `load_more` requests next, starting at 1.
+    pub next_page: usize,
+    /// Cursor returned with the last page, used when sorting by time.
+    pub cursor: String,
+}
+
+impl CommentTree {
+    pub fn new(resource: ResourceType, id: impl Into<String>, sort: CommentSort) -> Self {
+        CommentTree {
+            resource,
+            id: id.into(),
+            sort,
+            total: 0,
+            roots: vec![],
+            has_more: true,
+            next_page: 1,
+            cursor: "0".to_string(),
+        }
+    }
+
+    /// Loads the next page of top-level comments and returns how many were added.
+    pub async fn load_more(&mut self, client: &NeteaseClient) -> ApiResult<usize> {
+        if !self.has_more {
+            return Ok(0);
+        }
+        let page_no = self.next_page.to_string();
+        let page_size = COMMENT_PAGE_SIZE.to_string();
+        let mut params = self.resource.params(&self.id);
+        params.extend([
+            ("pageNo", page_no.as_str()),
+            ("pageSize", page_size.as_str()),
+            ("sortType", self.sort.sort_type()),
+            ("cursor", self.cursor.as_str()),
+        ]);
+        let body = client.call("/comment/new", &params).await?;
+        let data = body.get("data").unwrap_or(&Value::Null);
+
+        let comments = comments_of(data);
+        let before = self.roots.len();
+        for comment in comments {
+            if self.roots.iter().any(|node| node.comment.id == comment.id) {
+                continue;
+            }
+            self.roots.push(CommentNode {
+                has_more_replies: comment.reply_count > 0,
+                comment,
+                replies: vec![],
+                reply_cursor: -1,
+            });
+        }
+        let added = self.roots.len() - before;
+
+        self.total = data
+            .get("totalCount")
+            .and_then(Value::as_u64)
+            .unwrap_or(self.total);
+        self.has_more = added > 0
+            && data
+                .get("hasMore")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+        self.next_page += 1;
+        if let Some(cursor) = data.get("cursor") {
+            self.cursor = match cursor {
+                Value::String(cursor) => cursor.clone(),
+                cursor => cursor.to_string(),
+            };
+        }
+        Ok(added)
+    }
+
+    /// Loads the next page of replies under `comment_id` and returns how many were added.
+    pub async fn load_replies(
+        &mut self,
+        client: &NeteaseClient,
+        comment_id: u64,
+    ) -> ApiResult<usize> {
+        let node = match self
+            .roots
+            .iter_mut()
+            .find(|node| node.comment.id == comment_id)
+        {
+            Some(node) if node.has_more_replies => node,
+            _ => return Ok(0),
+        };
+        let parent = comment_id.to_string();
+        let time = node.reply_cursor.to_string();
+        let limit = REPLY_PAGE_SIZE.to_string();
+        let mut params = self.resource.params(&self.id);
+        params.extend([
+            ("parentCommentId", parent.as_str()),
+            ("time", time.as_str()),
+            ("limit", limit.as_str()),
+        ]);
+        let body = client.call("/comment/floor", &params).await?;
+        let data = body.get("data").unwrap_or(&Value::Null);
+
+        let before = node.replies.len();
+        for reply in comments_of(data) {
+            if !node.replies.iter().any(|old| old.id == reply.id) {
+                node.replies.push(reply);
+            }
+        }
+        let added = node.replies.len() - before;
+        node.has_more_replies = added > 0
+            && data
+                .get("hasMore")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+        node.reply_cursor = data
+            .get("time")
+            .and_then(Value::as_i64)
+            .or_else(|| node.replies.last().map(|reply| reply.time))
+            .unwrap_or(-1);
+        Ok(added)
+    }
+}
+
+/// Adds, replies to or deletes a comment on `id`; `content` is ignored when deleting.
+pub async fn send(
+    client: &NeteaseClient,
+    resource: ResourceType,
+    id: &str,
+    action: CommentAction,
+    content: &str,
+) -> ApiResult<CommentResult> {
+    let content = escape_json(content);
+    let comment_id = match action {
+        CommentAction::Add => None,
+        CommentAction::Reply { comment_id } | CommentAction::Delete { comment_id } => {
+            Some(comment_id.to_string())
+        }
+    };
+    let mut params = resource.params(id);
+    params.push(("t", action.operator()));
+    if let Some(comment_id) = &comment_id {
+        params.push(("commentId", comment_id));
+    }
+    if !matches!(action, CommentAction::Delete { .. }) {
+        params.push(("content", &content));
+    }
+
+    let body = match client.call("/comment", &params).await {
+        Ok(body) => body,
+        // rate limits, missing resources and logins are errors rather than rejections
+        Err(ApiError::Status { code, message })
+            if ApiOutcome::classify(code) == ApiOutcome::RiskControl =>
+        {
+            return Ok(CommentResult::Rejected { code, message })
+        }
+        Err(err) => return Err(err),
+    };
+    if let CommentAction::Delete { .. } = action {
+        return Ok(CommentResult::Deleted);
+    }
+    body.get("comment")
+        .and_then(comment_of)
+        .map(|comment| CommentResult::Posted { comment })
+        .ok_or_else(|| ApiError::Malformed("comment missing from /comment".to_string()))
+}
+
+fn comments_of(data: &Value) -> Vec<Comment> {
+    data.get("comments")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(comment_of)
+        .collect()
+}
+
+fn user_of(user: &Value) -> CommentUser {
+    CommentUser {
+        id: user
+            .get("userId")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+        nickname: text(user, "nickname"),
+        avatar_url: text(user, "avatarUrl"),
+    }
+}
+
+fn comment_of(comment: &Value) -> Option<Comment> {
+    let replied_to = comment
+        .get("beReplied")
+        .and_then(Value::as_array)
+        .and_then(|replied| replied.first())
+        .map(|replied| RepliedTo {
+            comment_id: replied
+                .get("beRepliedCommentId")
+                .and_then(Value::as_u64)
+                .unwrap_or_default(),
+            user: user_of(replied.get("user").unwrap_or(&Value::Null)),
+            content: text(replied, "content"),
+        });
+    // newer responses only carry the reply count inside `showFloorComment`
+    let reply_count = comment
+        .get("replyCount")
+        .or_else(|| comment.pointer("/showFloorComment/replyCount"))
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+    Some(Comment {
+        id: comment.get("commentId")?.as_u64()?,
+        user: user_of(comment.get("user").unwrap_or(&Value::Null)),
+        content: text(comment, "content"),
+        time: comment
+            .get("time")
+            .and_then(Value::as_i64)
+            .unwrap_or_default(),
+        liked_count: comment
+            .get("likedCount")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+        liked: comment
+            .get("liked")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        reply_count,
+        replied_to,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{send, CommentAction, CommentResult, CommentSort, CommentTree, ResourceType};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::{ApiOutcome, NeteaseClient};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn comment(id: u64, time: i64, replies: u64) -> serde_json::Value {
+        json!({
+            "commentId": id, "content": format!("c{}", id), "time": time, "likedCount": 1,
+            "user": { "userId": 7, "nickname": "u", "avatarUrl": "" },
+            "showFloorComment": { "replyCount": replies },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_comment_tree_paging() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/comment/new", |params| match param(params, "cursor") {
+                    "0" => json!({ "code": 200, "data": {
+                        "comments": [comment(1, 300, 2), comment(2, 200, 0)],
+                        "totalCount": 3, "hasMore": true, "cursor": "200",
+                    } }),
+                    _ => json!({ "code": 200, "data": {
+                        "comments": [comment(2, 200, 0), comment(3, 100, 0)],
+                        "totalCount": 3, "hasMore": false, "cursor": "100",
+                    } }),
+                })
+                .on("/comment/floor", |_| {
+                    json!({ "code": 200, "data": {
+                        "comments": [{
+                            "commentId": 10, "content": "r", "time": 310,
+                            "user": { "userId": 8, "nickname": "v" },
+                            "beReplied": [{ "beRepliedCommentId": 1, "content": "c1",
+                                "user": { "userId": 7, "nickname": "u" } }],
+                        }],
+                        "hasMore": false, "time": 310,
+                    } })
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let mut tree = CommentTree::new(ResourceType::Song, "5", CommentSort::Time);
+
+        assert_eq!(tree.load_more(&client).await.unwrap(), 2);
+        // the overlapping comment of the second page is not repeated
+        assert_eq!(tree.load_more(&client).await.unwrap(), 1);
+        assert!(!tree.has_more);
+        assert_eq!(tree.load_more(&client).await.unwrap(), 0);
+        assert_eq!(tree.total, 3);
+        let new = transport.calls_to("/comment/new");
+        assert_eq!(new.len(), 2);
+        assert_eq!(param(&new[1], "cursor"), "200");
+        assert_eq!((param(&new[1], "type"), param(&new[1], "id")), ("0", "5"));
+
+        assert_eq!(tree.load_replies(&client, 2).await.unwrap(), 0);
+        assert_eq!(tree.load_replies(&client, 1).await.unwrap(), 1);
+        let node = &tree.roots[0];
+        assert!(!node.has_more_replies);
+        assert_eq!(node.replies[0].replied_to.as_ref().unwrap().comment_id, 1);
+        let floor = transport.calls_to("/comment/floor");
+        assert_eq!(
+            (
+                param(&floor[0], "parentCommentId"),
+                param(&floor[0], "time")
+            ),
+            ("1", "-1")
+        );
+        assert_eq!(ResourceType::Video.thread_id("9"), "R_VI_62_9");
+    }
+
+    #[tokio::test]
+    async fn test_send_comment() {
+        let transport = Arc::new(MockTransport::default().on("/comment", |params| {
+            match (param(params, "t"), param(params, "content")) {
+                (_, "bad") => json!({ "code": 250, "message": "内容违规" }),
+                (_, "gone") => json!({ "code": 404, "message": "资源不存在" }),
+                ("1", _) => json!({ "code": 200 }),
+                (_, content) => json!({ "code": 200, "comment": {
+                    "commentId": 42, "content": content, "time": 1,
+                    "user": { "userId": 7, "nickname": "u" },
+                } }),
+            }
+        }));
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+
+        let reply = CommentAction::Reply { comment_id: 9 };
+        match send(&client, ResourceType::Mv, "3", reply, "say \"hi\"")
+            .await
+            .unwrap()
+        {
+            CommentResult::Posted { comment } => assert_eq!(comment.id, 42),
+            other => panic!("unexpected {:?}", other),
+        }
+        let call = &transport.calls_to("/comment")[0];
+        assert_eq!((param(call, "type"), param(call, "t")), ("1", "2"));
+        assert_eq!(param(call, "commentId"), "9");
+        assert_eq!(param(call, "content"), "say \\\"hi\\\"");
+
+        let delete = CommentAction::Delete { comment_id: 42 };
+        assert_eq!(
+            send(&client, ResourceType::Mv, "3", delete, "")
+                .await
+                .unwrap(),
+            CommentResult::Deleted
+        );
+        assert_eq!(
+            send(&client, ResourceType::Song, "3", CommentAction::Add, "bad")
+                .await
+                .unwrap(),
+            CommentResult::Rejected {
+                code: 250,
+                message: "内容违规".to_string()
+            }
+        );
+        let err = send(&client, ResourceType::Song, "4", CommentAction::Add, "gone")
+            .await
+            .unwrap_err();
+        assert_eq!(err.outcome(), Some(ApiOutcome::NoCopyright));
+    }
+}
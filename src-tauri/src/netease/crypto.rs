@@ -92,6 +92,15 @@ impl Crypto {
         QueryParams::from(vec![("eparams", params.as_str())]).stringify()
     }
 
+    /// Reads back what `linuxapi` sent, given the `eparams` hex.
+    #[cfg(test)]
+    pub fn linuxapi_decrypt(eparams: &str) -> String {
+        let data = hex::decode(eparams).unwrap();
+        let text =
+            openssl::symm::decrypt(Cipher::aes_128_ecb(), &LINUX_API_KEY, None, &data).unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
     pub fn aes_encrypt(
         data: &str,
         key: &Vec<u8>,
@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::history::Named;
+use crate::json::named;
+use crate::netease::client::{escape_json, ApiResult, NeteaseClient};
+use crate::netease::message::{attachment_of, Attachment};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    pub id: u64,
+    pub user: Named,
+    pub avatar_url: String,
+    /// Unix time in milliseconds.
+    pub time: i64,
+    /// Netease's event type, e.g. 18 for a shared song or 22 for a forward.
+    pub kind: u64,
+    pub text: String,
+    pub attachment: Option<Attachment>,
+    pub pictures: Vec<String>,
+    /// The original event when this one forwards it.
+    pub forwarded: Option<Box<Event>>,
+    /// Comment thread, for `comment::CommentTree` with `ResourceType::Event`.
+    pub thread_id: String,
+    pub liked_count: u64,
+    pub comment_count: u64,
+    pub share_count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventPage {
+    pub events: Vec<Event>,
+    pub more: bool,
+    /// Pass back as `lasttime` to load the following page.
+    pub lasttime: Option<i64>,
+}
+
+/// What a published event shares; albums cannot be shared as events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind", content = "id")]
+pub enum Shared {
+    Song(u64),
+    Playlist(u64),
+    Mv(u64),
+    DjProgram(u64),
+    DjRadio(u64),
+}
+
+impl Shared {
+    fn params(&self) -> (&'static str, u64) {
+        match *self {
+            Shared::Song(id) => ("song", id),
+            Shared::Playlist(id) => ("playlist", id),
+            Shared::Mv(id) => ("mv", id),
+            Shared::DjProgram(id) => ("djprogram", id),
+            Shared::DjRadio(id) => ("djradio", id),
+        }
+    }
+}
+
+fn count(info: &Value, key: &str) -> u64 {
+    info.get(key).and_then(Value::as_u64).unwrap_or_default()
+}
+
+/// Parses an event; its `json` field is a JSON document sent as a string, holding the text,
+/// the shared resource and, for forwards, the original event.
+pub(crate) fn event_of(event: &Value) -> Option<Event> {
+    let user = event.get("user").unwrap_or(&Value::Null);
+    let body: Value = event
+        .get("json")
+        .and_then(Value::as_str)
+        .and_then(|json| serde_json::from_str(json).ok())
+        .unwrap_or(Value::Null);
+    let info = event.get("info").unwrap_or(&Value::Null);
+    Some(Event {
+        id: event.get("id")?.as_u64()?,
+        user: named(user, "userId", "nickname").unwrap_or(Named {
+            id: 0,
+            name: String::new(),
+        }),
+        avatar_url: user
+            .get("avatarUrl")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        time: event
+            .get("eventTime")
+            .and_then(Value::as_i64)
+            .unwrap_or_default(),
+        kind: event
+            .get("type")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+        text: body
+            .get("msg")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        attachment: attachment_of(&body),
+        pictures: event
+            .get("pics")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|pic| pic.get("originUrl").and_then(Value::as_str))
+            .map(str::to_string)
+            .collect(),
+        forwarded: body.get("event").and_then(event_of).map(Box::new),
+        thread_id: info
+            .get("threadId")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        liked_count: count(info, "likedCount"),
+        comment_count: count(info, "commentCount"),
+        share_count: count(info, "shareCount"),
+    })
+}
+
+fn page_of(body: &Value, key: &str) -> EventPage {
+    let events: Vec<Event> = body
+        .get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(event_of)
+        .collect();
+    let more = body.get("more").and_then(Value::as_bool).unwrap_or(false);
+    let lasttime = body
+        .get("lasttime")
+        .and_then(Value::as_i64)
+        .or_else(|| events.last().map(|event| event.time));
+    EventPage {
+        events,
+        more,
+        lasttime: lasttime.filter(|_| more),
+    }
+}
+
+/// The signed-in user's timeline, newest first; `lasttime` continues a previous page.
+pub async fn timeline(
+    client: &NeteaseClient,
+    lasttime: Option<i64>,
+    limit: usize,
+) -> ApiResult<EventPage> {
+    let body = client
+        .call(
+            "/event",
+            &[
+                ("pagesize", &limit.to_string()),
+                ("lasttime", &lasttime.unwrap_or(-1).to_string()),
+            ],
+        )
+        .await?;
+    Ok(page_of(&body, "event"))
+}
+
+/// Events posted by one user, newest first.
+pub async fn user_events(
+    client: &NeteaseClient,
+    user_id: u64,
+    lasttime: Option<i64>,
+    limit: usize,
+) -> ApiResult<EventPage> {
+    let body = client
+        .call(
+            "/user/event",
+            &[
+                ("uid", &user_id.to_string()),
+                ("limit", &limit.to_string()),
+                ("lasttime", &lasttime.unwrap_or(-1).to_string()),
+            ],
+        )
+        .await?;
+    Ok(page_of(&body, "events"))
+}
+
+/// Publishes `text` as an event, sharing a resource when given. Returns the new event when
+/// Netease sends it back.
+pub async fn publish(
+    client: &NeteaseClient,
+    text: &str,
+    shared: Option<Shared>,
+) -> ApiResult<Option<Event>> {
+    let (kind, id) = match shared {
+        Some(shared) => {
+            let (kind, id) = shared.params();
+            (kind, id.to_string())
+        }
+        None => ("noresource", String::new()),
+    };
+    let body = client
+        .call(
+            "/share/resource",
+            &[("type", kind), ("id", &id), ("msg", &escape_json(text))],
+        )
+        .await?;
+    Ok(body.get("event").and_then(event_of))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{publish, timeline, Shared};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use crate::netease::message::Attachment;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_event_timeline() {
+        let original = json!({
+            "id": 1, "type": 39, "eventTime": 50, "user": { "userId": 3, "nickname": "cat" },
+            "json": json!({ "msg": "new video", "video": { "videoId": "AB12", "title": "Clip" } }).to_string(),
+        });
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/event", move |params| {
+                    assert_eq!(param(params, "lasttime"), "-1");
+                    json!({ "code": 200, "more": true, "lasttime": 90, "event": [
+                        { "id": 3, "type": 18, "eventTime": 100, "user": { "userId": 2, "nickname": "ann" },
+                          "info": { "threadId": "A_EV_2_3_2", "likedCount": 4 },
+                          "pics": [{ "originUrl": "http://p/1.jpg" }],
+                          "json": json!({ "msg": "listen", "song": { "id": 9, "name": "Tune", "artists": [{ "id": 5, "name": "Band" }] } }).to_string() },
+                        { "id": 2, "type": 22, "eventTime": 90, "user": { "userId": 2, "nickname": "ann" },
+                          "json": json!({ "msg": "fwd", "event": original }).to_string() },
+                    ] })
+                })
+                .on("/share/resource", |params| {
+                    json!({ "code": 200, "event": {
+                        "id": 4, "type": 24, "eventTime": 120, "user": { "userId": 1, "nickname": "me" },
+                        "json": format!("{{\"msg\":\"{}\",\"playlist\":{{\"id\":{}}}}}", param(params, "msg"), param(params, "id")),
+                    } })
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+
+        let page = timeline(&client, None, 20).await.unwrap();
+        assert_eq!(page.lasttime, Some(90));
+        let song = &page.events[0];
+        assert_eq!((song.text.as_str(), song.liked_count), ("listen", 4));
+        assert_eq!(song.pictures, vec!["http://p/1.jpg".to_string()]);
+        assert!(matches!(
+            song.attachment,
+            Some(Attachment::Song { id: 9, .. })
+        ));
+        let forwarded = page.events[1].forwarded.as_ref().unwrap();
+        assert_eq!(forwarded.user.name, "cat");
+        assert_eq!(
+            forwarded.attachment,
+            Some(Attachment::Video {
+                id: "AB12".to_string(),
+                title: "Clip".to_string()
+            })
+        );
+
+        let event = publish(&client, "mix \"one\"", Some(Shared::Playlist(77)))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.text, "mix \"one\"");
+        assert!(matches!(
+            event.attachment,
+            Some(Attachment::Playlist { id: 77, .. })
+        ));
+        let call = &transport.calls_to("/share/resource")[0];
+        assert_eq!(param(call, "type"), "playlist");
+    }
+}
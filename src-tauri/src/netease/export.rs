@@ -0,0 +1,306 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+
+use crate::netease::client::{ApiError, ApiResult, NeteaseClient};
+use crate::netease::playlist::{song_details, track_ids_of, PlaylistTracks};
+
+/// Extensions looked up when pointing entries at downloaded files.
+const AUDIO_EXTENSIONS: [&str; 6] = ["flac", "mp3", "m4a", "ogg", "wav", "ape"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    M3u8,
+    Xspf,
+    Csv,
+    Json,
+}
+
+/// Everything Netease returned for a playlist, used as the source of every export format.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlaylistSnapshot {
+    pub playlist: Value,
+    pub songs: Vec<Value>,
+    pub privileges: Vec<Value>,
+}
+
+struct Entry {
+    id: u64,
+    title: String,
+    artists: String,
+    album: String,
+    duration_ms: u64,
+    cover: String,
+    location: String,
+    local: Option<PathBuf>,
+}
+
+pub async fn fetch_snapshot(client: &NeteaseClient, id: &str) -> ApiResult<PlaylistSnapshot> {
+    let detail = client.call("/playlist/detail", &[("id", id)]).await?;
+    let ids = track_ids_of(&detail)?;
+    let mut playlist = detail
+        .get("playlist")
+        .cloned()
+        .ok_or_else(|| ApiError::Malformed("missing playlist".to_string()))?;
+    if let Some(playlist) = playlist.as_object_mut() {
+        // the truncated track list is replaced by the full `songs`
+        playlist.remove("tracks");
+    }
+
+    let PlaylistTracks { songs, privileges } = song_details(client, &ids).await?;
+    Ok(PlaylistSnapshot {
+        playlist,
+        songs,
+        privileges,
+    })
+}
+
+impl PlaylistSnapshot {
+    pub fn name(&self) -> &str {
+        self.playlist
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+    }
+
+    /// Renders the snapshot; with `local_dir`, entries point at downloaded files that exist there.
+    pub fn render(&self, format: ExportFormat, local_dir: Option<&Path>) -> String {
+        let entries: Vec<Entry> = self
+            .songs
+            .iter()
+            .map(|song| Entry::from_song(song, local_dir))
+            .collect();
+        match format {
+            ExportFormat::M3u8 => self.render_m3u8(&entries),
+            ExportFormat::Xspf => self.render_xspf(&entries),
+            ExportFormat::Csv => render_csv(&entries),
+            ExportFormat::Json => self.render_json(&entries),
+        }
+    }
+
+    fn render_m3u8(&self, entries: &[Entry]) -> String {
+        let mut out = format!("#EXTM3U\n#PLAYLIST:{}\n", self.name());
+        for entry in entries {
+            out.push_str(&format!(
+                "#EXTINF:{},{} - {}\n{}\n",
+                entry.duration_ms / 1000,
+                entry.artists,
+                entry.title,
+                entry.location
+            ));
+        }
+        out
+    }
+
+    fn render_xspf(&self, entries: &[Entry]) -> String {
+        let mut out = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+        );
+        out.push_str(&format!("  <title>{}</title>\n", escape_xml(self.name())));
+        out.push_str("  <trackList>\n");
+        for entry in entries {
+            out.push_str("    <track>\n");
+            out.push_str(&format!(
+                "      <location>{}</location>\n",
+                escape_xml(&entry.location)
+            ));
+            out.push_str(&format!(
+                "      <identifier>https://music.163.com/song?id={}</identifier>\n",
+                entry.id
+            ));
+            out.push_str(&format!(
+                "      <title>{}</title>\n",
+                escape_xml(&entry.title)
+            ));
+            out.push_str(&format!(
+                "      <creator>{}</creator>\n",
+                escape_xml(&entry.artists)
+            ));
+            out.push_str(&format!(
+                "      <album>{}</album>\n",
+                escape_xml(&entry.album)
+            ));
+            out.push_str(&format!(
+                "      <duration>{}</duration>\n",
+                entry.duration_ms
+            ));
+            if !entry.cover.is_empty() {
+                out.push_str(&format!(
+                    "      <image>{}</image>\n",
+                    escape_xml(&entry.cover)
+                ));
+            }
+            out.push_str("    </track>\n");
+        }
+        out.push_str("  </trackList>\n</playlist>\n");
+        out
+    }
+
+    fn render_json(&self, entries: &[Entry]) -> String {
+        let mut snapshot = json!({
+            "playlist": self.playlist,
+            "songs": self.songs,
+            "privileges": self.privileges,
+        });
+        let local_files: Map<String, Value> = entries
+            .iter()
+            .filter_map(|entry| {
+                let path = entry.local.as_ref()?;
+                Some((entry.id.to_string(), json!(path.to_string_lossy())))
+            })
+            .collect();
+        if !local_files.is_empty() {
+            snapshot["localFiles"] = Value::Object(local_files);
+        }
+        serde_json::to_string_pretty(&snapshot).unwrap_or_default()
+    }
+}
+
+fn render_csv(entries: &[Entry]) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let _ = writer.write_record(["id", "title", "artists", "album", "duration", "location"]);
+    for entry in entries {
+        let _ = writer.write_record([
+            entry.id.to_string().as_str(),
+            &entry.title,
+            &entry.artists,
+            &entry.album,
+            (entry.duration_ms / 1000).to_string().as_str(),
+            &entry.location,
+        ]);
+    }
+    String::from_utf8(writer.into_inner().unwrap_or_default()).unwrap_or_default()
+}
+
+impl Entry {
+    fn from_song(song: &Value, local_dir: Option<&Path>) -> Self {
+        let id = song.get("id").and_then(Value::as_u64).unwrap_or_default();
+        let text = |pointer: &str| {
+            song.pointer(pointer)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let artists = song
+            .get("ar")
+            .and_then(Value::as_array)
+            .map(|artists| {
+                artists
+                    .iter()
+                    .filter_map(|artist| artist.get("name").and_then(Value::as_str))
+                    .collect::<Vec<&str>>()
+                    .join(",")
+            })
+            .unwrap_or_default();
+        let title = text("/name");
+
+        let local = local_dir.and_then(|dir| find_local_file(dir, id, &artists, &title));
+        let location = local
+            .as_ref()
+            .and_then(|path| Url::from_file_path(path).ok())
+            .map(String::from)
+            .unwrap_or_else(|| format!("https://music.163.com/song/media/outer/url?id={}.mp3", id));
+
+        Entry {
+            id,
+            title,
+            artists,
+            album: text("/al/name"),
+            duration_ms: song.get("dt").and_then(Value::as_u64).unwrap_or_default(),
+            cover: text("/al/picUrl"),
+            location,
+            local,
+        }
+    }
+}
+
+/// Looks for `<artists> - <title>.<ext>` as the Netease client names downloads, then `<id>.<ext>`.
+fn find_local_file(dir: &Path, id: u64, artists: &str, title: &str) -> Option<PathBuf> {
+    let stems = [format!("{} - {}", artists, title), id.to_string()];
+    stems
+        .iter()
+        .flat_map(|stem| {
+            AUDIO_EXTENSIONS
+                .iter()
+                .map(move |ext| dir.join(format!("{}.{}", stem, ext)))
+        })
+        .find(|path| path.is_file())
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExportFormat, PlaylistSnapshot};
+    use crate::store::TempDir;
+    use serde_json::json;
+
+    fn snapshot() -> PlaylistSnapshot {
+        PlaylistSnapshot {
+            playlist: json!({ "id": 1, "name": "Rock & Roll" }),
+            songs: vec![
+                json!({ "id": 11, "name": "Song, One", "dt": 201000, "ar": [{ "name": "A" }, { "name": "B" }], "al": { "name": "X" } }),
+                json!({ "id": 12, "name": "Two", "dt": 99000, "ar": [{ "name": "C" }], "al": { "name": "<Y>" } }),
+            ],
+            privileges: vec![json!({ "id": 11 }), json!({ "id": 12 })],
+        }
+    }
+
+    #[test]
+    fn test_render_formats() {
+        let snapshot = snapshot();
+
+        let m3u8 = snapshot.render(ExportFormat::M3u8, None);
+        assert!(m3u8.starts_with("#EXTM3U\n#PLAYLIST:Rock & Roll\n"));
+        assert!(m3u8.contains(
+            "#EXTINF:201,A,B - Song, One\nhttps://music.163.com/song/media/outer/url?id=11.mp3\n"
+        ));
+
+        let xspf = snapshot.render(ExportFormat::Xspf, None);
+        assert!(xspf.contains("<title>Rock &amp; Roll</title>"));
+        assert!(xspf.contains("<album>&lt;Y&gt;</album>"));
+        assert!(xspf.contains("<duration>99000</duration>"));
+
+        let csv = snapshot.render(ExportFormat::Csv, None);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("id,title,artists,album,duration,location")
+        );
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("11,\"Song, One\",\"A,B\",X,201,"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&snapshot.render(ExportFormat::Json, None)).unwrap();
+        assert_eq!(json["songs"][1]["al"]["name"], "<Y>");
+        assert!(json.get("localFiles").is_none());
+    }
+
+    #[test]
+    fn test_render_local_files() {
+        let dir = TempDir::new("export");
+        std::fs::write(dir.join("C - Two.flac"), b"").unwrap();
+
+        let m3u8 = snapshot().render(ExportFormat::M3u8, Some(dir.path()));
+        assert!(m3u8.contains("outer/url?id=11.mp3"));
+        assert!(m3u8.contains("file://"));
+        assert!(m3u8.contains("C%20-%20Two.flac"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&snapshot().render(ExportFormat::Json, Some(dir.path()))).unwrap();
+        assert!(json["localFiles"]["12"]
+            .as_str()
+            .unwrap()
+            .ends_with("C - Two.flac"));
+    }
+}
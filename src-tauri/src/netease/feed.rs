@@ -0,0 +1,419 @@
+use chrono::{TimeZone, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::netease::client::{ApiError, ApiResult, NeteaseClient};
+use crate::netease::export::escape_xml;
+use crate::netease::podcast::{self, Episode, Radio};
+
+/// Port the feed server tries first, so subscriptions in podcast apps survive restarts.
+pub const FEED_PORT: u16 = 17_863;
+/// Most recent episodes listed in a feed.
+const FEED_EPISODES: usize = 200;
+/// Largest request head the server reads.
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// Where episode enclosures point to.
+pub enum Enclosures<'a> {
+    /// Stream URLs resolved now; they expire after a while.
+    Direct,
+    /// `{base}/episode/{song_id}`, resolved by the feed server on every request.
+    Proxy(&'a str),
+}
+
+struct Enclosure {
+    url: String,
+    size: u64,
+    mime: String,
+}
+
+/// Builds an RSS 2.0 feed with iTunes tags for a DJ radio.
+pub async fn radio_feed(
+    client: &NeteaseClient,
+    radio_id: u64,
+    enclosures: Enclosures<'_>,
+) -> ApiResult<String> {
+    let body = client
+        .call("/dj/detail", &[("rid", &radio_id.to_string())])
+        .await?;
+    let detail = body
+        .get("data")
+        .or_else(|| body.get("djRadio"))
+        .unwrap_or(&Value::Null);
+    let radio = podcast::radio_of(detail)
+        .ok_or_else(|| ApiError::Malformed(format!("no radio {}", radio_id)))?;
+    let category = detail
+        .get("category")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+
+    let mut episodes = vec![];
+    loop {
+        let (page, more) = podcast::programs(client, radio_id, episodes.len()).await?;
+        let empty = page.is_empty();
+        episodes.extend(page);
+        if !more || empty || episodes.len() >= FEED_EPISODES {
+            break;
+        }
+    }
+    episodes.truncate(FEED_EPISODES);
+
+    let resolved = match enclosures {
+        Enclosures::Direct => stream_urls(client, &episodes).await?,
+        Enclosures::Proxy(base) => episodes
+            .iter()
+            .map(|episode| {
+                let enclosure = Enclosure {
+                    url: format!("{}/episode/{}", base, episode.song_id),
+                    size: 0,
+                    mime: "audio/mpeg".to_string(),
+                };
+                (episode.song_id, enclosure)
+            })
+            .collect(),
+    };
+    Ok(render(&radio, category, &episodes, &resolved))
+}
+
+async fn stream_urls(
+    client: &NeteaseClient,
+    episodes: &[Episode],
+) -> ApiResult<HashMap<u64, Enclosure>> {
+    let mut urls = HashMap::new();
+    for chunk in episodes.chunks(100) {
+        let ids: Vec<String> = chunk.iter().map(|e| e.song_id.to_string()).collect();
+        let body = client.call("/song/url", &[("id", &ids.join(","))]).await?;
+        for entry in body
+            .get("data")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let id = entry.get("id").and_then(Value::as_u64);
+            let url = entry.get("url").and_then(Value::as_str);
+            if let (Some(id), Some(url)) = (id, url) {
+                let kind = entry.get("type").and_then(Value::as_str).unwrap_or("mp3");
+                let enclosure = Enclosure {
+                    url: url.to_string(),
+                    size: entry
+                        .get("size")
+                        .and_then(Value::as_u64)
+                        .unwrap_or_default(),
+                    mime: audio_mime(kind).to_string(),
+                };
+                urls.insert(id, enclosure);
+            }
+        }
+    }
+    Ok(urls)
+}
+
+fn audio_mime(kind: &str) -> &'static str {
+    match kind.to_ascii_lowercase().as_str() {
+        "flac" => "audio/flac",
+        "m4a" | "aac" => "audio/mp4",
+        _ => "audio/mpeg",
+    }
+}
+
+/// `HH:MM:SS`, as `itunes:duration` expects.
+fn duration(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn render(
+    radio: &Radio,
+    category: &str,
+    episodes: &[Episode],
+    enclosures: &HashMap<u64, Enclosure>,
+) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(
+        "<rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\">\n",
+    );
+    out.push_str("<channel>\n");
+    out.push_str(&format!("  <title>{}</title>\n", escape_xml(&radio.name)));
+    out.push_str(&format!(
+        "  <link>https://music.163.com/#/djradio?id={}</link>\n",
+        radio.id
+    ));
+    out.push_str(&format!(
+        "  <description>{}</description>\n",
+        escape_xml(&radio.description)
+    ));
+    out.push_str("  <language>zh-cn</language>\n");
+    out.push_str("  <generator>OneListen</generator>\n");
+    if !radio.cover_url.is_empty() {
+        let cover = escape_xml(&radio.cover_url);
+        out.push_str(&format!(
+            "  <image>\n    <url>{}</url>\n    <title>{}</title>\n    <link>https://music.163.com/#/djradio?id={}</link>\n  </image>\n",
+            cover,
+            escape_xml(&radio.name),
+            radio.id
+        ));
+        out.push_str(&format!("  <itunes:image href=\"{}\"/>\n", cover));
+    }
+    out.push_str(&format!(
+        "  <itunes:author>{}</itunes:author>\n",
+        escape_xml(&radio.dj.name)
+    ));
+    out.push_str(&format!(
+        "  <itunes:summary>{}</itunes:summary>\n",
+        escape_xml(&radio.description)
+    ));
+    if !category.is_empty() {
+        out.push_str(&format!(
+            "  <itunes:category text=\"{}\"/>\n",
+            escape_xml(category)
+        ));
+    }
+    out.push_str("  <itunes:explicit>false</itunes:explicit>\n");
+
+    for episode in episodes {
+        out.push_str("  <item>\n");
+        out.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape_xml(&episode.name)
+        ));
+        out.push_str(&format!(
+            "    <description>{}</description>\n",
+            escape_xml(&episode.description)
+        ));
+        out.push_str(&format!(
+            "    <link>https://music.163.com/#/program?id={}</link>\n",
+            episode.id
+        ));
+        out.push_str(&format!(
+            "    <guid isPermaLink=\"false\">netease-program-{}</guid>\n",
+            episode.id
+        ));
+        if let Some(published) = Utc.timestamp_millis_opt(episode.create_time).single() {
+            out.push_str(&format!(
+                "    <pubDate>{}</pubDate>\n",
+                published.to_rfc2822()
+            ));
+        }
+        if let Some(enclosure) = enclosures.get(&episode.song_id) {
+            out.push_str(&format!(
+                "    <enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>\n",
+                escape_xml(&enclosure.url),
+                enclosure.size,
+                enclosure.mime
+            ));
+        }
+        out.push_str(&format!(
+            "    <itunes:duration>{}</itunes:duration>\n",
+            duration(episode.duration_ms)
+        ));
+        if episode.serial_num > 0 {
+            out.push_str(&format!(
+                "    <itunes:episode>{}</itunes:episode>\n",
+                episode.serial_num
+            ));
+        }
+        if !episode.cover_url.is_empty() {
+            out.push_str(&format!(
+                "    <itunes:image href=\"{}\"/>\n",
+                escape_xml(&episode.cover_url)
+            ));
+        }
+        out.push_str("  </item>\n");
+    }
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+/// Serves radio feeds on localhost:
+///
+/// * `GET /radio/{id}.xml` answers the feed, with enclosures pointing back at the server;
+/// * `GET /episode/{song_id}` redirects to a freshly resolved stream URL.
+pub struct FeedServer {
+    addr: SocketAddr,
+}
+
+impl FeedServer {
+    /// Listens on `port`, or on any free port when it is taken. Feeds of radios that need a
+    /// login are fetched with `client`'s cookie.
+    pub async fn start(port: u16, client: NeteaseClient) -> io::Result<Self> {
+        let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+            Ok(listener) => listener,
+            Err(_) => TcpListener::bind(("127.0.0.1", 0)).await?,
+        };
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let _ = serve(stream, addr, client).await;
+                });
+            }
+        });
+        Ok(FeedServer { addr })
+    }
+
+    pub fn url(&self, radio_id: u64) -> String {
+        format!("http://{}/radio/{}.xml", self.addr, radio_id)
+    }
+}
+
+async fn serve(mut stream: TcpStream, addr: SocketAddr, client: NeteaseClient) -> io::Result<()> {
+    let mut head = vec![];
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line
+        .next()
+        .unwrap_or_default()
+        .split('?')
+        .next()
+        .unwrap_or_default();
+
+    let response = if method != "GET" && method != "HEAD" {
+        respond(405, "Method Not Allowed", &[], "")
+    } else if let Some(id) = path
+        .strip_prefix("/radio/")
+        .and_then(|rest| rest.strip_suffix(".xml"))
+        .and_then(|id| id.parse().ok())
+    {
+        let base = format!("http://{}", addr);
+        match radio_feed(&client, id, Enclosures::Proxy(&base)).await {
+            Ok(feed) => respond(
+                200,
+                "OK",
+                &[("Content-Type", "application/rss+xml; charset=utf-8")],
+                &feed,
+            ),
+            Err(err) => respond(502, "Bad Gateway", &[], &err.to_string()),
+        }
+    } else if let Some(id) = path
+        .strip_prefix("/episode/")
+        .and_then(|id| id.parse::<u64>().ok())
+    {
+        match client.call("/song/url", &[("id", &id.to_string())]).await {
+            Ok(body) => match body.pointer("/data/0/url").and_then(Value::as_str) {
+                Some(url) => respond(302, "Found", &[("Location", url)], ""),
+                None => respond(404, "Not Found", &[], "no stream"),
+            },
+            Err(err) => respond(502, "Bad Gateway", &[], &err.to_string()),
+        }
+    } else {
+        respond(404, "Not Found", &[], "")
+    };
+    let response = if method == "HEAD" {
+        let end = response.find("\r\n\r\n").map_or(response.len(), |i| i + 4);
+        response[..end].to_string()
+    } else {
+        response
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn respond(status: u16, reason: &str, headers: &[(&str, &str)], body: &str) -> String {
+    let mut out = format!("HTTP/1.1 {} {}\r\n", status, reason);
+    for (name, value) in headers {
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !headers.iter().any(|(name, _)| *name == "Content-Type") {
+        out.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+    }
+    out.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FeedServer;
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn get(addr: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_feed_server() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/dj/detail", |_| {
+                    json!({ "code": 200, "data": {
+                        "id": 7, "name": "Tom & Jerry", "desc": "<b>talk</b>", "picUrl": "http://p/c.jpg?a=1&b=2",
+                        "category": "Talk", "dj": { "userId": 3, "nickname": "host" },
+                    } })
+                })
+                .on("/dj/program", |_| {
+                    json!({ "code": 200, "more": false, "programs": [
+                        { "id": 21, "name": "Second", "duration": 3_725_000, "createTime": 1_700_000_000_000u64, "serialNum": 2, "mainSong": { "id": 121 } },
+                        { "id": 20, "name": "First", "duration": 59_000, "createTime": 1_690_000_000_000u64, "serialNum": 1, "mainSong": { "id": 120 } },
+                    ] })
+                })
+                .on("/song/url", |params| {
+                    json!({ "code": 200, "data": [{ "id": param(params, "id").parse::<u64>().unwrap(), "url": "http://cdn/a.mp3" }] })
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        // port 0 keeps parallel test runs apart
+        let server = FeedServer::start(0, client).await.unwrap();
+        let url = server.url(7);
+        let addr = url
+            .trim_start_matches("http://")
+            .split('/')
+            .next()
+            .unwrap()
+            .to_string();
+
+        let feed = get(&addr, "/radio/7.xml").await;
+        assert!(feed.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(feed.contains("application/rss+xml"));
+        assert!(feed.contains("<title>Tom &amp; Jerry</title>"));
+        assert!(feed.contains("<description>&lt;b&gt;talk&lt;/b&gt;</description>"));
+        assert!(feed.contains("<itunes:image href=\"http://p/c.jpg?a=1&amp;b=2\"/>"));
+        assert!(feed.contains(&format!(
+            "<enclosure url=\"http://{}/episode/121\" length=\"0\" type=\"audio/mpeg\"/>",
+            addr
+        )));
+        assert!(feed.contains("<itunes:duration>01:02:05</itunes:duration>"));
+        assert!(feed.contains("<pubDate>Tue, 14 Nov 2023 22:13:20 +0000</pubDate>"));
+        assert!(feed.find("Second").unwrap() < feed.find("First").unwrap());
+
+        let episode = get(&addr, "/episode/120").await;
+        assert!(episode.starts_with("HTTP/1.1 302 Found\r\nLocation: http://cdn/a.mp3\r\n"));
+        assert!(get(&addr, "/nothing").await.starts_with("HTTP/1.1 404"));
+        // only numeric ids reach `/song/url`
+        assert!(get(&addr, "/episode/1%22%5D%2C%22x")
+            .await
+            .starts_with("HTTP/1.1 404"));
+        assert_eq!(transport.calls_to("/song/url").len(), 1);
+    }
+}
@@ -0,0 +1,524 @@
+use futures::stream::{self, StreamExt};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::path::Path;
+
+use crate::netease::client::{ApiResult, NeteaseClient};
+use crate::netease::playlist::{EditReport, PlaylistEditor};
+
+lazy_static! {
+    static ref BRACKETS: Regex = Regex::new(r"[(\[（【][^)\]）】]*[)\]）】]").unwrap();
+    static ref FEATURING: Regex = Regex::new(r"(?i)\s+(feat\.?|ft\.?|featuring)\s+.*$").unwrap();
+    static ref ARTIST_SEPARATORS: Regex =
+        Regex::new(r"(?i)\s*(,|&|/|、|;|\s+feat\.?\s+|\s+ft\.?\s+|\s+x\s+)\s*").unwrap();
+}
+
+const SEARCH_LIMIT: &str = "10";
+pub(crate) const SEARCH_CONCURRENCY: usize = 4;
+/// Scores at or above this are accepted without review.
+const MATCH_THRESHOLD: f64 = 0.85;
+/// Scores at or above this are kept as candidates the user should confirm.
+const REVIEW_THRESHOLD: f64 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// One `Artist - Title` per line.
+    Text,
+    Csv,
+    M3u,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ImportEntry {
+    pub line: usize,
+    pub raw: String,
+    pub title: String,
+    pub artist: String,
+    /// Seconds, when the source knows it.
+    pub duration: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Candidate {
+    pub id: u64,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub duration: Option<u64>,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchStatus {
+    Matched,
+    Review,
+    Unmatched,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryMatch {
+    pub entry: ImportEntry,
+    pub status: MatchStatus,
+    pub best: Option<Candidate>,
+    /// Why the search for this entry failed; it is then left unmatched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub playlist_id: String,
+    pub matches: Vec<EntryMatch>,
+    pub unmatched: Vec<ImportEntry>,
+    pub added: EditReport,
+}
+
+pub fn parse_entries(content: &str, format: ImportFormat) -> Vec<ImportEntry> {
+    match format {
+        ImportFormat::Text => parse_text(content),
+        ImportFormat::Csv => parse_csv(content),
+        ImportFormat::M3u => parse_m3u(content),
+    }
+}
+
+fn parse_text(content: &str) -> Vec<ImportEntry> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| entry_from_label(index + 1, line, line, None))
+        .collect()
+}
+
+/// Builds an entry from `Artist - Title`, or a bare title when there is no separator.
+pub(crate) fn entry_from_label(
+    line: usize,
+    raw: &str,
+    label: &str,
+    duration: Option<u64>,
+) -> ImportEntry {
+    let (artist, title) = match label.split_once(" - ") {
+        Some((artist, title)) => (artist.trim(), title.trim()),
+        None => ("", label.trim()),
+    };
+    ImportEntry {
+        line,
+        raw: raw.trim().to_string(),
+        title: title.to_string(),
+        artist: artist.to_string(),
+        duration,
+    }
+}
+
+/// Reads exports of other services by looking for title/artist/duration style headers.
+fn parse_csv(content: &str) -> Vec<ImportEntry> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers: Vec<String> = match reader.headers() {
+        Ok(headers) => headers.iter().map(|h| h.trim().to_lowercase()).collect(),
+        Err(_) => return vec![],
+    };
+    let column = |names: &[&str]| headers.iter().position(|h| names.contains(&h.as_str()));
+    let title = column(&[
+        "title",
+        "name",
+        "track",
+        "track name",
+        "song",
+        "歌曲",
+        "歌名",
+    ]);
+    let artist = column(&["artist", "artists", "artist name", "artist name(s)", "歌手"]);
+    let duration = column(&["duration", "length", "duration (ms)", "时长"]);
+    let duration_in_ms = duration.map_or(false, |index| headers[index].contains("ms"));
+
+    reader
+        .records()
+        .filter_map(Result::ok)
+        .filter_map(|record| {
+            let line = record
+                .position()
+                .map(|p| p.line() as usize)
+                .unwrap_or_default();
+            let raw = record.iter().collect::<Vec<&str>>().join(",");
+            let field = |index: Option<usize>| index.and_then(|i| record.get(i)).unwrap_or("");
+            let seconds = parse_duration(field(duration), duration_in_ms);
+            let entry = match title {
+                Some(_) => ImportEntry {
+                    line,
+                    raw: raw.clone(),
+                    title: field(title).trim().to_string(),
+                    artist: field(artist).trim().to_string(),
+                    duration: seconds,
+                },
+                // without a known header the first column is read like a text line
+                None => entry_from_label(line, &raw, field(Some(0)), seconds),
+            };
+            Some(entry).filter(|entry| !entry.title.is_empty())
+        })
+        .collect()
+}
+
+fn parse_m3u(content: &str) -> Vec<ImportEntry> {
+    let mut entries = vec![];
+    let mut pending: Option<ImportEntry> = None;
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (seconds, label) = info.split_once(',').unwrap_or(("", info));
+            let seconds = seconds.trim().parse::<i64>().ok().filter(|s| *s > 0);
+            pending = Some(entry_from_label(
+                index + 1,
+                line,
+                label,
+                seconds.map(|s| s as u64),
+            ));
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let entry = pending.take().unwrap_or_else(|| {
+                let stem = Path::new(line)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                entry_from_label(index + 1, line, &stem, None)
+            });
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+fn parse_duration(text: &str, in_ms: bool) -> Option<u64> {
+    let text = text.trim();
+    if let Some((minutes, seconds)) = text.split_once(':') {
+        return Some(minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?);
+    }
+    let value = text.parse::<f64>().ok()?;
+    // plain numbers above ten thousand cannot be seconds of a song
+    if in_ms || value > 10_000.0 {
+        Some((value / 1000.0).round() as u64)
+    } else {
+        Some(value.round() as u64)
+    }
+}
+
+/// Lowercases and drops bracketed suffixes, featuring credits and punctuation.
+pub(crate) fn normalize(text: &str) -> String {
+    let text = BRACKETS.replace_all(text, " ");
+    let text = FEATURING.replace_all(&text, "");
+    text.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Normalized edit distance similarity in `0.0..=1.0`.
+pub(crate) fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    if a == b {
+        return 1.0;
+    }
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+    1.0 - previous[b.len()] as f64 / a.len().max(b.len()) as f64
+}
+
+fn artist_similarity(wanted: &str, artists: &[String]) -> f64 {
+    let wanted: Vec<&str> = ARTIST_SEPARATORS
+        .split(wanted)
+        .filter(|name| !name.trim().is_empty())
+        .collect();
+    if wanted.is_empty() || artists.is_empty() {
+        return 0.0;
+    }
+    wanted
+        .iter()
+        .map(|name| {
+            artists
+                .iter()
+                .map(|artist| similarity(name, artist))
+                .fold(0.0, f64::max)
+        })
+        .sum::<f64>()
+        / wanted.len() as f64
+}
+
+/// Weighs title, artist and duration similarity; unknown parts are left out of the weighting.
+pub(crate) fn score(entry: &ImportEntry, candidate: &Candidate) -> f64 {
+    let mut parts = vec![(similarity(&entry.title, &candidate.title), 0.6)];
+    if !entry.artist.is_empty() {
+        parts.push((artist_similarity(&entry.artist, &candidate.artists), 0.3));
+    }
+    if let (Some(wanted), Some(actual)) = (entry.duration, candidate.duration) {
+        // within three seconds counts as equal, thirty seconds apart as unrelated
+        let diff = wanted.abs_diff(actual).saturating_sub(3) as f64;
+        parts.push(((1.0 - diff / 27.0).max(0.0), 0.1));
+    }
+    let weight: f64 = parts.iter().map(|(_, weight)| weight).sum();
+    parts
+        .iter()
+        .map(|(value, weight)| value * weight)
+        .sum::<f64>()
+        / weight
+}
+
+fn candidates_of(body: &Value) -> Vec<Candidate> {
+    let songs = body.pointer("/result/songs").and_then(Value::as_array);
+    songs
+        .into_iter()
+        .flatten()
+        .filter_map(|song| {
+            let artists = song
+                .get("artists")
+                .or_else(|| song.get("ar"))
+                .and_then(Value::as_array)
+                .map(|artists| {
+                    artists
+                        .iter()
+                        .filter_map(|artist| artist.get("name").and_then(Value::as_str))
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(Candidate {
+                id: song.get("id").and_then(Value::as_u64)?,
+                title: song.get("name").and_then(Value::as_str)?.to_string(),
+                artists,
+                duration: song
+                    .get("duration")
+                    .or_else(|| song.get("dt"))
+                    .and_then(Value::as_u64)
+                    .map(|ms| ms / 1000),
+                score: 0.0,
+            })
+        })
+        .collect()
+}
+
+/// Searches for `entry` and returns the candidates, best first.
+pub(crate) async fn ranked_candidates(
+    client: &NeteaseClient,
+    entry: &ImportEntry,
+) -> ApiResult<Vec<Candidate>> {
+    let keywords = format!("{} {}", entry.title, entry.artist);
+    let body = client
+        .call(
+            "/search",
+            &[
+                ("keywords", keywords.trim()),
+                ("type", "1"),
+                ("limit", SEARCH_LIMIT),
+            ],
+        )
+        .await?;
+
+    let mut candidates: Vec<Candidate> = candidates_of(&body)
+        .into_iter()
+        .map(|mut candidate| {
+            candidate.score = score(entry, &candidate);
+            candidate
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+    Ok(candidates)
+}
+
+pub(crate) fn status_of(best: Option<&Candidate>) -> MatchStatus {
+    match best.map(|candidate| candidate.score) {
+        Some(score) if score >= MATCH_THRESHOLD => MatchStatus::Matched,
+        Some(score) if score >= REVIEW_THRESHOLD => MatchStatus::Review,
+        _ => MatchStatus::Unmatched,
+    }
+}
+
+async fn match_entry(client: &NeteaseClient, entry: ImportEntry) -> EntryMatch {
+    let (best, error) = match ranked_candidates(client, &entry).await {
+        Ok(candidates) => (candidates.into_iter().next(), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+    let status = status_of(best.as_ref());
+    EntryMatch {
+        entry,
+        status,
+        best: best.filter(|_| status != MatchStatus::Unmatched),
+        error,
+    }
+}
+
+/// Searches every entry and scores the best candidate. A failed search leaves its entry
+/// unmatched rather than failing the whole list.
+pub async fn match_entries(client: &NeteaseClient, entries: Vec<ImportEntry>) -> Vec<EntryMatch> {
+    stream::iter(entries)
+        .map(|entry| match_entry(client, entry))
+        .buffered(SEARCH_CONCURRENCY)
+        .collect()
+        .await
+}
+
+/// Creates the playlist and adds the matched songs in the order of the source.
+pub async fn create_from_ids(
+    client: &NeteaseClient,
+    name: &str,
+    privacy: &str,
+    ids: &[u64],
+) -> ApiResult<EditReport> {
+    let editor = PlaylistEditor::new(client);
+    let pid = editor.create(name, privacy).await?;
+    let report = editor.add(&pid, ids).await?;
+    editor.reorder(&pid, ids).await?;
+    Ok(report)
+}
+
+/// Matches `content` and creates a playlist from the confident matches, plus the uncertain
+/// ones when `include_review` is set.
+pub async fn import_playlist(
+    client: &NeteaseClient,
+    name: &str,
+    privacy: &str,
+    content: &str,
+    format: ImportFormat,
+    include_review: bool,
+) -> ApiResult<ImportReport> {
+    let matches = match_entries(client, parse_entries(content, format)).await;
+    let ids: Vec<u64> = matches
+        .iter()
+        .filter(|m| {
+            m.status == MatchStatus::Matched || (include_review && m.status == MatchStatus::Review)
+        })
+        .filter_map(|m| m.best.as_ref().map(|candidate| candidate.id))
+        .collect();
+    let added = create_from_ids(client, name, privacy, &ids).await?;
+    let unmatched = matches
+        .iter()
+        .filter(|m| m.status == MatchStatus::Unmatched)
+        .map(|m| m.entry.clone())
+        .collect();
+
+    Ok(ImportReport {
+        playlist_id: added.playlist_id.clone(),
+        matches,
+        unmatched,
+        added,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_playlist, parse_entries, score, Candidate, ImportEntry, ImportFormat};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_entries() {
+        let text = parse_entries(
+            "Queen - Bohemian Rhapsody\n\n# skipped\nYesterday\n",
+            ImportFormat::Text,
+        );
+        assert_eq!(text.len(), 2);
+        assert_eq!(
+            (text[0].artist.as_str(), text[0].title.as_str()),
+            ("Queen", "Bohemian Rhapsody")
+        );
+        assert_eq!((text[1].line, text[1].artist.as_str()), (4, ""));
+
+        let csv = "Track Name,Artist Name(s),Duration (ms)\n\"Hello, World\",Adele,295000\n";
+        let csv = parse_entries(csv, ImportFormat::Csv);
+        assert_eq!(csv[0].title, "Hello, World");
+        assert_eq!(csv[0].duration, Some(295));
+
+        let m3u = "#EXTM3U\n#EXTINF:201,A - One\n/music/one.mp3\n/music/B - Two.flac\n";
+        let m3u = parse_entries(m3u, ImportFormat::M3u);
+        assert_eq!(m3u.len(), 2);
+        assert_eq!((m3u[0].title.as_str(), m3u[0].duration), ("One", Some(201)));
+        assert_eq!(
+            (m3u[1].artist.as_str(), m3u[1].title.as_str()),
+            ("B", "Two")
+        );
+    }
+
+    #[test]
+    fn test_score() {
+        let entry = ImportEntry {
+            title: "Bohemian Rhapsody (Remastered 2011)".to_string(),
+            artist: "Queen".to_string(),
+            duration: Some(355),
+            ..Default::default()
+        };
+        let candidate = |title: &str, artist: &str, duration| Candidate {
+            id: 1,
+            title: title.to_string(),
+            artists: vec![artist.to_string()],
+            duration: Some(duration),
+            score: 0.0,
+        };
+        assert!(score(&entry, &candidate("Bohemian Rhapsody", "Queen", 354)) > 0.95);
+        assert!(score(&entry, &candidate("Bohemian Rhapsody", "Panic", 240)) < 0.85);
+        assert!(score(&entry, &candidate("Radio Ga Ga", "Queen", 343)) < 0.6);
+    }
+
+    #[tokio::test]
+    async fn test_import_playlist() {
+        let transport = MockTransport::default()
+            .on("/search", |params| {
+                let songs = match param(params, "keywords") {
+                    "Down D" => return json!({ "code": -1, "message": "search failed" }),
+                    "One A" => json!([{ "id": 1, "name": "One", "artists": [{ "name": "A" }], "duration": 200000 }]),
+                    "Twoo B" => json!([{ "id": 2, "name": "Two", "artists": [{ "name": "B" }] }]),
+                    _ => json!([{ "id": 3, "name": "Something else", "artists": [{ "name": "Z" }] }]),
+                };
+                json!({ "code": 200, "result": { "songs": songs } })
+            })
+            .on("/playlist/create", |_| json!({ "code": 200, "id": 5 }))
+            .on("/playlist/detail", |_| json!({ "code": 200, "playlist": { "trackIds": [] } }))
+            .on("/song/detail", |_| json!({ "code": 200, "songs": [], "privileges": [] }))
+            .on("/playlist/tracks", |_| json!({ "code": 200 }))
+            .on("/song/order/update", |_| json!({ "code": 200 }));
+        let transport = Arc::new(transport);
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+
+        let content = "A - One\nB - Twoo\nC - Missing\nD - Down\n";
+        let report = import_playlist(&client, "mix", "0", content, ImportFormat::Text, false)
+            .await
+            .unwrap();
+        assert_eq!(report.playlist_id, "5");
+        assert_eq!(report.unmatched.len(), 2);
+        assert_eq!(report.unmatched[0].raw, "C - Missing");
+        // a failed search only costs its own entry
+        assert_eq!(report.unmatched[1].raw, "D - Down");
+        assert!(report.matches[3]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("search failed"));
+        // the misspelled title only reaches review and is left out
+        assert_eq!(
+            param(&transport.calls_to("/playlist/tracks")[0], "tracks"),
+            "1"
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::netease::client::{ApiError, ApiOutcome, ApiResult, NeteaseClient};
+use crate::netease::playlist::song_details;
+use crate::store::JsonStore;
+
+/// A like or unlike made while the request could not be delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingLike {
+    pub id: u64,
+    pub like: bool,
+}
+
+/// What is persisted between runs: the server list as of `check_point` plus queued toggles.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LikesState {
+    pub uid: u64,
+    pub check_point: u64,
+    pub ids: Vec<u64>,
+    pub songs: HashMap<u64, Value>,
+    pub pending: Vec<PendingLike>,
+}
+
+impl LikesState {
+    /// Server ids with the queued toggles applied on top.
+    fn effective(&self) -> HashSet<u64> {
+        let mut liked: HashSet<u64> = self.ids.iter().copied().collect();
+        for pending in &self.pending {
+            if pending.like {
+                liked.insert(pending.id);
+            } else {
+                liked.remove(&pending.id);
+            }
+        }
+        liked
+    }
+
+    fn apply(&mut self, pending: PendingLike) {
+        self.ids.retain(|id| *id != pending.id);
+        if pending.like {
+            // the like list is newest first
+            self.ids.insert(0, pending.id);
+        } else {
+            self.songs.remove(&pending.id);
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LikesSync {
+    pub added: Vec<u64>,
+    pub removed: Vec<u64>,
+    /// Queued toggles that still could not be delivered.
+    pub pending: usize,
+}
+
+/// Local copy of the user's liked songs.
+///
+/// Lookups are answered from memory; toggles are applied locally right away and delivered
+/// through a queue that survives restarts until Netease accepts them.
+pub struct LikeMirror {
+    store: Mutex<JsonStore<LikesState>>,
+    liked: Mutex<HashSet<u64>>,
+    replaying: AtomicBool,
+}
+
+impl LikeMirror {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let store: JsonStore<LikesState> = JsonStore::open(path);
+        let liked = store.get().effective();
+        LikeMirror {
+            store: Mutex::new(store),
+            liked: Mutex::new(liked),
+            replaying: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_liked(&self, id: u64) -> bool {
+        self.liked.lock().unwrap().contains(&id)
+    }
+
+    pub fn ids(&self) -> Vec<u64> {
+        let store = self.store.lock().unwrap();
+        let state = store.get();
+        let mut ids = state.ids.clone();
+        for pending in &state.pending {
+            ids.retain(|id| *id != pending.id);
+            if pending.like {
+                ids.insert(0, pending.id);
+            }
+        }
+        ids
+    }
+
+    /// Cached song details in like order; songs liked offline show up after the next sync.
+    pub fn songs(&self) -> Vec<Value> {
+        let ids = self.ids();
+        let store = self.store.lock().unwrap();
+        ids.iter()
+            .filter_map(|id| store.get().songs.get(id).cloned())
+            .collect()
+    }
+
+    pub fn pending(&self) -> Vec<PendingLike> {
+        self.store.lock().unwrap().get().pending.clone()
+    }
+
+    fn update<R>(&self, f: impl FnOnce(&mut LikesState) -> R) -> ApiResult<R> {
+        let mut store = self.store.lock().unwrap();
+        let result = store.update(f)?;
+        *self.liked.lock().unwrap() = store.get().effective();
+        Ok(result)
+    }
+
+    /// Likes or unlikes `id` locally and tries to deliver it; returns whether it reached Netease.
+    pub async fn set_liked(&self, client: &NeteaseClient, id: u64, like: bool) -> ApiResult<bool> {
+        self.update(|state| {
+            // only the latest intent per song is worth replaying
+            state.pending.retain(|pending| pending.id != id);
+            state.pending.push(PendingLike { id, like });
+        })?;
+        Ok(self.replay(client).await? == 0)
+    }
+
+    /// Delivers queued toggles in order and returns how many are left.
+    ///
+    /// Stops at the first transport failure or expired login, since the rest would fail the
+    /// same way; a toggle Netease rejects is dropped and reported, which also rolls the local
+    /// state back.
+    pub async fn replay(&self, client: &NeteaseClient) -> ApiResult<usize> {
+        if self.replaying.swap(true, Ordering::SeqCst) {
+            return Ok(self.pending().len());
+        }
+        let result = self.deliver(client).await;
+        self.replaying.store(false, Ordering::SeqCst);
+        result
+    }
+
+    async fn deliver(&self, client: &NeteaseClient) -> ApiResult<usize> {
+        while let Some(pending) = self.pending().first().copied() {
+            let id = pending.id.to_string();
+            let like = pending.like.to_string();
+            let result = client.call("/like", &[("id", &id), ("like", &like)]).await;
+            if matches!(&result, Err(err) if err.is_transport()
+                || err.outcome() == Some(ApiOutcome::NeedsLogin))
+            {
+                break;
+            }
+            self.update(|state| {
+                // a newer toggle for the same song may have replaced this one meanwhile
+                if state.pending.first() == Some(&pending) {
+                    state.pending.remove(0);
+                }
+                if result.is_ok() {
+                    state.apply(pending);
+                }
+            })?;
+            result?;
+        }
+        Ok(self.pending().len())
+    }
+
+    /// Delivers queued toggles, then refreshes the list if its `checkPoint` moved and fetches
+    /// details only for songs that are not cached yet.
+    pub async fn sync(&self, client: &NeteaseClient, uid: u64) -> ApiResult<LikesSync> {
+        // toggles queued under another account must not be replayed with this one's cookie
+        self.update(|state| {
+            if state.uid != 0 && state.uid != uid {
+                *state = LikesState {
+                    uid,
+                    ..Default::default()
+                };
+            }
+        })?;
+        let pending = self.replay(client).await?;
+        let body = client
+            .call("/likelist", &[("uid", &uid.to_string())])
+            .await?;
+        let check_point = body
+            .get("checkPoint")
+            .and_then(Value::as_u64)
+            .unwrap_or_default();
+        let ids: Vec<u64> = body
+            .get("ids")
+            .and_then(Value::as_array)
+            .ok_or_else(|| ApiError::Malformed("likelist without ids".to_string()))?
+            .iter()
+            .filter_map(Value::as_u64)
+            .collect();
+
+        let (old, missing) = {
+            let store = self.store.lock().unwrap();
+            let state = store.get();
+            if state.uid == uid && check_point != 0 && state.check_point == check_point {
+                return Ok(LikesSync {
+                    pending,
+                    ..Default::default()
+                });
+            }
+            let old: HashSet<u64> = if state.uid == uid {
+                state.ids.iter().copied().collect()
+            } else {
+                HashSet::new()
+            };
+            let missing: Vec<u64> = ids
+                .iter()
+                .copied()
+                .filter(|id| state.uid != uid || !state.songs.contains_key(id))
+                .collect();
+            (old, missing)
+        };
+        let details = song_details(client, &missing).await?;
+
+        let current: HashSet<u64> = ids.iter().copied().collect();
+        let added = ids.iter().copied().filter(|id| !old.contains(id)).collect();
+        let removed = old
+            .iter()
+            .copied()
+            .filter(|id| !current.contains(id))
+            .collect();
+        self.update(|state| {
+            if state.uid != uid {
+                *state = LikesState {
+                    uid,
+                    ..Default::default()
+                };
+            }
+            state.songs.retain(|id, _| current.contains(id));
+            for song in details.songs {
+                if let Some(id) = song.get("id").and_then(Value::as_u64) {
+                    state.songs.insert(id, song);
+                }
+            }
+            state.ids = ids;
+            state.check_point = check_point;
+        })?;
+
+        Ok(LikesSync {
+            added,
+            removed,
+            pending,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LikeMirror;
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use crate::store::TempDir;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_likes_sync_and_queue() {
+        let dir = TempDir::new("likes");
+        let path = dir.join("likes.json");
+        let rejected = Arc::new(AtomicBool::new(false));
+        let flag = rejected.clone();
+        let transport = Arc::new(
+            MockTransport::default()
+                .on(
+                    "/likelist",
+                    |_| json!({ "code": 200, "checkPoint": 7, "ids": [3, 2, 1] }),
+                )
+                .on("/song/detail", |params| {
+                    let songs: Vec<_> = param(params, "ids")
+                        .split(',')
+                        .map(|id| json!({ "id": id.parse::<u64>().unwrap() }))
+                        .collect();
+                    json!({ "code": 200, "songs": songs, "privileges": [] })
+                })
+                .on("/like", move |params| match param(params, "id") {
+                    "5" => json!({ "code": 301, "msg": "not logged in" }),
+                    _ if flag.load(Ordering::SeqCst) => json!({ "code": 400, "msg": "rejected" }),
+                    _ => json!({ "code": 200 }),
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+
+        let mirror = LikeMirror::open(&path);
+        let sync = mirror.sync(&client, 9).await.unwrap();
+        assert_eq!(sync.added, vec![3, 2, 1]);
+        assert!(mirror.is_liked(2) && !mirror.is_liked(4));
+        assert_eq!(mirror.songs().len(), 3);
+
+        // same checkPoint: nothing is fetched again
+        mirror.sync(&client, 9).await.unwrap();
+        assert_eq!(transport.calls_to("/song/detail").len(), 1);
+
+        // offline toggles apply at once and wait in the queue, also across restarts
+        transport.set_offline(true);
+        assert!(!mirror.set_liked(&client, 4, true).await.unwrap());
+        assert!(!mirror.set_liked(&client, 3, false).await.unwrap());
+        assert!(mirror.is_liked(4) && !mirror.is_liked(3));
+        let mirror = LikeMirror::open(&path);
+        assert_eq!(mirror.ids(), vec![4, 2, 1]);
+
+        transport.set_offline(false);
+        assert_eq!(mirror.replay(&client).await.unwrap(), 0);
+        assert_eq!(mirror.ids(), vec![4, 2, 1]);
+        assert!(mirror.pending().is_empty());
+
+        // a rejected toggle is dropped and rolled back
+        rejected.store(true, Ordering::SeqCst);
+        assert!(mirror.set_liked(&client, 1, false).await.is_err());
+        assert!(mirror.is_liked(1));
+        assert!(mirror.pending().is_empty());
+
+        // an expired login keeps the toggle queued, but not across a switch of account
+        assert!(!mirror.set_liked(&client, 5, true).await.unwrap());
+        assert_eq!(mirror.pending().len(), 1);
+        mirror.sync(&client, 10).await.unwrap();
+        assert!(mirror.pending().is_empty());
+        assert_eq!(transport.calls_to("/like").len(), 6);
+    }
+}
@@ -0,0 +1,536 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::history::Named;
+use crate::json::{named, text};
+use crate::netease::client::{escape_json, ApiResult, NeteaseClient};
+
+/// How often the poller asks for unread counts.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Conversations fetched when the unread count moves.
+const POLL_CONVERSATIONS: usize = 30;
+
+/// Something shared inside a message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Attachment {
+    Song {
+        id: u64,
+        name: String,
+        artists: Vec<Named>,
+    },
+    Album {
+        id: u64,
+        name: String,
+        artists: Vec<Named>,
+    },
+    Playlist {
+        id: u64,
+        name: String,
+        creator: Option<Named>,
+    },
+    Mv {
+        id: u64,
+        name: String,
+        artists: Vec<Named>,
+    },
+    /// Video ids are opaque strings.
+    Video { id: String, title: String },
+    /// Kinds this app does not render, e.g. DJ programs; `title` is the server's summary.
+    Other { title: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub id: u64,
+    pub from: u64,
+    pub to: u64,
+    /// Unix time in milliseconds.
+    pub time: i64,
+    pub text: String,
+    pub attachment: Option<Attachment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conversation {
+    pub user: Named,
+    pub avatar_url: String,
+    pub last_text: String,
+    pub last_attachment: Option<Attachment>,
+    /// Unix time in milliseconds.
+    pub last_time: i64,
+    pub unread: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationPage {
+    pub conversations: Vec<Conversation>,
+    pub more: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendReport {
+    /// The messages as stored by Netease, one per recipient that got it.
+    pub messages: Vec<Message>,
+    /// Recipients who blocked the sender and did not get the message.
+    pub blocked: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPage {
+    /// Oldest first.
+    pub messages: Vec<Message>,
+    pub more: bool,
+    /// Pass back as `before` to load the messages preceding this page.
+    pub before: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnreadCounts {
+    pub messages: u64,
+    pub comments: u64,
+    pub notices: u64,
+    pub forwards: u64,
+}
+
+/// What a message shares, sent through the matching `/send/*` route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind", content = "id")]
+pub enum Outgoing {
+    Song(u64),
+    Album(u64),
+    Playlist(u64),
+}
+
+impl Outgoing {
+    fn route(&self) -> (&'static str, u64) {
+        match *self {
+            Outgoing::Song(id) => ("/send/song", id),
+            Outgoing::Album(id) => ("/send/album", id),
+            Outgoing::Playlist(id) => ("/send/playlist", id),
+        }
+    }
+}
+
+/// `artists`, or the single `artist` some resources carry instead.
+fn artists_of(value: &Value) -> Vec<Named> {
+    match value.get("artists").and_then(Value::as_array) {
+        Some(artists) => artists
+            .iter()
+            .filter_map(|artist| named(artist, "id", "name"))
+            .collect(),
+        None => value
+            .get("artist")
+            .and_then(|artist| named(artist, "id", "name"))
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Message bodies are JSON documents sent as strings; the shared resource sits under a key
+/// named after its kind.
+pub(crate) fn content_of(raw: &str) -> (String, Option<Attachment>) {
+    match serde_json::from_str(raw) {
+        Ok(body) => (text(&body, "msg"), attachment_of(&body)),
+        Err(_) => (raw.to_string(), None),
+    }
+}
+
+/// The resource shared in a decoded message or event body.
+pub(crate) fn attachment_of(body: &Value) -> Option<Attachment> {
+    if let Some(song) = body.get("song") {
+        song.get("id")
+            .and_then(Value::as_u64)
+            .map(|id| Attachment::Song {
+                id,
+                name: text(song, "name"),
+                artists: artists_of(song),
+            })
+    } else if let Some(album) = body.get("album") {
+        album
+            .get("id")
+            .and_then(Value::as_u64)
+            .map(|id| Attachment::Album {
+                id,
+                name: text(album, "name"),
+                artists: artists_of(album),
+            })
+    } else if let Some(playlist) = body.get("playlist") {
+        playlist
+            .get("id")
+            .and_then(Value::as_u64)
+            .map(|id| Attachment::Playlist {
+                id,
+                name: text(playlist, "name"),
+                creator: playlist
+                    .get("creator")
+                    .and_then(|creator| named(creator, "userId", "nickname")),
+            })
+    } else if let Some(mv) = body.get("mv") {
+        mv.get("id")
+            .and_then(Value::as_u64)
+            .map(|id| Attachment::Mv {
+                id,
+                name: text(mv, "name"),
+                artists: artists_of(mv),
+            })
+    } else if let Some(video) = body.get("video") {
+        video
+            .get("videoId")
+            .and_then(|id| match id {
+                Value::String(id) => Some(id.clone()),
+                Value::Number(id) => Some(id.to_string()),
+                _ => None,
+            })
+            .map(|id| Attachment::Video {
+                id,
+                title: text(video, "title"),
+            })
+    } else {
+        body.get("title")
+            .and_then(Value::as_str)
+            .filter(|title| !title.is_empty())
+            .map(|title| Attachment::Other {
+                title: title.to_string(),
+            })
+    }
+}
+
+fn message_of(msg: &Value) -> Option<Message> {
+    let (text, attachment) = content_of(msg.get("msg").and_then(Value::as_str).unwrap_or("{}"));
+    Some(Message {
+        id: msg.get("id")?.as_u64()?,
+        from: msg
+            .get("fromUserId")
+            .and_then(Value::as_u64)
+            .or_else(|| msg.pointer("/fromUser/userId").and_then(Value::as_u64))
+            .unwrap_or_default(),
+        to: msg
+            .get("toUserId")
+            .and_then(Value::as_u64)
+            .or_else(|| msg.pointer("/toUser/userId").and_then(Value::as_u64))
+            .unwrap_or_default(),
+        time: msg.get("time").and_then(Value::as_i64).unwrap_or_default(),
+        text,
+        attachment,
+    })
+}
+
+fn conversation_of(entry: &Value) -> Option<Conversation> {
+    let user = entry.get("fromUser")?;
+    let (last_text, last_attachment) =
+        content_of(entry.get("lastMsg").and_then(Value::as_str).unwrap_or("{}"));
+    Some(Conversation {
+        user: named(user, "userId", "nickname")?,
+        avatar_url: text(user, "avatarUrl"),
+        last_text,
+        last_attachment,
+        last_time: entry
+            .get("lastMsgTime")
+            .and_then(Value::as_i64)
+            .unwrap_or_default(),
+        unread: entry
+            .get("newMsgCount")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+    })
+}
+
+/// The conversation list, most recent first.
+pub async fn conversations(
+    client: &NeteaseClient,
+    offset: usize,
+    limit: usize,
+) -> ApiResult<ConversationPage> {
+    let body = client
+        .call(
+            "/msg/private",
+            &[
+                ("offset", &offset.to_string()),
+                ("limit", &limit.to_string()),
+            ],
+        )
+        .await?;
+    Ok(ConversationPage {
+        conversations: body
+            .get("msgs")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(conversation_of)
+            .collect(),
+        more: body.get("more").and_then(Value::as_bool).unwrap_or(false),
+    })
+}
+
+/// Messages exchanged with `user_id` before the `before` cursor, or the latest ones.
+pub async fn history(
+    client: &NeteaseClient,
+    user_id: u64,
+    before: Option<i64>,
+    limit: usize,
+) -> ApiResult<HistoryPage> {
+    let body = client
+        .call(
+            "/msg/private/history",
+            &[
+                ("uid", &user_id.to_string()),
+                ("limit", &limit.to_string()),
+                ("before", &before.unwrap_or(0).to_string()),
+            ],
+        )
+        .await?;
+    let mut messages: Vec<Message> = body
+        .get("msgs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(message_of)
+        .collect();
+    messages.sort_by_key(|message| (message.time, message.id));
+    let more = body.get("more").and_then(Value::as_bool).unwrap_or(false);
+    Ok(HistoryPage {
+        before: messages
+            .first()
+            .map(|message| message.time)
+            .filter(|_| more),
+        messages,
+        more,
+    })
+}
+
+pub async fn unread_counts(client: &NeteaseClient) -> ApiResult<UnreadCounts> {
+    let body = client.call("/pl/count", &[]).await?;
+    let count = |key: &str| body.get(key).and_then(Value::as_u64).unwrap_or_default();
+    Ok(UnreadCounts {
+        messages: count("msg"),
+        comments: count("comment"),
+        notices: count("notice"),
+        forwards: count("forward"),
+    })
+}
+
+/// Sends `text` to every user, sharing `attachment` when given.
+pub async fn send(
+    client: &NeteaseClient,
+    user_ids: &[u64],
+    text: &str,
+    attachment: Option<Outgoing>,
+) -> ApiResult<SendReport> {
+    if user_ids.is_empty() {
+        return Ok(SendReport {
+            messages: vec![],
+            blocked: vec![],
+        });
+    }
+    let ids: Vec<String> = user_ids.iter().map(u64::to_string).collect();
+    let ids = ids.join(",");
+    let text = escape_json(text);
+    let body = match attachment {
+        None => {
+            client
+                .call("/send/text", &[("user_ids", &ids), ("msg", &text)])
+                .await?
+        }
+        Some(outgoing) => {
+            let (route, id) = outgoing.route();
+            // the playlist route predates the others and names its id `playlist`
+            let key = if route == "/send/playlist" {
+                "playlist"
+            } else {
+                "id"
+            };
+            client
+                .call(
+                    route,
+                    &[(key, &id.to_string()), ("user_ids", &ids), ("msg", &text)],
+                )
+                .await?
+        }
+    };
+    let messages = body
+        .get("newMsgs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(message_of)
+        .collect();
+    let blocked = body
+        .get("sendblacklist")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|user| user.get("userId").and_then(Value::as_u64))
+        .collect();
+    Ok(SendReport { messages, blocked })
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageUpdate {
+    pub unread: UnreadCounts,
+    /// Conversations with messages newer than the previous poll.
+    pub conversations: Vec<Conversation>,
+}
+
+/// Called with every change the poller notices.
+pub type Notify = Arc<dyn Fn(&MessageUpdate) + Send + Sync>;
+
+/// Polls unread counts and, when messages arrived, the conversations they belong to.
+///
+/// Nothing is persisted: the first poll after start only sets the baseline.
+#[derive(Default)]
+pub struct MessagePoller {
+    unread: Mutex<Option<UnreadCounts>>,
+    last_seen: Mutex<HashMap<u64, i64>>,
+}
+
+impl MessagePoller {
+    /// Forgets the baseline, so another account does not get the previous one's messages
+    /// reported as new.
+    pub fn reset(&self) {
+        *self.unread.lock().unwrap() = None;
+        self.last_seen.lock().unwrap().clear();
+    }
+
+    /// Returns an update when the unread counts or a conversation changed since the last poll.
+    pub async fn poll(&self, client: &NeteaseClient) -> ApiResult<Option<MessageUpdate>> {
+        let unread = unread_counts(client).await?;
+        let previous = *self.unread.lock().unwrap();
+        let first = previous.is_none();
+        let arrived = previous.map_or(true, |previous| unread.messages != previous.messages);
+
+        let mut changed = vec![];
+        if arrived && (unread.messages > 0 || first) {
+            let page = conversations(client, 0, POLL_CONVERSATIONS).await?;
+            let mut last_seen = self.last_seen.lock().unwrap();
+            for conversation in page.conversations {
+                let newer = last_seen
+                    .get(&conversation.user.id)
+                    .map_or(!first, |&seen| conversation.last_time > seen);
+                last_seen.insert(conversation.user.id, conversation.last_time);
+                if newer {
+                    changed.push(conversation);
+                }
+            }
+        }
+        *self.unread.lock().unwrap() = Some(unread);
+        if previous == Some(unread) && changed.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(MessageUpdate {
+            unread,
+            conversations: changed,
+        }))
+    }
+}
+
+/// Polls every `POLL_INTERVAL` while `client` is logged in.
+pub async fn schedule(poller: Arc<MessagePoller>, client: NeteaseClient, notify: Notify) {
+    loop {
+        if client.logged_in() {
+            if let Ok(Some(update)) = poller.poll(&client).await {
+                notify(&update);
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{history, send, Attachment, MessagePoller, Outgoing};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_messages() {
+        let unread = Arc::new(AtomicU64::new(0));
+        let count = unread.clone();
+        let latest = unread.clone();
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/pl/count", move |_| {
+                    json!({ "code": 200, "msg": count.load(Ordering::SeqCst), "comment": 2 })
+                })
+                .on("/msg/private", move |_| {
+                    let unread = latest.load(Ordering::SeqCst);
+                    json!({ "code": 200, "more": false, "msgs": [
+                        { "fromUser": { "userId": 5, "nickname": "ann" }, "lastMsg": "{\"msg\":\"hi\"}", "lastMsgTime": 20 + unread, "newMsgCount": unread },
+                        { "fromUser": { "userId": 6, "nickname": "bob" }, "lastMsg": "{}", "lastMsgTime": 15 },
+                    ] })
+                })
+                .on("/msg/private/history", |params| {
+                    assert_eq!(param(params, "before"), "0");
+                    json!({ "code": 200, "more": true, "msgs": [
+                        { "id": 2, "fromUserId": 5, "toUserId": 1, "time": 20, "msg": "{\"msg\":\"hi\",\"type\":6}" },
+                        { "id": 1, "fromUserId": 1, "toUserId": 5, "time": 10,
+                          "msg": "{\"msg\":\"listen\",\"type\":1,\"song\":{\"id\":33,\"name\":\"Tune\",\"artists\":[{\"id\":4,\"name\":\"Band\"}]}}" },
+                    ] })
+                })
+                .on("/send/song", |params| {
+                    let blacklist: Vec<_> = param(params, "user_ids")
+                        .split(',')
+                        .filter(|id| *id == "6")
+                        .map(|id| json!({ "userId": id.parse::<u64>().unwrap() }))
+                        .collect();
+                    json!({ "code": 200, "sendblacklist": blacklist, "newMsgs": [
+                        { "id": 3, "fromUserId": 1, "toUserId": 5, "time": 30,
+                          "msg": format!("{{\"msg\":\"{}\",\"song\":{{\"id\":{}}}}}", param(params, "msg"), param(params, "id")) },
+                    ] })
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+
+        let page = history(&client, 5, None, 30).await.unwrap();
+        let ids: Vec<u64> = page.messages.iter().map(|m| m.id).collect();
+        assert_eq!((ids, page.before), (vec![1, 2], Some(10)));
+        assert_eq!(
+            page.messages[0].attachment,
+            Some(Attachment::Song {
+                id: 33,
+                name: "Tune".to_string(),
+                artists: vec![crate::history::Named {
+                    id: 4,
+                    name: "Band".to_string()
+                }],
+            })
+        );
+
+        let sent = send(&client, &[5, 6], "say \"hi\"", Some(Outgoing::Song(33)))
+            .await
+            .unwrap();
+        assert_eq!(sent.messages[0].text, "say \"hi\"");
+        // a blocked recipient is reported, not an error
+        assert_eq!(sent.blocked, vec![6]);
+
+        let poller = MessagePoller::default();
+        // the first poll is the baseline
+        let first = poller.poll(&client).await.unwrap().unwrap();
+        assert!(first.conversations.is_empty());
+        assert_eq!(first.unread.comments, 2);
+        assert!(poller.poll(&client).await.unwrap().is_none());
+        unread.store(1, Ordering::SeqCst);
+        let update = poller.poll(&client).await.unwrap().unwrap();
+        assert_eq!(update.unread.messages, 1);
+        let moved: Vec<&str> = update
+            .conversations
+            .iter()
+            .map(|c| c.user.name.as_str())
+            .collect();
+        assert_eq!(moved, vec!["ann"]);
+        assert_eq!(transport.calls_to("/msg/private").len(), 2);
+    }
+}
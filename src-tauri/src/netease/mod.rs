@@ -1,4 +1,22 @@
+pub mod artist;
+pub mod checkin;
+pub mod client;
+pub mod cloud;
+pub mod comment;
 mod crypto;
+pub mod event;
+pub mod export;
+pub mod feed;
+pub mod import;
+pub mod likes;
 mod macros;
+pub mod message;
 pub mod music_api;
+pub mod playlist;
+pub mod podcast;
+pub mod recommend;
 mod request;
+pub mod social;
+pub mod toplist;
+pub mod video;
+pub mod weblog;
@@ -201,6 +201,24 @@ pub fn index_artist_mv(options: Options) -> FormatParams {
     request_handler(url, "weapi", query_params, &cookies)
 }
 
+// #[get("/artist/songs")]
+pub fn index_artist_songs(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/v1/artist/songs";
+    let query_string = QueryParams::from(options.params);
+
+    let query_params = json_object!({
+        "id": query_string.value("id").unwrap(),
+        "private_cloud": "true",
+        "work_type": "1",
+        "order": query_string.value("order").unwrap_or("hot"),
+        "offset": query_string.value("offset").unwrap_or("0"),
+        "limit": query_string.value("limit").unwrap_or("100")
+    });
+
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
 // #[get("/artist/sub")]
 pub fn index_artist_sub(options: Options) -> FormatParams {
     let query_string = QueryParams::from(options.params);
@@ -230,7 +248,7 @@ pub fn index_artist_sublist(options: Options) -> FormatParams {
     let query_string = QueryParams::from(options.params);
 
     let query_params = json_object!({
-        "limit": query_string.value("cat").unwrap_or("25"),
+        "limit": query_string.value("limit").unwrap_or("25"),
         "offset": query_string.value("offset").unwrap_or("0"),
         "total": "true"
     });
@@ -302,6 +320,103 @@ fn comment_common(url: &str, query_string: QueryParams<'_>, cookie: &str) -> For
     request_handler(url, "weapi", query_params, &cookies)
 }
 
+/// `threadId` of the resource named by `type` and `id`; events pass their `threadId` directly.
+/// `None` when `type` names no resource or the id is missing.
+fn comment_thread_id(query_string: &QueryParams<'_>) -> Option<String> {
+    let _type: &str = query_string
+        .value("type")
+        .unwrap_or("0")
+        .parse::<usize>()
+        .ok()
+        .and_then(|kind| {
+            [
+                "R_SO_4_", "R_MV_5_", "A_PL_0_", "R_AL_3_", "A_DJ_1_", "R_VI_62_", "A_EV_2_",
+            ]
+            .get(kind)
+            .copied()
+        })?;
+    if _type == "A_EV_2_" {
+        query_string.value("threadId").map(str::to_string)
+    } else {
+        query_string.value("id").map(|id| _type.to_owned() + id)
+    }
+}
+
+/// What `dispatch` answers when it cannot build a request; the client reports it as an error
+/// instead of sending anything.
+fn no_request() -> FormatParams {
+    FormatParams {
+        url: "".to_string(),
+        headers: vec![],
+        body: "".to_string(),
+        method: "POST".to_string(),
+    }
+}
+
+/// `sortType` and `cursor` as `v2/resource/comments` expects them: recommended is 99 and
+/// pages by position, hot pages by `normalHot#{position}`, and time continues from the
+/// `cursor` of the previous page.
+fn comment_page(query_string: &QueryParams<'_>) -> (String, String) {
+    let page_no = query_string
+        .value("pageNo")
+        .and_then(|page| page.parse::<usize>().ok())
+        .unwrap_or(1)
+        .max(1);
+    let page_size = query_string
+        .value("pageSize")
+        .and_then(|size| size.parse::<usize>().ok())
+        .unwrap_or(20);
+    let offset = (page_no - 1) * page_size;
+    match query_string.value("sortType").unwrap_or("1") {
+        "2" => ("2".to_string(), format!("normalHot#{}", offset)),
+        "3" => (
+            "3".to_string(),
+            query_string.value("cursor").unwrap_or("0").to_string(),
+        ),
+        _ => ("99".to_string(), offset.to_string()),
+    }
+}
+
+// #[get("/cloud/match")]
+pub fn index_cloud_match(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/cloud/user/song/match";
+    let query = QueryParams::from(options.params);
+    let query_params = json_object!({
+        "userId": query.value("uid").unwrap(),
+        "songId": query.value("sid").unwrap(),
+        "adjustSongId": query.value("asid").unwrap(),
+    });
+    let cookies = get_cookie_string(options.cookie);
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
+// #[get("/cloud/pub/v2")]
+pub fn index_cloud_pub_v2(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/cloud/pub/v2";
+    let query = QueryParams::from(options.params);
+    let query_params = json_object!({
+        "songid": query.value("songid").unwrap(),
+    });
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
+// #[get("/cloud/upload/check")]
+pub fn index_cloud_upload_check(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/cloud/upload/check";
+    let query = QueryParams::from(options.params);
+    let query_params = json_object!({
+        "bitrate": query.value("bitrate").unwrap_or("999000"),
+        "ext": "",
+        "length": query.value("length").unwrap(),
+        "md5": query.value("md5").unwrap(),
+        "songId": "0",
+        "version": "1",
+    });
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
 // #[get("/comment/album")]
 pub fn index_comment_album(options: Options) -> FormatParams {
     let query_string = QueryParams::from(options.params);
@@ -339,6 +454,24 @@ pub fn index_comment_event(options: Options) -> FormatParams {
     request_handler(url, "weapi", query_params, &cookies)
 }
 
+// #[get("/comment/floor")]
+pub fn index_comment_floor(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/resource/comment/floor/get";
+    let query_string = QueryParams::from(options.params);
+    let thread_id = match comment_thread_id(&query_string) {
+        Some(thread_id) => thread_id,
+        None => return no_request(),
+    };
+    let query_params = json_object!({
+        "parentCommentId": query_string.value("parentCommentId").unwrap(),
+        "threadId": thread_id.as_str(),
+        "time": query_string.value("time").unwrap_or("-1"),
+        "limit": query_string.value("limit").unwrap_or("20"),
+    });
+    let cookies = get_cookie_string(options.cookie);
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
 // #[get("/comment/hot")]
 pub fn index_comment_hot(options: Options) -> FormatParams {
     let query_string = QueryParams::from(options.params);
@@ -382,7 +515,7 @@ pub fn index_comment_like(options: Options) -> FormatParams {
     let thread_id = _type.to_owned() + query_string.value("id").unwrap();
     let query_params = json_object!({
         "commentId": query_string.value("cid").unwrap(),
-        "threadId": &thread_id,
+        "threadId": thread_id.as_str(),
     });
     let cookies = get_cookie_string(options.cookie);
     request_handler(url, "weapi", query_params, &cookies)
@@ -408,6 +541,29 @@ pub fn index_comment_mv(options: Options) -> FormatParams {
     comment_common(url, query_string, options.cookie)
 }
 
+// #[get("/comment/new")]
+pub fn index_comment_new(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/v2/resource/comments";
+    let query_string = QueryParams::from(options.params);
+    let thread_id = match comment_thread_id(&query_string) {
+        Some(thread_id) => thread_id,
+        None => return no_request(),
+    };
+    let page_no = query_string.value("pageNo").unwrap_or("1");
+    let page_size = query_string.value("pageSize").unwrap_or("20");
+    let (sort_type, cursor) = comment_page(&query_string);
+    let query_params = json_object!({
+        "threadId": thread_id.as_str(),
+        "pageNo": page_no,
+        "showInner": query_string.value("showInner").unwrap_or("true"),
+        "pageSize": page_size,
+        "cursor": cursor.as_str(),
+        "sortType": sort_type.as_str(),
+    });
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
 // #[get("/comment/playlist")]
 pub fn index_comment_playlist(options: Options) -> FormatParams {
     let query_string = QueryParams::from(options.params);
@@ -421,36 +577,31 @@ pub fn index_comment_playlist(options: Options) -> FormatParams {
 // #[get("/comment")]
 pub fn index_comment(options: Options) -> FormatParams {
     let query_string = QueryParams::from(options.params);
-    let _t = ["add", "delete", "reply"][query_string
+    let _t = query_string
         .value("t")
         .unwrap_or("0")
         .parse::<usize>()
-        .unwrap()];
+        .ok()
+        .and_then(|t| ["add", "delete", "reply"].get(t).copied());
+    let (_t, thread_id) = match (_t, comment_thread_id(&query_string)) {
+        (Some(_t), Some(thread_id)) => (_t, thread_id),
+        _ => return no_request(),
+    };
 
     let url = &format!("https://music.163.com/weapi/resource/comments/{}", _t);
 
-    let _type: &str = [
-        "R_SO_4_", "R_MV_5_", "A_PL_0_", "R_AL_3_", "A_DJ_1_", "R_VI_62_", "A_EV_2_",
-    ][query_string
-        .value("type")
-        .unwrap_or("0")
-        .parse::<usize>()
-        .unwrap()];
-
-    let mut query_params = json_object!({});
-    let _td = _type.to_owned() + query_string.value("id").unwrap();
-    if _type == "A_EV_2_" {
-        query_params.insert("threadId", query_string.value("threadId").unwrap());
-    } else {
-        query_params.insert("threadId", &_td);
-    };
+    let mut query_params = json_object!({
+        "threadId": thread_id.as_str(),
+    });
+    let content = query_string.value("content").unwrap_or_default();
+    let comment_id = query_string.value("commentId").unwrap_or_default();
     if _t == "add" {
-        query_params.insert("content", query_string.value("content").unwrap());
+        query_params.insert("content", content);
     } else if _t == "delete" {
-        query_params.insert("commentId", query_string.value("commentId").unwrap());
+        query_params.insert("commentId", comment_id);
     } else if _t == "reply" {
-        query_params.insert("commentId", query_string.value("commentId").unwrap());
-        query_params.insert("content", query_string.value("content").unwrap());
+        query_params.insert("commentId", comment_id);
+        query_params.insert("content", content);
     };
 
     let cookies = get_cookie_string(options.cookie) + ";os=pc;";
@@ -828,6 +979,25 @@ pub fn index_follow(options: Options) -> FormatParams {
     request_handler(url, "weapi", _params, &cookies)
 }
 
+// #[get("/history/recommend/songs")]
+pub fn index_history_recommend_songs(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/discovery/recommend/songs/history/recent";
+    let _params = json_object!({});
+    let cookies = get_cookie_string(options.cookie) + ";os=ios;";
+    request_handler(url, "weapi", _params, &cookies)
+}
+
+// #[get("/history/recommend/songs/detail")]
+pub fn index_history_recommend_songs_detail(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/discovery/recommend/songs/history/detail";
+    let query = QueryParams::from(options.params);
+    let query_params = json_object!({
+        "date": query.value("date").unwrap_or(""),
+    });
+    let cookies = get_cookie_string(options.cookie) + ";os=ios;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
 // #[get("/hot/topic")]
 pub fn index_hot_topic(options: Options) -> FormatParams {
     let url = "http://music.163.com/weapi/act/hot";
@@ -1006,7 +1176,7 @@ pub fn index_msg_notices(options: Options) -> FormatParams {
 
 // #[get("/msg/private/history")]
 pub fn index_msg_private_history(options: Options) -> FormatParams {
-    let url = "https://music.163.com/api/msg/private/history";
+    let url = "https://music.163.com/weapi/msg/private/history";
     let query = QueryParams::from(options.params);
     let query_params = json_object!({
         "userId": query.value("uid").unwrap(),
@@ -1020,7 +1190,7 @@ pub fn index_msg_private_history(options: Options) -> FormatParams {
 
 // #[get("/msg/private")]
 pub fn index_msg_private(options: Options) -> FormatParams {
-    let url = "https://music.163.com/api/msg/private/users";
+    let url = "https://music.163.com/weapi/msg/private/users";
     let query = QueryParams::from(options.params);
     let query_params = json_object!({
         "offset": query.value("offset").unwrap_or("0"),
@@ -1130,6 +1300,23 @@ pub fn index_mv_url(options: Options) -> FormatParams {
     request_handler(url, "weapi", query_params, &cookies)
 }
 
+// #[get("/nos/token/alloc")]
+pub fn index_nos_token_alloc(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/nos/token/alloc";
+    let query = QueryParams::from(options.params);
+    let query_params = json_object!({
+        "bucket": "",
+        "ext": query.value("ext").unwrap(),
+        "filename": query.value("filename").unwrap(),
+        "local": "false",
+        "nos_product": "3",
+        "type": "audio",
+        "md5": query.value("md5").unwrap(),
+    });
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
 // #[get("/personal/fm")]
 pub fn index_personal_fm(options: Options) -> FormatParams {
     let url = "https://music.163.com/weapi/v1/radio/get";
@@ -1177,6 +1364,12 @@ pub fn index_personalized(options: Options) -> FormatParams {
     request_handler(url, "weapi", query_params, &cookies)
 }
 
+// #[get("/pl/count")]
+pub fn index_pl_count(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/pl/count";
+    empty_query_params_handler(url, "weapi", options.cookie)
+}
+
 // #[get("/playlist/catlist")]
 pub fn index_playlist_catlist(options: Options) -> FormatParams {
     let url = "https://music.163.com/weapi/playlist/catalogue";
@@ -1312,13 +1505,14 @@ pub fn index_playlist_tags_update(options: Options) -> FormatParams {
 pub fn index_playlist_tracks(options: Options) -> FormatParams {
     let url = "https://music.163.com/weapi/playlist/manipulate/tracks";
     let query = QueryParams::from(options.params);
-    let ids = "[".to_owned() + query.value("trakcs").unwrap() + "]";
+    let ids = "[".to_owned() + query.value("tracks").unwrap() + "]";
     let query_params = json_object!({
         "op": query.value("op").unwrap(),
-        "pid": query.value("pid").unwrap_or("20"),
-        "tackIds": &ids,
+        "pid": query.value("pid").unwrap(),
+        "trackIds": &ids,
+        "imme": "true",
     });
-    let cookies = get_cookie_string(options.cookie);
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
     request_handler(url, "weapi", query_params, &cookies)
 }
 
@@ -1347,7 +1541,7 @@ pub fn index_playlist_update(options: Options) -> FormatParams {
         "/api/playlist/tags/update": &_tags[..],
         "/api/playlist/update/name": &_name[..],
     });
-    let cookies = get_cookie_string(options.cookie) + "os=pc;";
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
     request_handler(url, "weapi", query_params, &cookies)
 }
 
@@ -1411,6 +1605,19 @@ pub fn index_recommend_songs(options: Options) -> FormatParams {
     request_handler(url, "weapi", query_params, &cookies)
 }
 
+// #[get("/recommend/songs/dislike")]
+pub fn index_recommend_songs_dislike(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/v2/discovery/recommend/dislike";
+    let query = QueryParams::from(options.params);
+    let query_params = json_object!({
+        "resId": query.value("id").unwrap(),
+        "resType": "4",
+        "sceneType": "1",
+    });
+    let cookies = get_cookie_string(options.cookie) + ";os=ios;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
 // #[get("/register/cellphone")]
 pub fn index_register_cellphone(options: Options) -> FormatParams {
     let url = "https://music.163.com/weapi/register/cellphone";
@@ -1423,7 +1630,7 @@ pub fn index_register_cellphone(options: Options) -> FormatParams {
         "nickname": query.value("nickname").unwrap(),
     });
 
-    let cookies = get_cookie_string(options.cookie) + "os=pc;";
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
     request_handler(url, "weapi", query_params, &cookies)
 }
 
@@ -1553,6 +1760,22 @@ pub fn index_search(options: Options) -> FormatParams {
     request_handler(url, "weapi", query_params, &cookies)
 }
 
+// #[get("/send/album")]
+pub fn index_send_album(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/msg/private/send";
+    let query = QueryParams::from(options.params);
+    let _ids = "[".to_owned() + query.value("user_ids").unwrap() + "]";
+    let query_params = json_object!({
+        "id": query.value("id").unwrap(),
+        "type": "album",
+        "msg": query.value("msg").unwrap_or(""),
+        "userIds": &_ids,
+    });
+
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
 // #[get("/send/playlist")]
 pub fn index_send_playlist(options: Options) -> FormatParams {
     let url = "https://music.163.com/weapi/msg/private/send";
@@ -1565,7 +1788,23 @@ pub fn index_send_playlist(options: Options) -> FormatParams {
         "userIds": &_ids,
     });
 
-    let cookies = get_cookie_string(options.cookie) + "os=pc;";
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
+// #[get("/send/song")]
+pub fn index_send_song(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/msg/private/send";
+    let query = QueryParams::from(options.params);
+    let _ids = "[".to_owned() + query.value("user_ids").unwrap() + "]";
+    let query_params = json_object!({
+        "id": query.value("id").unwrap(),
+        "type": "song",
+        "msg": query.value("msg").unwrap_or(""),
+        "userIds": &_ids,
+    });
+
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
     request_handler(url, "weapi", query_params, &cookies)
 }
 
@@ -1575,13 +1814,12 @@ pub fn index_send_text(options: Options) -> FormatParams {
     let query = QueryParams::from(options.params);
     let _ids = "[".to_owned() + query.value("user_ids").unwrap() + "]";
     let query_params = json_object!({
-        "id": query.value("playlist").unwrap(),
         "type": "text",
         "msg": query.value("msg").unwrap_or(""),
         "userIds": &_ids,
     });
 
-    let cookies = get_cookie_string(options.cookie) + "os=pc;";
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
     request_handler(url, "weapi", query_params, &cookies)
 }
 
@@ -1675,8 +1913,15 @@ pub fn index_simi_user(options: Options) -> FormatParams {
 pub fn index_song_detail(options: Options) -> FormatParams {
     let url = "https://music.163.com/weapi/v3/song/detail";
     let query = QueryParams::from(options.params);
-    let c = &format!(r#""[{{"id":{}}}]""#, query.value("ids").unwrap());
-    let ids = &format!(r#""[{}]""#, query.value("ids").unwrap());
+    let ids = query.value("ids").unwrap();
+    let c = &format!(
+        "[{}]",
+        ids.split(',')
+            .map(|id| format!(r#"{{\"id\":{}}}"#, id.trim()))
+            .collect::<Vec<String>>()
+            .join(",")
+    );
+    let ids = &format!("[{}]", ids);
     let query_params = json_object!({
         "c": &c[..],
         "ids": &ids[..],
@@ -1685,6 +1930,20 @@ pub fn index_song_detail(options: Options) -> FormatParams {
     request_handler(url, "weapi", query_params, &cookies)
 }
 
+// #[get("/song/order/update")]
+pub fn index_song_order_update(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/playlist/manipulate/tracks";
+    let query = QueryParams::from(options.params);
+    let ids = "[".to_owned() + query.value("ids").unwrap() + "]";
+    let query_params = json_object!({
+        "op": "update",
+        "pid": query.value("pid").unwrap(),
+        "trackIds": &ids,
+    });
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
 // #[get("/song/url")]
 pub fn index_song_url(options: Options) -> FormatParams {
     let url = "https://music.163.com/api/song/enhance/player/url";
@@ -1756,47 +2015,9 @@ pub fn index_top_artist(options: Options) -> FormatParams {
 pub fn index_top_list(options: Options) -> FormatParams {
     let url = "https://music.163.com/weapi/v3/playlist/detail";
     let query = QueryParams::from(options.params);
-    static TOP_LIST: [&str; 37] = [
-        "3779629",    //云音乐新歌榜
-        "3778678",    //云音乐热歌榜
-        "2884035",    //云音乐原创榜
-        "19723756",   //云音乐飙升榜
-        "10520166",   //云音乐电音榜
-        "180106",     //UK排行榜周榜
-        "60198",      //美国Billboard周榜
-        "21845217",   //KTV嗨榜
-        "11641012",   //iTunes榜
-        "120001",     //Hit FM Top榜
-        "60131",      //日本Oricon周榜
-        "3733003",    //韩国Melon排行榜周榜
-        "60255",      //韩国Mnet排行榜周榜
-        "46772709",   //韩国Melon原声周榜
-        "112504",     //中国TOP排行榜(港台榜)
-        "64016",      //中国TOP排行榜(内地榜)
-        "10169002",   //香港电台中文歌曲龙虎榜
-        "4395559",    //华语金曲榜
-        "1899724",    //中国嘻哈榜
-        "27135204",   //法国 NRJ EuroHot 30周榜
-        "112463",     //台湾Hito排行榜
-        "3812895",    //Beatport全球电子舞曲榜
-        "71385702",   //云音乐ACG音乐榜
-        "991319590",  //云音乐说唱榜,
-        "71384707",   //云音乐古典音乐榜
-        "1978921795", //云音乐电音榜
-        "2250011882", //抖音排行榜
-        "2617766278", //新声榜
-        "745956260",  //云音乐韩语榜
-        "2023401535", //英国Q杂志中文版周榜
-        "2006508653", //电竞音乐榜
-        "2809513713", //云音乐欧美热歌榜
-        "2809577409", //云音乐欧美新歌榜
-        "2847251561", //说唱TOP榜
-        "3001835560", //云音乐ACG动画榜
-        "3001795926", //云音乐ACG游戏榜
-        "3001890046", //云音乐ACG VOCALOID榜
-    ];
-    let query_params = json_object!({
-        "id": TOP_LIST[query.value("idx").unwrap_or("0").parse::<usize>().unwrap()],
+    // `id` of a chart as listed by `/toplist`
+    let query_params = json_object!({
+        "id": query.value("id").unwrap_or_default(),
         "n": "10000",
     });
     let cookies = get_cookie_string(options.cookie);
@@ -1883,6 +2104,24 @@ pub fn index_toplist(options: Options) -> FormatParams {
     empty_query_params_handler(url, "weapi", options.cookie)
 }
 
+// #[get("/upload/cloud/info/v2")]
+pub fn index_upload_cloud_info_v2(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/upload/cloud/info/v2";
+    let query = QueryParams::from(options.params);
+    let query_params = json_object!({
+        "md5": query.value("md5").unwrap(),
+        "songid": query.value("songid").unwrap(),
+        "filename": query.value("filename").unwrap(),
+        "song": query.value("song").unwrap(),
+        "album": query.value("album").unwrap_or("未知专辑"),
+        "artist": query.value("artist").unwrap_or("未知艺术家"),
+        "bitrate": query.value("bitrate").unwrap_or("999000"),
+        "resourceId": query.value("resourceId").unwrap(),
+    });
+    let cookies = get_cookie_string(options.cookie) + ";os=pc;";
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
 // #[get("/user/audio")]
 pub fn index_user_audio(options: Options) -> FormatParams {
     let url = "https://music.163.com/weapi/djradio/get/byuser";
@@ -1974,7 +2213,7 @@ pub fn index_user_event(options: Options) -> FormatParams {
 pub fn index_user_followeds(options: Options) -> FormatParams {
     let query = QueryParams::from(options.params);
     let url = &format!(
-        "https://music.163.com/eapi/user/getfolloweds/{}",
+        "https://music.163.com/weapi/user/getfolloweds/{}",
         query.value("uid").unwrap()
     );
     let query_params = json_object!({
@@ -1983,14 +2222,7 @@ pub fn index_user_followeds(options: Options) -> FormatParams {
         "limit": query.value("limit").unwrap_or("30"),
     });
     let cookies = get_cookie_string(options.cookie);
-    let request_params = json_object!({
-        "crypto": "eapi",
-        "cookie": &cookies,
-        "proxy": "",
-        "url": "/api/user/getfolloweds",
-    });
-
-    generate_response(url, "POST", query_params, request_params)
+    request_handler(url, "weapi", query_params, &cookies)
 }
 
 // #[get("/user/follows")]
@@ -2126,25 +2358,303 @@ pub fn index_video_url(options: Options) -> FormatParams {
 // #[get("/weblog")]
 pub fn index_weblog(options: Options) -> FormatParams {
     let url = "https://music.163.com/weapi/feedback/weblog";
+    let query = QueryParams::from(options.params);
+    let query_params = json_object!({
+        "logs": query.value("logs").unwrap_or("[]"),
+    });
+    let cookies = get_cookie_string(options.cookie);
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
+// #[get("/yunbei/tasks")]
+pub fn index_yunbei_tasks(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/usertool/task/list/all";
+    empty_query_params_handler(url, "weapi", options.cookie)
+}
+
+// #[get("/yunbei/tasks/todo")]
+pub fn index_yunbei_tasks_todo(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/usertool/task/todo/query";
     empty_query_params_handler(url, "weapi", options.cookie)
 }
 
+// #[get("/yunbei/task/finish")]
+pub fn index_yunbei_task_finish(options: Options) -> FormatParams {
+    let url = "https://music.163.com/weapi/usertool/task/point/receive";
+    let query = QueryParams::from(options.params);
+    let query_params = json_object!({
+        "userTaskId": query.value("userTaskId").unwrap(),
+        "depositCode": query.value("depositCode").unwrap_or("0"),
+    });
+    let cookies = get_cookie_string(options.cookie);
+    request_handler(url, "weapi", query_params, &cookies)
+}
+
+/// Builds the request for a NeteaseCloudMusicApi style route such as `/song/detail`.
+pub fn dispatch(options: Options) -> FormatParams {
+    match options.url {
+        "/album/detail/dynamic" => index_album_detail_dynamic(options),
+        "/album/newest" => index_album_newest(options),
+        "/album/sub" => index_album_sub(options),
+        "/album/sublist" => index_album_sublist(options),
+        "/album" => index_album(options),
+        "/artist/detail" => index_artist_detail(options),
+        "/artist/album" => index_artist_album(options),
+        "/artist/desc" => index_artist_desc(options),
+        "/artist/list" => index_artist_list(options),
+        "/artist/mv" => index_artist_mv(options),
+        "/artist/songs" => index_artist_songs(options),
+        "/artist/sub" => index_artist_sub(options),
+        "/artist/sublist" => index_artist_sublist(options),
+        "/artist/top/song" => index_artist_top_song(options),
+        "/artists" => index_artists(options),
+        "/banner" => index_banner(options),
+        "/check/music" => index_check_music(options),
+        "/cloud/match" => index_cloud_match(options),
+        "/cloud/pub/v2" => index_cloud_pub_v2(options),
+        "/cloud/upload/check" => index_cloud_upload_check(options),
+        "/comment/album" => index_comment_album(options),
+        "/comment/dj" => index_comment_dj(options),
+        "/comment/event" => index_comment_event(options),
+        "/comment/floor" => index_comment_floor(options),
+        "/comment/hot" => index_comment_hot(options),
+        "/comment/hotwall/list" => index_comment_hotwall_list(options),
+        "/comment/like" => index_comment_like(options),
+        "/comment/music" => index_comment_music(options),
+        "/comment/mv" => index_comment_mv(options),
+        "/comment/new" => index_comment_new(options),
+        "/comment/playlist" => index_comment_playlist(options),
+        "/comment" => index_comment(options),
+        "/daily_signin" => index_daily_sigin(options),
+        "/digitalAlbum/purchased" => index_digitalAlbum_purchased(options),
+        "/dj/banner" => index_dj_banner(options),
+        "/dj/category/excludehot" => index_dj_category_excludehot(options),
+        "/dj/category/recommend" => index_dj_category_recommend(options),
+        "/dj/catelist" => index_dj_catelist(options),
+        "/dj/detail" => index_dj_detail(options),
+        "/dj/hot" => index_dj_hot(options),
+        "/dj/paygift" => index_dj_paygift(options),
+        "/dj/program/detail" => index_dj_program_detail(options),
+        "/dj/program/toplist/hours" => index_dj_program_toplist_hours(options),
+        "/dj/program/toplist" => index_dj_program_toplist(options),
+        "/dj/program" => index_dj_program(options),
+        "/dj/radio/hot" => index_dj_radio_hot(options),
+        "/dj/recommend/type" => index_dj_recommend_type(options),
+        "/dj/recommend" => index_dj_recommend(options),
+        "/dj/sub" => index_dj_sub(options),
+        "/dj/sublist" => index_dj_sublist(options),
+        "/dj/today/perfered" => index_dj_today_perfered(options),
+        "/dj/toplist/hours" => index_dj_toplist_hours(options),
+        "/dj/toplist/newcomer" => index_dj_toplist_newcomer(options),
+        "/dj/toplist/pay" => index_dj_toplist_pay(options),
+        "/dj/toplist/popular" => index_dj_toplist_popular(options),
+        "/dj/toplist" => index_dj_toplist(options),
+        "/event/del" => index_event_del(options),
+        "/event/forward" => index_event_forward(options),
+        "/event" => index_event(options),
+        "/fm/trash" => index_fm_trash(options),
+        "/follow" => index_follow(options),
+        "/history/recommend/songs" => index_history_recommend_songs(options),
+        "/history/recommend/songs/detail" => index_history_recommend_songs_detail(options),
+        "/hot/topic" => index_hot_topic(options),
+        "/like" => index_like(options),
+        "/likelist" => index_likelist(options),
+        "/login/cellphone" => index_login_cellphone(options),
+        "/login/qr/key" => index_login_qr_key(options),
+        "/login/qr/create" => index_login_qr_create(options),
+        "/login/qr/check" => index_login_qr_check(options),
+        "/login/status" => index_login_status(options),
+        "/login/refresh" => index_login_refresh(options),
+        "/logout" => index_logout(options),
+        "/lyric" => index_lyric(options),
+        "/msg/comments" => index_msg_comments(options),
+        "/msg/forwards" => index_msg_forwards(options),
+        "/msg/notices" => index_msg_notices(options),
+        "/msg/private/history" => index_msg_private_history(options),
+        "/msg/private" => index_msg_private(options),
+        "/mv/all" => index_mv_all(options),
+        "/mv/detail" => index_mv_detail(options),
+        "/mv/exclusive/rcmd" => index_mv_exclusive_rcmd(options),
+        "/mv/first" => index_mv_first(options),
+        "/mv/sub" => index_mv_sub(options),
+        "/mv/sublist" => index_mv_sublist(options),
+        "/mv/url" => index_mv_url(options),
+        "/nos/token/alloc" => index_nos_token_alloc(options),
+        "/personal/fm" => index_personal_fm(options),
+        "/personalized/djprogram" => index_personalized_djprogram(options),
+        "/personalized/mv" => index_personalized_mv(options),
+        "/personalized/newsong" => index_personalized_newsong(options),
+        "/personalized/privatecontent" => index_personalized_privatecontent(options),
+        "/personalized" => index_personalized(options),
+        "/pl/count" => index_pl_count(options),
+        "/playlist/catlist" => index_playlist_catlist(options),
+        "/playlist/create" => index_playlist_create(options),
+        "/playlist/delete" => index_playlist_delete(options),
+        "/playlist/desc_update" => index_playlist_desc_update(options),
+        "/playlist/detail" => index_playlist_detail(options),
+        "/playlist/hot" => index_playlist_hot(options),
+        "/playlist/name/update" => index_playlist_name_update(options),
+        "/playlist/subscribe" => index_playlist_subscribe(options),
+        "/playlist/subscribers" => index_playlist_subscribers(options),
+        "/playlist/tags/update" => index_playlist_tags_update(options),
+        "/playlist/tracks" => index_playlist_tracks(options),
+        "/playlist/update" => index_playlist_update(options),
+        "/playmode/intelligence/list" => index_playmode_intelligence_list(options),
+        "/program/recommend" => index_program_recommend(options),
+        "/rebind" => index_rebind(options),
+        "/recommend/resource" => index_recommend_resource(options),
+        "/recommend/songs" => index_recommend_songs(options),
+        "/recommend/songs/dislike" => index_recommend_songs_dislike(options),
+        "/register/cellphone" => index_register_cellphone(options),
+        "/related/allvideo" => index_related_allvideo(options),
+        "/related/playlist" => index_related_playlist(options),
+        "/resource/like" => index_resource_like(options),
+        "/search/default" => index_search_default(options),
+        "/search/hot/detail" => index_search_hot_detail(options),
+        "/search/hot" => index_search_hot(options),
+        "/search/multimatch" => index_search_multimatch(options),
+        "/search/suggest" => index_search_suggest(options),
+        "/search" => index_search(options),
+        "/send/album" => index_send_album(options),
+        "/send/playlist" => index_send_playlist(options),
+        "/send/song" => index_send_song(options),
+        "/send/text" => index_send_text(options),
+        "/setting" => index_setting(options),
+        "/share/resource" => index_share_resource(options),
+        "/simi/artist" => index_simi_artist(options),
+        "/simi/mv" => index_simi_mv(options),
+        "/simi/playlist" => index_simi_playlist(options),
+        "/simi/song" => index_simi_song(options),
+        "/simi/user" => index_simi_user(options),
+        "/song/detail" => index_song_detail(options),
+        "/song/order/update" => index_song_order_update(options),
+        "/song/url" => index_song_url(options),
+        "/song/url/v1" => index_song_url_v1(options),
+        "/top/album" => index_top_album(options),
+        "/top/artist" => index_top_artist(options),
+        "/top/list" => index_top_list(options),
+        "/top/mv" => index_top_mv(options),
+        "/top/playlist/highquality" => index_top_playlist_highquality(options),
+        "/top/playlist" => index_top_playlist(options),
+        "/top/song" => index_top_song(options),
+        "/toplist/artist" => index_toplist_artist(options),
+        "/toplist/detail" => index_toplist_detail(options),
+        "/toplist" => index_toplist(options),
+        "/upload/cloud/info/v2" => index_upload_cloud_info_v2(options),
+        "/user/audio" => index_user_audio(options),
+        "/user/cloud/del" => index_user_cloud_del(options),
+        "/user/cloud/detail" => index_user_cloud_detail(options),
+        "/user/cloud" => index_user_cloud(options),
+        "/user/detail" => index_user_detail(options),
+        "/user/dj" => index_user_dj(options),
+        "/user/event" => index_user_event(options),
+        "/user/followeds" => index_user_followeds(options),
+        "/user/follows" => index_user_follows(options),
+        "/user/playlist" => index_user_playlist(options),
+        "/user/record" => index_user_record(options),
+        "/user/account" => index_user_account(options),
+        "/user/subcount" => index_user_subcount(options),
+        "/user/update" => index_user_update(options),
+        "/video/detail" => index_video_detail(options),
+        "/video/group/list" => index_video_group_list(options),
+        "/video/group" => index_video_group(options),
+        "/video/sub" => index_video_sub(options),
+        "/video/url" => index_video_url(options),
+        "/weblog" => index_weblog(options),
+        "/yunbei/tasks" => index_yunbei_tasks(options),
+        "/yunbei/tasks/todo" => index_yunbei_tasks_todo(options),
+        "/yunbei/task/finish" => index_yunbei_task_finish(options),
+        _ => no_request(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
+    use crate::netease::comment::CommentSort;
+    use crate::netease::crypto::Crypto;
     use crate::Options;
+    use urlqstring::QueryParams;
 
-    use super::index_top_list;
+    use super::{
+        comment_page, comment_thread_id, index_comment, index_send_album, index_send_song,
+        index_send_text, index_top_list,
+    };
 
     #[test]
     fn test_index_top_list() {
         let options = Options {
-            params: vec![("idx", "0")],
+            params: vec![("id", "19723756")],
             cookie: &"".to_string(),
             url: &"".to_string(),
             method: &"".to_string(),
         };
         let result = index_top_list(options);
-        println!("{:?}", result);
+        assert_eq!(result.url, "https://music.163.com/api/linux/forward");
+        let eparams = result
+            .body
+            .trim_start_matches("eparams=")
+            .trim_end_matches('&');
+        let sent: serde_json::Value =
+            serde_json::from_str(&Crypto::linuxapi_decrypt(eparams)).unwrap();
+        assert_eq!(sent["url"], "https://music.163.com/api/v3/playlist/detail");
+        assert_eq!(sent["params"]["id"], "19723756");
+        assert_eq!(sent["params"]["n"], "10000");
+    }
+
+    #[test]
+    fn test_comment_page() {
+        let page = |sort: CommentSort, page_no: &str| {
+            comment_page(&QueryParams::from(vec![
+                ("sortType", sort.sort_type()),
+                ("pageNo", page_no),
+                ("pageSize", "20"),
+                ("cursor", "1700000000000"),
+            ]))
+        };
+        let pair = |sort: &str, cursor: &str| (sort.to_string(), cursor.to_string());
+        assert_eq!(page(CommentSort::Recommended, "1"), pair("99", "0"));
+        assert_eq!(page(CommentSort::Recommended, "3"), pair("99", "40"));
+        assert_eq!(page(CommentSort::Hot, "1"), pair("2", "normalHot#0"));
+        assert_eq!(page(CommentSort::Hot, "2"), pair("2", "normalHot#20"));
+        assert_eq!(page(CommentSort::Time, "2"), pair("3", "1700000000000"));
+
+        let thread =
+            |kind: &str| comment_thread_id(&QueryParams::from(vec![("type", kind), ("id", "5")]));
+        assert_eq!(thread("1").as_deref(), Some("R_MV_5_5"));
+        assert_eq!(thread("9"), None);
+        assert_eq!(thread("mv"), None);
+
+        // a request that names no resource is not sent at all
+        for params in [
+            vec![("t", "1"), ("type", "mv"), ("id", "5")],
+            vec![("t", "7")],
+        ] {
+            let result = index_comment(Options {
+                params,
+                cookie: "",
+                url: "",
+                method: "",
+            });
+            assert!(result.url.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_send_cookie() {
+        for index in [index_send_album, index_send_song, index_send_text] {
+            let result = index(Options {
+                params: vec![("id", "1"), ("user_ids", "2"), ("msg", "hi")],
+                cookie: "MUSIC_U=abc",
+                url: "",
+                method: "",
+            });
+            let cookie = result
+                .headers
+                .iter()
+                .find(|(name, _)| name == "cookie")
+                .map(|(_, value)| value.as_str());
+            assert_eq!(cookie, Some("MUSIC_U=abc;os=pc;"));
+        }
     }
 }
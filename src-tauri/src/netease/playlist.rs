@@ -0,0 +1,496 @@
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use crate::netease::client::{ApiError, ApiResult, NeteaseClient};
+
+/// `song/detail` rejects requests with more ids than this.
+const SONG_DETAIL_BATCH: usize = 1000;
+const SONG_DETAIL_CONCURRENCY: usize = 4;
+/// Tracks sent per `playlist/manipulate/tracks` request.
+const PLAYLIST_EDIT_BATCH: usize = 500;
+
+#[derive(Debug, Default, Serialize)]
+pub struct PlaylistTracks {
+    pub songs: Vec<Value>,
+    pub privileges: Vec<Value>,
+}
+
+/// Reads the playlist's `trackIds` and resolves the requested window through `song/detail`,
+/// since the detail endpoint only returns full `tracks` for the first slice.
+pub async fn playlist_all_tracks(
+    client: &NeteaseClient,
+    id: &str,
+    limit: Option<usize>,
+    offset: usize,
+) -> ApiResult<PlaylistTracks> {
+    let ids: Vec<u64> = playlist_track_ids(client, id)
+        .await?
+        .into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+
+    song_details(client, &ids).await
+}
+
+/// Returns every track id of the playlist in playlist order.
+pub async fn playlist_track_ids(client: &NeteaseClient, id: &str) -> ApiResult<Vec<u64>> {
+    let detail = client.call("/playlist/detail", &[("id", id)]).await?;
+    track_ids_of(&detail)
+}
+
+/// Reads `playlist.trackIds` out of a `playlist/detail` body.
+pub(crate) fn track_ids_of(detail: &Value) -> ApiResult<Vec<u64>> {
+    let track_ids = detail
+        .pointer("/playlist/trackIds")
+        .and_then(Value::as_array)
+        .ok_or_else(|| ApiError::Malformed("playlist without trackIds".to_string()))?;
+
+    Ok(track_ids
+        .iter()
+        .filter_map(|track| track.get("id").and_then(Value::as_u64))
+        .collect())
+}
+
+/// Fetches `song/detail` in batches and returns songs and privileges in the order of `ids`.
+pub async fn song_details(client: &NeteaseClient, ids: &[u64]) -> ApiResult<PlaylistTracks> {
+    // joined up front: a stream over borrowed chunks would make the future non-`Send`
+    let joined: Vec<String> = ids.chunks(SONG_DETAIL_BATCH).map(join_ids).collect();
+    let batches: Vec<Value> = stream::iter(joined)
+        .map(|joined| async move { client.call("/song/detail", &[("ids", &joined)]).await })
+        .buffered(SONG_DETAIL_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    let mut songs = HashMap::new();
+    let mut privileges = HashMap::new();
+    for batch in batches {
+        collect_by_id(&batch, "songs", &mut songs);
+        collect_by_id(&batch, "privileges", &mut privileges);
+    }
+
+    let mut tracks = PlaylistTracks::default();
+    for id in ids {
+        if let Some(song) = songs.get(id) {
+            tracks.songs.push(song.clone());
+        }
+        if let Some(privilege) = privileges.get(id) {
+            tracks.privileges.push(privilege.clone());
+        }
+    }
+    Ok(tracks)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TrackOutcome {
+    Succeeded,
+    AlreadyPresent,
+    CopyrightRejected,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackReport {
+    pub id: u64,
+    pub outcome: TrackOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditReport {
+    pub playlist_id: String,
+    pub tracks: Vec<TrackReport>,
+}
+
+type Outcomes = HashMap<u64, (TrackOutcome, Option<String>)>;
+
+impl EditReport {
+    /// Lists the outcome of every requested id in request order; repeated ids count as present.
+    fn new(playlist_id: &str, ids: &[u64], mut outcomes: Outcomes) -> Self {
+        let tracks = ids
+            .iter()
+            .map(|&id| {
+                let (outcome, message) = outcomes
+                    .remove(&id)
+                    .unwrap_or((TrackOutcome::AlreadyPresent, None));
+                TrackReport {
+                    id,
+                    outcome,
+                    message,
+                }
+            })
+            .collect();
+        EditReport {
+            playlist_id: playlist_id.to_string(),
+            tracks,
+        }
+    }
+}
+
+pub struct PlaylistEditor<'a> {
+    client: &'a NeteaseClient,
+}
+
+impl<'a> PlaylistEditor<'a> {
+    pub fn new(client: &'a NeteaseClient) -> Self {
+        PlaylistEditor { client }
+    }
+
+    /// Adds songs that are not in the playlist yet and have a playable copyright.
+    pub async fn add(&self, pid: &str, ids: &[u64]) -> ApiResult<EditReport> {
+        let existing: HashSet<u64> = playlist_track_ids(self.client, pid)
+            .await?
+            .into_iter()
+            .collect();
+        let mut outcomes = Outcomes::new();
+        let mut candidates = vec![];
+        for &id in ids {
+            if existing.contains(&id) {
+                outcomes.insert(id, (TrackOutcome::AlreadyPresent, None));
+            } else if let Entry::Vacant(entry) = outcomes.entry(id) {
+                entry.insert((TrackOutcome::Succeeded, None));
+                candidates.push(id);
+            }
+        }
+
+        // privileges with a negative `st` belong to songs without copyright
+        let details = song_details(self.client, &candidates).await?;
+        let blocked: HashSet<u64> = details
+            .privileges
+            .iter()
+            .filter(|privilege| privilege.get("st").and_then(Value::as_i64).unwrap_or(0) < 0)
+            .filter_map(|privilege| privilege.get("id").and_then(Value::as_u64))
+            .collect();
+        candidates.retain(|id| !blocked.contains(id));
+        for id in blocked {
+            outcomes.insert(id, (TrackOutcome::CopyrightRejected, None));
+        }
+
+        outcomes.extend(self.manipulate(pid, "add", &candidates).await);
+        Ok(EditReport::new(pid, ids, outcomes))
+    }
+
+    pub async fn remove(&self, pid: &str, ids: &[u64]) -> ApiResult<EditReport> {
+        let existing: HashSet<u64> = playlist_track_ids(self.client, pid)
+            .await?
+            .into_iter()
+            .collect();
+        let mut outcomes = Outcomes::new();
+        let mut candidates = vec![];
+        for &id in ids {
+            if !existing.contains(&id) {
+                let message = Some("not in playlist".to_string());
+                outcomes.insert(id, (TrackOutcome::Failed, message));
+            } else if let Entry::Vacant(entry) = outcomes.entry(id) {
+                entry.insert((TrackOutcome::Succeeded, None));
+                candidates.push(id);
+            }
+        }
+
+        outcomes.extend(self.manipulate(pid, "del", &candidates).await);
+        Ok(EditReport::new(pid, ids, outcomes))
+    }
+
+    /// Moves `ids` to the top in the given order and keeps the remaining tracks after them.
+    pub async fn reorder(&self, pid: &str, ids: &[u64]) -> ApiResult<()> {
+        let existing = playlist_track_ids(self.client, pid).await?;
+        let present: HashSet<u64> = existing.iter().copied().collect();
+        let mut seen = HashSet::new();
+        let order: Vec<u64> = ids
+            .iter()
+            .filter(|id| present.contains(id))
+            .chain(existing.iter())
+            .copied()
+            .filter(|id| seen.insert(*id))
+            .collect();
+
+        self.update_order(pid, &order).await
+    }
+
+    /// Keeps the first occurrence of every song and drops the repeats.
+    pub async fn dedupe(&self, pid: &str) -> ApiResult<EditReport> {
+        let existing = playlist_track_ids(self.client, pid).await?;
+        let mut seen = HashSet::new();
+        let mut duplicated = vec![];
+        for &id in &existing {
+            if !seen.insert(id) && !duplicated.contains(&id) {
+                duplicated.push(id);
+            }
+        }
+        if duplicated.is_empty() {
+            return Ok(EditReport::new(pid, &[], Outcomes::new()));
+        }
+
+        // deleting drops every copy, so add one back and restore the original order
+        let mut outcomes = self.manipulate(pid, "del", &duplicated).await;
+        let removed: Vec<u64> = duplicated
+            .iter()
+            .copied()
+            .filter(|id| outcomes[id].0 == TrackOutcome::Succeeded)
+            .collect();
+        outcomes.extend(self.manipulate(pid, "add", &removed).await);
+
+        let mut kept = HashSet::new();
+        let order: Vec<u64> = existing.into_iter().filter(|id| kept.insert(*id)).collect();
+        self.update_order(pid, &order).await?;
+        Ok(EditReport::new(pid, &duplicated, outcomes))
+    }
+
+    /// Creates a new playlist holding the tracks of `source` in the same order.
+    pub async fn copy(&self, source: &str, name: &str, privacy: &str) -> ApiResult<EditReport> {
+        let ids = playlist_track_ids(self.client, source).await?;
+        let pid = self.create(name, privacy).await?;
+
+        let report = self.add(&pid, &ids).await?;
+        // added tracks are prepended, so the copy would come out reversed
+        self.update_order(&pid, &ids).await?;
+        Ok(report)
+    }
+
+    /// Creates an empty playlist and returns its id.
+    pub async fn create(&self, name: &str, privacy: &str) -> ApiResult<String> {
+        let created = self
+            .client
+            .call("/playlist/create", &[("name", name), ("privacy", privacy)])
+            .await?;
+        created
+            .get("id")
+            .or_else(|| created.pointer("/playlist/id"))
+            .and_then(Value::as_u64)
+            .map(|id| id.to_string())
+            .ok_or_else(|| ApiError::Malformed("created playlist without id".to_string()))
+    }
+
+    async fn update_order(&self, pid: &str, ids: &[u64]) -> ApiResult<()> {
+        self.client
+            .call(
+                "/song/order/update",
+                &[("pid", pid), ("ids", &join_ids(ids))],
+            )
+            .await
+            .map(|_| ())
+    }
+
+    /// Sends `ids` in batches and retries a failed batch one track at a time.
+    async fn manipulate(&self, pid: &str, op: &str, ids: &[u64]) -> Outcomes {
+        let mut outcomes = Outcomes::new();
+        for chunk in ids.chunks(PLAYLIST_EDIT_BATCH) {
+            match self.send_tracks(pid, op, chunk).await {
+                Ok(()) => {
+                    for &id in chunk {
+                        outcomes.insert(id, (TrackOutcome::Succeeded, None));
+                    }
+                }
+                Err(err) if chunk.len() == 1 => {
+                    outcomes.insert(chunk[0], classify_edit_error(&err));
+                }
+                Err(_) => {
+                    for &id in chunk {
+                        let outcome = match self.send_tracks(pid, op, &[id]).await {
+                            Ok(()) => (TrackOutcome::Succeeded, None),
+                            Err(err) => classify_edit_error(&err),
+                        };
+                        outcomes.insert(id, outcome);
+                    }
+                }
+            }
+        }
+        outcomes
+    }
+
+    async fn send_tracks(&self, pid: &str, op: &str, ids: &[u64]) -> ApiResult<()> {
+        let tracks = join_ids(ids);
+        let params = [("op", op), ("pid", pid), ("tracks", tracks.as_str())];
+        match self.client.call("/playlist/tracks", &params).await {
+            // Netease rejects some batches with 512 but accepts them when every id is sent twice
+            Err(ApiError::Status { code: 512, .. }) => {
+                let doubled = format!("{},{}", tracks, tracks);
+                let params = [("op", op), ("pid", pid), ("tracks", doubled.as_str())];
+                self.client
+                    .call("/playlist/tracks", &params)
+                    .await
+                    .map(|_| ())
+            }
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+fn classify_edit_error(err: &ApiError) -> (TrackOutcome, Option<String>) {
+    match err {
+        ApiError::Status { code: 502, .. } => (TrackOutcome::AlreadyPresent, None),
+        _ => (TrackOutcome::Failed, Some(err.to_string())),
+    }
+}
+
+fn join_ids(ids: &[u64]) -> String {
+    ids.iter()
+        .map(u64::to_string)
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn collect_by_id(body: &Value, key: &str, into: &mut HashMap<u64, Value>) {
+    for item in body
+        .get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        if let Some(id) = item.get("id").and_then(Value::as_u64) {
+            into.insert(id, item.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{playlist_all_tracks, PlaylistEditor, TrackOutcome};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    fn details(params: &[(String, String)]) -> Value {
+        // answer in reverse order to make sure the original order is restored
+        let ids: Vec<u64> = param(params, "ids")
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .map(|id| id.parse().unwrap())
+            .rev()
+            .collect();
+        json!({
+            "code": 200,
+            "songs": ids.iter().map(|id| json!({ "id": id })).collect::<Vec<Value>>(),
+            "privileges": ids
+                .iter()
+                .map(|id| json!({ "id": id, "st": if *id == 404 { -200 } else { 0 } }))
+                .collect::<Vec<Value>>(),
+        })
+    }
+
+    fn ids_param(params: &[(String, String)], key: &str) -> Vec<u64> {
+        param(params, key)
+            .split(',')
+            .map(|id| id.parse().unwrap())
+            .collect()
+    }
+
+    /// Keeps one playlist in memory and applies add/del/update like Netease does.
+    fn playlist_transport(tracks: Vec<u64>) -> (Arc<MockTransport>, Arc<Mutex<Vec<u64>>>) {
+        let state = Arc::new(Mutex::new(tracks));
+        let (detail, edit, order) = (state.clone(), state.clone(), state.clone());
+        let transport = MockTransport::default()
+            .on("/playlist/detail", move |_| {
+                let ids: Vec<Value> = detail
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|id| json!({ "id": id }))
+                    .collect();
+                json!({ "code": 200, "playlist": { "trackIds": ids } })
+            })
+            .on("/song/detail", details)
+            .on("/playlist/tracks", move |params| {
+                let ids = ids_param(params, "tracks");
+                let mut tracks = edit.lock().unwrap();
+                match param(params, "op") {
+                    "add" if ids.iter().any(|id| tracks.contains(id)) => json!({ "code": 502 }),
+                    "add" => {
+                        tracks.splice(0..0, ids);
+                        json!({ "code": 200 })
+                    }
+                    _ => {
+                        tracks.retain(|id| !ids.contains(id));
+                        json!({ "code": 200 })
+                    }
+                }
+            })
+            .on("/song/order/update", move |params| {
+                *order.lock().unwrap() = ids_param(params, "ids");
+                json!({ "code": 200 })
+            })
+            .on("/playlist/create", |_| json!({ "code": 200, "id": 77 }));
+        (Arc::new(transport), state)
+    }
+
+    #[tokio::test]
+    async fn test_playlist_all_tracks() {
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/playlist/detail", |_| {
+                    let ids: Vec<Value> = (1..=2500).map(|id| json!({ "id": id })).collect();
+                    json!({ "code": 200, "playlist": { "trackIds": ids } })
+                })
+                .on("/song/detail", details),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+
+        let tracks = playlist_all_tracks(&client, "1", None, 0).await.unwrap();
+        assert_eq!(tracks.songs.len(), 2500);
+        assert_eq!(tracks.privileges.len(), 2500);
+        assert_eq!(tracks.songs[0]["id"], 1);
+        assert_eq!(tracks.songs[2499]["id"], 2500);
+        assert_eq!(transport.calls_to("/song/detail").len(), 3);
+
+        let tracks = playlist_all_tracks(&client, "1", Some(10), 995)
+            .await
+            .unwrap();
+        let ids: Vec<u64> = tracks
+            .songs
+            .iter()
+            .map(|s| s["id"].as_u64().unwrap())
+            .collect();
+        assert_eq!(ids, (996..=1005).collect::<Vec<u64>>());
+    }
+
+    #[tokio::test]
+    async fn test_playlist_editor_add() {
+        let (transport, state) = playlist_transport(vec![1, 2]);
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let editor = PlaylistEditor::new(&client);
+
+        let report = editor.add("9", &[2, 3, 404, 3, 5]).await.unwrap();
+        let outcomes: Vec<TrackOutcome> = report.tracks.iter().map(|t| t.outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                TrackOutcome::AlreadyPresent,
+                TrackOutcome::Succeeded,
+                TrackOutcome::CopyrightRejected,
+                TrackOutcome::AlreadyPresent,
+                TrackOutcome::Succeeded,
+            ]
+        );
+        assert_eq!(*state.lock().unwrap(), vec![3, 5, 1, 2]);
+        assert_eq!(
+            param(&transport.calls_to("/playlist/tracks")[0], "tracks"),
+            "3,5"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_playlist_editor_reorder_dedupe_copy() {
+        let (transport, state) = playlist_transport(vec![1, 2, 1, 3, 2]);
+        let client = NeteaseClient::with_transport(transport, "");
+        let editor = PlaylistEditor::new(&client);
+
+        let report = editor.dedupe("9").await.unwrap();
+        assert_eq!(report.tracks.len(), 2);
+        assert_eq!(*state.lock().unwrap(), vec![1, 2, 3]);
+
+        editor.reorder("9", &[3, 9]).await.unwrap();
+        assert_eq!(*state.lock().unwrap(), vec![3, 1, 2]);
+
+        *state.lock().unwrap() = vec![4, 5, 6];
+        let report = editor.copy("9", "copy", "0").await.unwrap();
+        assert_eq!(report.playlist_id, "77");
+        assert_eq!(*state.lock().unwrap(), vec![4, 5, 6]);
+    }
+}
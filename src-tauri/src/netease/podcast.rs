@@ -0,0 +1,470 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::history::Named;
+use crate::json::text;
+use crate::netease::client::{ApiResult, NeteaseClient};
+use crate::store::JsonStore;
+
+/// Radios requested per `dj/sublist` page.
+const SUBLIST_PAGE: usize = 100;
+/// Episodes requested per `dj/program` page.
+const PROGRAM_PAGE: usize = 50;
+/// Pages of new episodes fetched per radio and sync; older ones come through `load_older`.
+const SYNC_PAGES: usize = 4;
+/// An episode counts as played once less than this is left.
+const PLAYED_MARGIN_MS: u64 = 30_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Radio {
+    pub id: u64,
+    pub name: String,
+    pub cover_url: String,
+    pub dj: Named,
+    pub program_count: u64,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Episode {
+    /// The program id.
+    pub id: u64,
+    pub radio_id: u64,
+    pub name: String,
+    pub description: String,
+    pub cover_url: String,
+    pub duration_ms: u64,
+    /// Unix time in milliseconds.
+    pub create_time: i64,
+    pub serial_num: u64,
+    /// The song id the audio is played through.
+    pub song_id: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpisodeProgress {
+    pub position_ms: u64,
+    pub played: bool,
+    /// Unix time in milliseconds.
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpisodeView {
+    pub episode: Episode,
+    pub progress: EpisodeProgress,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodcastState {
+    pub radios: Vec<Radio>,
+    /// Known episodes per radio, newest first.
+    pub episodes: HashMap<u64, Vec<Episode>>,
+    /// Whether every episode of a radio has been fetched.
+    pub complete: HashSet<u64>,
+    pub progress: HashMap<u64, EpisodeProgress>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodcastSync {
+    pub radios: usize,
+    pub new_episodes: usize,
+    /// Radios that are no longer subscribed and were dropped.
+    pub removed: Vec<u64>,
+}
+
+/// Mirror of the subscribed radios with their episodes and listening progress.
+pub struct PodcastLibrary {
+    store: Mutex<JsonStore<PodcastState>>,
+}
+
+impl PodcastLibrary {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        PodcastLibrary {
+            store: Mutex::new(JsonStore::open(path)),
+        }
+    }
+
+    pub fn radios(&self) -> Vec<Radio> {
+        self.store.lock().unwrap().get().radios.clone()
+    }
+
+    fn view(state: &PodcastState, episode: &Episode) -> EpisodeView {
+        EpisodeView {
+            episode: episode.clone(),
+            progress: state.progress.get(&episode.id).copied().unwrap_or_default(),
+        }
+    }
+
+    /// A page of a radio's known episodes, newest first.
+    pub fn episodes(&self, radio_id: u64, offset: usize, limit: usize) -> Vec<EpisodeView> {
+        let store = self.store.lock().unwrap();
+        let state = store.get();
+        state
+            .episodes
+            .get(&radio_id)
+            .into_iter()
+            .flatten()
+            .skip(offset)
+            .take(limit)
+            .map(|episode| Self::view(state, episode))
+            .collect()
+    }
+
+    /// Unplayed episodes of every subscription, newest first.
+    pub fn unplayed(&self, limit: usize) -> Vec<EpisodeView> {
+        let store = self.store.lock().unwrap();
+        let state = store.get();
+        let mut unplayed: Vec<&Episode> = state
+            .episodes
+            .values()
+            .flatten()
+            .filter(|episode| !state.progress.get(&episode.id).map_or(false, |p| p.played))
+            .collect();
+        unplayed.sort_by_key(|episode| Reverse(episode.create_time));
+        unplayed
+            .into_iter()
+            .take(limit)
+            .map(|episode| Self::view(state, episode))
+            .collect()
+    }
+
+    /// Where to continue an episode; played episodes start over.
+    pub fn resume_position(&self, episode_id: u64) -> u64 {
+        let store = self.store.lock().unwrap();
+        match store.get().progress.get(&episode_id) {
+            Some(progress) if !progress.played => progress.position_ms,
+            _ => 0,
+        }
+    }
+
+    /// Stores the playback position, marking the episode played close to its end.
+    pub fn set_position(&self, episode_id: u64, position_ms: u64) -> ApiResult<EpisodeProgress> {
+        let mut store = self.store.lock().unwrap();
+        let duration = store
+            .get()
+            .episodes
+            .values()
+            .flatten()
+            .find(|episode| episode.id == episode_id)
+            .map(|episode| episode.duration_ms);
+        let progress = store.update(|state| {
+            let progress = state.progress.entry(episode_id).or_default();
+            progress.position_ms = position_ms;
+            progress.played = duration.map_or(false, |duration| {
+                duration > 0 && position_ms + PLAYED_MARGIN_MS >= duration
+            });
+            progress.updated_at = Utc::now().timestamp_millis();
+            *progress
+        })?;
+        Ok(progress)
+    }
+
+    pub fn set_played(&self, episode_id: u64, played: bool) -> ApiResult<EpisodeProgress> {
+        let progress = self.store.lock().unwrap().update(|state| {
+            let progress = state.progress.entry(episode_id).or_default();
+            progress.played = played;
+            progress.position_ms = 0;
+            progress.updated_at = Utc::now().timestamp_millis();
+            *progress
+        })?;
+        Ok(progress)
+    }
+
+    /// Refreshes the subscriptions and fetches the episodes published since the last sync.
+    pub async fn sync(&self, client: &NeteaseClient) -> ApiResult<PodcastSync> {
+        let radios = subscribed_radios(client).await?;
+        let mut new_episodes = 0;
+        let mut fetched = HashMap::new();
+        for radio in &radios {
+            let known: HashSet<u64> = self.known_ids(radio.id);
+            let mut fresh = vec![];
+            // whether the fetched pages join up with the known episodes
+            let mut joined = known.is_empty();
+            let mut exhausted = false;
+            'pages: for page in 0..SYNC_PAGES {
+                let (episodes, more) = programs(client, radio.id, page * PROGRAM_PAGE).await?;
+                for episode in episodes {
+                    if known.contains(&episode.id) {
+                        joined = true;
+                        break 'pages;
+                    }
+                    fresh.push(episode);
+                }
+                if !more {
+                    exhausted = true;
+                    break;
+                }
+            }
+            new_episodes += fresh.len();
+            fetched.insert(radio.id, (fresh, joined, exhausted));
+        }
+
+        let removed = self.store.lock().unwrap().update(|state| {
+            let subscribed: HashSet<u64> = radios.iter().map(|radio| radio.id).collect();
+            let removed: Vec<u64> = state
+                .radios
+                .iter()
+                .map(|radio| radio.id)
+                .filter(|id| !subscribed.contains(id))
+                .collect();
+            for id in &removed {
+                if let Some(episodes) = state.episodes.remove(id) {
+                    for episode in episodes {
+                        state.progress.remove(&episode.id);
+                    }
+                }
+                state.complete.remove(id);
+            }
+            for (radio_id, (mut fresh, joined, exhausted)) in fetched {
+                let episodes = state.episodes.entry(radio_id).or_default();
+                // after a long absence the old episodes would leave a gap, so they are
+                // dropped and fetched again through `load_older`
+                if joined || exhausted {
+                    fresh.append(episodes);
+                }
+                *episodes = fresh;
+                if exhausted {
+                    state.complete.insert(radio_id);
+                } else if !joined {
+                    state.complete.remove(&radio_id);
+                }
+            }
+            state.radios = radios.clone();
+            removed
+        })?;
+        Ok(PodcastSync {
+            radios: radios.len(),
+            new_episodes,
+            removed,
+        })
+    }
+
+    fn known_ids(&self, radio_id: u64) -> HashSet<u64> {
+        let store = self.store.lock().unwrap();
+        store
+            .get()
+            .episodes
+            .get(&radio_id)
+            .into_iter()
+            .flatten()
+            .map(|episode| episode.id)
+            .collect()
+    }
+
+    /// Fetches the next page of episodes older than the ones known and returns how many
+    /// were added; 0 once the whole radio is mirrored.
+    pub async fn load_older(&self, client: &NeteaseClient, radio_id: u64) -> ApiResult<usize> {
+        let (offset, complete) = {
+            let store = self.store.lock().unwrap();
+            let state = store.get();
+            (
+                state.episodes.get(&radio_id).map_or(0, Vec::len),
+                state.complete.contains(&radio_id),
+            )
+        };
+        if complete {
+            return Ok(0);
+        }
+        let (episodes, more) = programs(client, radio_id, offset).await?;
+        let added = self.store.lock().unwrap().update(|state| {
+            let known = state.episodes.entry(radio_id).or_default();
+            let before = known.len();
+            for episode in episodes {
+                // episodes published since the last sync shift the offsets
+                if !known.iter().any(|old| old.id == episode.id) {
+                    known.push(episode);
+                }
+            }
+            if !more {
+                state.complete.insert(radio_id);
+            }
+            known.len() - before
+        })?;
+        Ok(added)
+    }
+}
+
+async fn subscribed_radios(client: &NeteaseClient) -> ApiResult<Vec<Radio>> {
+    let limit = SUBLIST_PAGE.to_string();
+    let mut radios = vec![];
+    loop {
+        let offset = radios.len().to_string();
+        let body = client
+            .call("/dj/sublist", &[("limit", &limit), ("offset", &offset)])
+            .await?;
+        let page: Vec<Radio> = body
+            .get("djRadios")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(radio_of)
+            .collect();
+        let more = body
+            .get("hasMore")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if page.is_empty() {
+            break;
+        }
+        radios.extend(page);
+        if !more {
+            break;
+        }
+    }
+    Ok(radios)
+}
+
+/// One page of a radio's programs, newest first, and whether there are more.
+pub(crate) async fn programs(
+    client: &NeteaseClient,
+    radio_id: u64,
+    offset: usize,
+) -> ApiResult<(Vec<Episode>, bool)> {
+    let body = client
+        .call(
+            "/dj/program",
+            &[
+                ("rid", &radio_id.to_string()),
+                ("limit", &PROGRAM_PAGE.to_string()),
+                ("offset", &offset.to_string()),
+                ("asc", "false"),
+            ],
+        )
+        .await?;
+    let episodes = body
+        .get("programs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|program| episode_of(program, radio_id))
+        .collect();
+    let more = body.get("more").and_then(Value::as_bool).unwrap_or(false);
+    Ok((episodes, more))
+}
+
+pub(crate) fn radio_of(radio: &Value) -> Option<Radio> {
+    let dj = radio.get("dj").unwrap_or(&Value::Null);
+    Some(Radio {
+        id: radio.get("id")?.as_u64()?,
+        name: text(radio, "name"),
+        cover_url: text(radio, "picUrl"),
+        dj: Named {
+            id: dj.get("userId").and_then(Value::as_u64).unwrap_or_default(),
+            name: text(dj, "nickname"),
+        },
+        program_count: radio
+            .get("programCount")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+        description: text(radio, "desc"),
+    })
+}
+
+pub(crate) fn episode_of(program: &Value, radio_id: u64) -> Option<Episode> {
+    Some(Episode {
+        id: program.get("id")?.as_u64()?,
+        radio_id,
+        name: text(program, "name"),
+        description: text(program, "description"),
+        cover_url: text(program, "coverUrl"),
+        duration_ms: program
+            .get("duration")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+        create_time: program
+            .get("createTime")
+            .and_then(Value::as_i64)
+            .unwrap_or_default(),
+        serial_num: program
+            .get("serialNum")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+        song_id: program
+            .pointer("/mainSong/id")
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PodcastLibrary;
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use crate::store::TempDir;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    fn program(id: u64) -> serde_json::Value {
+        json!({ "id": id, "name": format!("ep {}", id), "duration": 3_600_000, "createTime": id * 1000, "mainSong": { "id": id + 100 } })
+    }
+
+    #[tokio::test]
+    async fn test_podcast_sync_and_resume() {
+        let dir = TempDir::new("podcasts");
+        let path = dir.join("podcasts.json");
+        // the newest episode id; radio 1 has episodes 1..=newest
+        let newest = Arc::new(AtomicU64::new(3));
+        let latest = newest.clone();
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/dj/sublist", |_| {
+                    json!({ "code": 200, "hasMore": false, "djRadios": [
+                        { "id": 1, "name": "Radio", "dj": { "userId": 9, "nickname": "host" }, "programCount": 3 },
+                    ] })
+                })
+                .on("/dj/program", move |params| {
+                    let newest = latest.load(Ordering::SeqCst);
+                    let offset: u64 = param(params, "offset").parse().unwrap();
+                    let limit: u64 = param(params, "limit").parse().unwrap();
+                    let programs: Vec<_> = (1..=newest)
+                        .rev()
+                        .skip(offset as usize)
+                        .take(limit as usize)
+                        .map(program)
+                        .collect();
+                    json!({ "code": 200, "programs": programs, "more": offset + limit < newest })
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let library = PodcastLibrary::open(&path);
+
+        assert_eq!(library.sync(&client).await.unwrap().new_episodes, 3);
+        newest.store(5, Ordering::SeqCst);
+        let sync = library.sync(&client).await.unwrap();
+        assert_eq!((sync.radios, sync.new_episodes), (1, 2));
+        let ids: Vec<u64> = library
+            .episodes(1, 0, 10)
+            .iter()
+            .map(|view| view.episode.id)
+            .collect();
+        assert_eq!(ids, vec![5, 4, 3, 2, 1]);
+        assert_eq!(library.load_older(&client, 1).await.unwrap(), 0);
+
+        library.set_position(4, 600_000).unwrap();
+        library.set_position(5, 3_590_000).unwrap();
+        let library = PodcastLibrary::open(&path);
+        assert_eq!(library.resume_position(4), 600_000);
+        assert_eq!(library.resume_position(5), 0);
+        let unplayed: Vec<u64> = library
+            .unplayed(3)
+            .iter()
+            .map(|view| view.episode.id)
+            .collect();
+        assert_eq!(unplayed, vec![4, 3, 2]);
+        assert_eq!(library.episodes(1, 1, 1)[0].progress.position_ms, 600_000);
+    }
+}
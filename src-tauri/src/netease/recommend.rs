@@ -0,0 +1,270 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::history::Named;
+use crate::json::named;
+use crate::netease::client::{ApiError, ApiResult, NeteaseClient};
+use crate::store::JsonStore;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendedSong {
+    pub id: u64,
+    pub name: String,
+    pub artists: Vec<Named>,
+    pub album: Option<Named>,
+    pub cover_url: String,
+    pub duration_ms: u64,
+    /// Why Netease picked the song, e.g. "similar to songs you liked"; often missing.
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyList {
+    /// `YYYY-MM-DD`.
+    pub date: String,
+    pub songs: Vec<RecommendedSong>,
+}
+
+/// Songs come in the old shape (`artists`, `album`, `duration`) from some endpoints and
+/// the new one (`ar`, `al`, `dt`) from others.
+fn song_of(song: &Value, reason: Option<String>) -> Option<RecommendedSong> {
+    let artists = song
+        .get("ar")
+        .or_else(|| song.get("artists"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|artist| named(artist, "id", "name"))
+        .collect();
+    let album = song.get("al").or_else(|| song.get("album"));
+    let reason = reason.or_else(|| {
+        song.get("reason")
+            .or_else(|| song.get("recommendReason"))
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    });
+    Some(RecommendedSong {
+        id: song.get("id")?.as_u64()?,
+        name: song
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        artists,
+        album: album.and_then(|album| named(album, "id", "name")),
+        cover_url: album
+            .and_then(|album| album.get("picUrl"))
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        duration_ms: song
+            .get("dt")
+            .or_else(|| song.get("duration"))
+            .and_then(Value::as_u64)
+            .unwrap_or_default(),
+        reason: reason.filter(|reason| !reason.is_empty()),
+    })
+}
+
+/// Today's list is `data.dailySongs` with reasons in `data.recommendReasons`, or a flat
+/// `recommend` list carrying a `reason` per song.
+fn daily_songs(body: &Value) -> Vec<RecommendedSong> {
+    let reasons: HashMap<u64, String> = body
+        .pointer("/data/recommendReasons")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|reason| {
+            Some((
+                reason.get("songId")?.as_u64()?,
+                reason.get("reason")?.as_str()?.to_string(),
+            ))
+        })
+        .collect();
+    body.pointer("/data/dailySongs")
+        .or_else(|| body.get("recommend"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|song| {
+            let reason = song
+                .get("id")
+                .and_then(Value::as_u64)
+                .and_then(|id| reasons.get(&id).cloned());
+            song_of(song, reason)
+        })
+        .collect()
+}
+
+/// Today's recommendations, cached for the day so a dislike replaces its track in place.
+pub struct DailyRecommendations {
+    store: Mutex<JsonStore<Option<DailyList>>>,
+}
+
+impl DailyRecommendations {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        DailyRecommendations {
+            store: Mutex::new(JsonStore::open(path)),
+        }
+    }
+
+    /// The list for `today`, fetched once per day unless `force` is set.
+    pub async fn today(
+        &self,
+        client: &NeteaseClient,
+        today: NaiveDate,
+        force: bool,
+    ) -> ApiResult<DailyList> {
+        let date = today.to_string();
+        if !force {
+            if let Some(list) = self.store.lock().unwrap().get() {
+                if list.date == date {
+                    return Ok(list.clone());
+                }
+            }
+        }
+        let body = client.call("/recommend/songs", &[]).await?;
+        let list = DailyList {
+            date,
+            songs: daily_songs(&body),
+        };
+        self.store
+            .lock()
+            .unwrap()
+            .update(|cached| *cached = Some(list.clone()))?;
+        Ok(list)
+    }
+
+    /// Tells Netease the song is unwanted and puts the replacement it sends at the same
+    /// position, or drops the song when none comes back. A song that is not in today's list
+    /// is not sent at all.
+    pub async fn dislike(&self, client: &NeteaseClient, song_id: u64) -> ApiResult<DailyList> {
+        let not_found = || ApiError::NotFound(format!("song {} is not recommended today", song_id));
+        let recommended = self
+            .store
+            .lock()
+            .unwrap()
+            .get()
+            .as_ref()
+            .map_or(false, |list| {
+                list.songs.iter().any(|song| song.id == song_id)
+            });
+        if !recommended {
+            return Err(not_found());
+        }
+        let body = client
+            .call("/recommend/songs/dislike", &[("id", &song_id.to_string())])
+            .await?;
+        let replacement = body.get("data").and_then(|song| song_of(song, None));
+        let list = self.store.lock().unwrap().update(|cached| {
+            let list = cached.as_mut()?;
+            let at = list.songs.iter().position(|song| song.id == song_id)?;
+            match replacement {
+                Some(song) if !list.songs.iter().any(|old| old.id == song.id) => {
+                    list.songs[at] = song
+                }
+                _ => {
+                    list.songs.remove(at);
+                }
+            }
+            Some(list.clone())
+        })?;
+        // a forced refresh may have replaced the list while the call was out
+        list.ok_or_else(not_found)
+    }
+}
+
+/// Past days that have recommendations, newest first.
+pub async fn history_dates(client: &NeteaseClient) -> ApiResult<Vec<String>> {
+    let body = client.call("/history/recommend/songs", &[]).await?;
+    Ok(body
+        .pointer("/data/dates")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect())
+}
+
+/// The recommendations of a past day, as listed by `history_dates`.
+pub async fn history_day(client: &NeteaseClient, date: &str) -> ApiResult<DailyList> {
+    let body = client
+        .call("/history/recommend/songs/detail", &[("date", date)])
+        .await?;
+    Ok(DailyList {
+        date: date.to_string(),
+        songs: body
+            .pointer("/data/songs")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|song| song_of(song, None))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DailyRecommendations;
+    use crate::netease::client::mock::MockTransport;
+    use crate::netease::client::{ApiError, NeteaseClient};
+    use crate::store::TempDir;
+    use chrono::NaiveDate;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_daily_dislike_in_place() {
+        let dir = TempDir::new("recommend");
+        let path = dir.join("recommend.json");
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/recommend/songs", |_| {
+                    json!({ "code": 200, "data": {
+                        "dailySongs": [
+                            { "id": 1, "name": "One", "ar": [{ "id": 7, "name": "Band" }], "al": { "id": 3, "name": "LP", "picUrl": "http://p" }, "dt": 1000 },
+                            { "id": 2, "name": "Two" },
+                            { "id": 3, "name": "Three" },
+                        ],
+                        "recommendReasons": [{ "songId": 2, "reason": "similar to songs you liked" }],
+                    } })
+                })
+                .on("/recommend/songs/dislike", |_| {
+                    json!({ "code": 200, "data": { "id": 9, "name": "Nine", "artists": [{ "id": 8, "name": "Solo" }], "duration": 2000 } })
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let daily = DailyRecommendations::open(&path);
+        let today = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+
+        let list = daily.today(&client, today, false).await.unwrap();
+        assert_eq!(list.songs[0].album.as_ref().unwrap().name, "LP");
+        assert_eq!(
+            list.songs[1].reason.as_deref(),
+            Some("similar to songs you liked")
+        );
+
+        let list = daily.dislike(&client, 2).await.unwrap();
+        let ids: Vec<u64> = list.songs.iter().map(|song| song.id).collect();
+        assert_eq!(ids, vec![1, 9, 3]);
+        assert_eq!(list.songs[1].artists[0].name, "Solo");
+
+        // a song that is not in today's list never reaches Netease
+        let err = daily.dislike(&client, 42).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+        assert_eq!(transport.calls_to("/recommend/songs/dislike").len(), 1);
+
+        // the cached list keeps the replacement for the rest of the day
+        let daily = DailyRecommendations::open(&path);
+        let list = daily.today(&client, today, false).await.unwrap();
+        assert_eq!(list.songs[1].id, 9);
+        assert_eq!(transport.calls_to("/recommend/songs").len(), 1);
+    }
+}
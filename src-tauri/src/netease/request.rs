@@ -21,20 +21,6 @@ lazy_static! {
 //     "pc", "android", "iphone", "ipad"
 // ];
 
-// pub const RESOURCE_TYPE: [&str; 7] = [
-//     "R_SO_4_",  //  歌曲
-//     "R_MV_5_",  //  MV
-//     "A_PL_0_",  //  歌单
-//     "R_AL_3_",  //  专辑
-//     "A_DJ_1_",  //  电台,
-//     "R_VI_62_", //  视频
-//     "A_EV_2_"   //  动态
-// ];
-
-// pub const OPERATOR: [&str; 3] = [
-//     "delete", "add", "reply"
-// ];
-
 const LINUX_USER_AGNET: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/60.0.3112.90 Safari/537.36";
 
 const USER_AGENT_LIST: [&str; 14] = [
@@ -0,0 +1,325 @@
+use futures::stream::{self, Stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::json::text;
+use crate::netease::client::{ApiError, ApiResult, NeteaseClient};
+use crate::store::JsonStore;
+
+/// Users requested per page of either list.
+const SOCIAL_PAGE: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SocialUser {
+    pub id: u64,
+    pub name: String,
+    pub avatar_url: String,
+    pub signature: String,
+}
+
+fn user_of(user: &Value) -> Option<SocialUser> {
+    Some(SocialUser {
+        id: user.get("userId")?.as_u64()?,
+        name: text(user, "nickname"),
+        avatar_url: text(user, "avatarUrl"),
+        signature: text(user, "signature"),
+    })
+}
+
+/// Where the next page of a list starts: `/user/follows` pages by offset, `/user/followeds`
+/// by the follow time of the last user.
+enum Cursor {
+    Offset(usize),
+    Lasttime(i64),
+    Done,
+}
+
+/// Every user `user_id` follows, page by page.
+pub fn follows(
+    client: &NeteaseClient,
+    user_id: u64,
+) -> impl Stream<Item = ApiResult<SocialUser>> + '_ {
+    walk(client, user_id, Cursor::Offset(0))
+}
+
+/// Every follower of `user_id`, most recent first.
+pub fn followers(
+    client: &NeteaseClient,
+    user_id: u64,
+) -> impl Stream<Item = ApiResult<SocialUser>> + '_ {
+    walk(client, user_id, Cursor::Lasttime(-1))
+}
+
+fn walk(
+    client: &NeteaseClient,
+    user_id: u64,
+    start: Cursor,
+) -> impl Stream<Item = ApiResult<SocialUser>> + '_ {
+    let uid = user_id.to_string();
+    stream::try_unfold(start, move |cursor| {
+        let uid = uid.clone();
+        async move {
+            let limit = SOCIAL_PAGE.to_string();
+            let (route, key, params) = match cursor {
+                Cursor::Done => return Ok(None),
+                Cursor::Offset(offset) => ("/user/follows", "follow", ("offset", offset as i64)),
+                Cursor::Lasttime(time) => ("/user/followeds", "followeds", ("lasttime", time)),
+            };
+            let body = client
+                .call(
+                    route,
+                    &[
+                        ("uid", &uid),
+                        ("limit", &limit),
+                        (params.0, &params.1.to_string()),
+                    ],
+                )
+                .await?;
+            let entries = body.get(key).and_then(Value::as_array);
+            let users: Vec<SocialUser> =
+                entries.into_iter().flatten().filter_map(user_of).collect();
+            let more = body.get("more").and_then(Value::as_bool).unwrap_or(false);
+            let next = match cursor {
+                _ if !more || users.is_empty() => Cursor::Done,
+                Cursor::Offset(offset) => Cursor::Offset(offset + users.len()),
+                _ => {
+                    let last = entries
+                        .and_then(|entries| entries.last())
+                        .and_then(|entry| entry.get("time"))
+                        .and_then(Value::as_i64);
+                    match last {
+                        Some(time) => Cursor::Lasttime(time),
+                        None => {
+                            return Err(ApiError::Malformed(
+                                "follower without a follow time".to_string(),
+                            ))
+                        }
+                    }
+                }
+            };
+            Ok(Some((stream::iter(users.into_iter().map(Ok)), next)))
+        }
+    })
+    .try_flatten()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SocialGraph {
+    pub user_id: u64,
+    /// Unix time in milliseconds.
+    pub taken_at: i64,
+    pub follows: Vec<SocialUser>,
+    pub followers: Vec<SocialUser>,
+}
+
+impl SocialGraph {
+    pub async fn fetch(client: &NeteaseClient, user_id: u64, now: i64) -> ApiResult<Self> {
+        let follows: Vec<SocialUser> = follows(client, user_id).try_collect().await?;
+        let followers: Vec<SocialUser> = followers(client, user_id).try_collect().await?;
+        Ok(SocialGraph {
+            user_id,
+            taken_at: now,
+            follows: dedupe(follows),
+            followers: dedupe(followers),
+        })
+    }
+
+    /// Users following back, in follow order.
+    pub fn mutual(&self) -> Vec<&SocialUser> {
+        let followers: HashSet<u64> = self.followers.iter().map(|user| user.id).collect();
+        self.follows
+            .iter()
+            .filter(|user| followers.contains(&user.id))
+            .collect()
+    }
+
+    /// What changed since `previous`.
+    pub fn diff(&self, previous: &SocialGraph) -> SocialDiff {
+        SocialDiff {
+            new_followers: missing(&self.followers, &previous.followers),
+            lost_followers: missing(&previous.followers, &self.followers),
+            new_follows: missing(&self.follows, &previous.follows),
+            unfollowed: missing(&previous.follows, &self.follows),
+        }
+    }
+
+    /// One row per user in either list.
+    pub fn to_csv(&self) -> String {
+        let following: HashSet<u64> = self.follows.iter().map(|user| user.id).collect();
+        let followed_by: HashSet<u64> = self.followers.iter().map(|user| user.id).collect();
+        let mut writer = csv::Writer::from_writer(vec![]);
+        let _ = writer.write_record(["id", "name", "following", "follower", "mutual", "signature"]);
+        let mut written = HashSet::new();
+        for user in self.follows.iter().chain(&self.followers) {
+            if !written.insert(user.id) {
+                continue;
+            }
+            let following = following.contains(&user.id);
+            let follower = followed_by.contains(&user.id);
+            let _ = writer.write_record([
+                user.id.to_string().as_str(),
+                &user.name,
+                following.to_string().as_str(),
+                follower.to_string().as_str(),
+                (following && follower).to_string().as_str(),
+                &user.signature,
+            ]);
+        }
+        String::from_utf8(writer.into_inner().unwrap_or_default()).unwrap_or_default()
+    }
+}
+
+fn dedupe(users: Vec<SocialUser>) -> Vec<SocialUser> {
+    let mut seen = HashSet::new();
+    users
+        .into_iter()
+        .filter(|user| seen.insert(user.id))
+        .collect()
+}
+
+fn missing(from: &[SocialUser], other: &[SocialUser]) -> Vec<SocialUser> {
+    let other: HashSet<u64> = other.iter().map(|user| user.id).collect();
+    from.iter()
+        .filter(|user| !other.contains(&user.id))
+        .cloned()
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SocialDiff {
+    pub new_followers: Vec<SocialUser>,
+    pub lost_followers: Vec<SocialUser>,
+    pub new_follows: Vec<SocialUser>,
+    pub unfollowed: Vec<SocialUser>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SocialReport {
+    pub follows: usize,
+    pub followers: usize,
+    pub mutual: Vec<SocialUser>,
+    /// Unix time in milliseconds of the snapshot compared against.
+    pub previous: Option<i64>,
+    pub diff: SocialDiff,
+}
+
+/// The latest follow graph per user, kept to report what changed on the next snapshot.
+pub struct SocialSnapshots {
+    store: Mutex<JsonStore<HashMap<u64, SocialGraph>>>,
+}
+
+impl SocialSnapshots {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        SocialSnapshots {
+            store: Mutex::new(JsonStore::open(path)),
+        }
+    }
+
+    pub fn latest(&self, user_id: u64) -> Option<SocialGraph> {
+        self.store.lock().unwrap().get().get(&user_id).cloned()
+    }
+
+    /// Walks both lists, compares them with the previous snapshot and keeps the new one.
+    pub async fn take(
+        &self,
+        client: &NeteaseClient,
+        user_id: u64,
+        now: i64,
+    ) -> ApiResult<SocialReport> {
+        let graph = SocialGraph::fetch(client, user_id, now).await?;
+        let previous = self.latest(user_id);
+        let report = SocialReport {
+            follows: graph.follows.len(),
+            followers: graph.followers.len(),
+            mutual: graph.mutual().into_iter().cloned().collect(),
+            previous: previous.as_ref().map(|previous| previous.taken_at),
+            diff: previous
+                .map(|previous| graph.diff(&previous))
+                .unwrap_or_default(),
+        };
+        self.store.lock().unwrap().update(|graphs| {
+            graphs.insert(user_id, graph);
+        })?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SocialSnapshots;
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use crate::store::TempDir;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_social_snapshots() {
+        let dir = TempDir::new("social");
+        let path = dir.join("social.json");
+        let changed = Arc::new(AtomicBool::new(false));
+        let later = changed.clone();
+        let user = |id: u64| json!({ "userId": id, "nickname": format!("u{}", id), "time": 1000 + id as i64 });
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/user/follows", move |params| {
+                    let follow: Vec<_> = match param(params, "offset") {
+                        "0" => vec![user(1), user(2)],
+                        _ => vec![user(3)],
+                    };
+                    json!({ "code": 200, "follow": follow, "more": param(params, "offset") == "0" })
+                })
+                .on("/user/followeds", move |params| {
+                    // followers come newest first and page by the time of the last one
+                    let all: Vec<u64> = if later.load(Ordering::SeqCst) {
+                        vec![4, 2, 1]
+                    } else {
+                        vec![3, 2, 1]
+                    };
+                    let lasttime: i64 = param(params, "lasttime").parse().unwrap();
+                    let page: Vec<_> = all
+                        .iter()
+                        .filter(|&&id| lasttime == -1 || 1000 + (id as i64) < lasttime)
+                        .take(2)
+                        .map(|&id| user(id))
+                        .collect();
+                    let more = lasttime == -1;
+                    json!({ "code": 200, "followeds": page, "more": more })
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let snapshots = SocialSnapshots::open(&path);
+
+        let first = snapshots.take(&client, 7, 1).await.unwrap();
+        assert_eq!((first.follows, first.followers), (3, 3));
+        assert_eq!(first.previous, None);
+        assert_eq!(first.mutual.len(), 3);
+        assert_eq!(transport.calls_to("/user/follows").len(), 2);
+        assert_eq!(
+            param(&transport.calls_to("/user/followeds")[1], "lasttime"),
+            "1002"
+        );
+
+        changed.store(true, Ordering::SeqCst);
+        let snapshots = SocialSnapshots::open(&path);
+        let second = snapshots.take(&client, 7, 2).await.unwrap();
+        let ids = |users: &[super::SocialUser]| users.iter().map(|u| u.id).collect::<Vec<_>>();
+        assert_eq!(second.previous, Some(1));
+        assert_eq!(ids(&second.diff.new_followers), vec![4]);
+        assert_eq!(ids(&second.diff.lost_followers), vec![3]);
+        assert!(second.diff.unfollowed.is_empty());
+
+        let csv = snapshots.latest(7).unwrap().to_csv();
+        assert!(csv.starts_with("id,name,following,follower,mutual,signature\n"));
+        assert!(csv.contains("3,u3,true,false,false,\n"));
+        assert!(csv.contains("4,u4,false,true,false,\n"));
+    }
+}
@@ -0,0 +1,526 @@
+use chrono::{Datelike, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::json::text;
+use crate::netease::client::{ApiResult, NeteaseClient};
+use crate::store::{JsonLinesStore, JsonStore};
+
+/// How long the catalogue is served from the cache before it is fetched again.
+const CATALOGUE_TTL_MS: i64 = 6 * 60 * 60 * 1000;
+/// How often the snapshot job checks whether today's snapshots are still missing.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A track shown on the chart overview, before the chart itself is loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewTrack {
+    pub name: String,
+    pub artist: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Toplist {
+    /// The id of the playlist behind the chart, as `/top/list` expects.
+    pub id: u64,
+    pub name: String,
+    /// As Netease words it, e.g. `每天更新`.
+    pub update_frequency: String,
+    /// Unix time in milliseconds.
+    pub update_time: i64,
+    pub cover_url: String,
+    pub description: String,
+    /// Netease's own charts carry a `ToplistType` code; partner charts do not.
+    pub official: bool,
+    pub preview: Vec<PreviewTrack>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogueState {
+    /// Unix time in milliseconds, 0 before the first fetch.
+    pub fetched_at: i64,
+    pub lists: Vec<Toplist>,
+}
+
+/// Every chart Netease currently publishes, cached for `CATALOGUE_TTL_MS`.
+pub struct ToplistCatalogue {
+    store: Mutex<JsonStore<CatalogueState>>,
+}
+
+impl ToplistCatalogue {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        ToplistCatalogue {
+            store: Mutex::new(JsonStore::open(path)),
+        }
+    }
+
+    fn fresh(&self, now: i64) -> Option<Vec<Toplist>> {
+        let store = self.store.lock().unwrap();
+        let state = store.get();
+        (!state.lists.is_empty() && now - state.fetched_at < CATALOGUE_TTL_MS)
+            .then(|| state.lists.clone())
+    }
+
+    /// The cached catalogue, fetched again once stale or when `force` is set.
+    pub async fn lists(&self, client: &NeteaseClient, force: bool) -> ApiResult<Vec<Toplist>> {
+        let now = Utc::now().timestamp_millis();
+        if !force {
+            if let Some(lists) = self.fresh(now) {
+                return Ok(lists);
+            }
+        }
+        let lists = fetch_catalogue(client).await?;
+        self.store.lock().unwrap().update(|state| {
+            state.fetched_at = now;
+            state.lists = lists.clone();
+        })?;
+        Ok(lists)
+    }
+
+    /// Looks a chart up by id or by name, ignoring case and punctuation.
+    pub async fn find(&self, client: &NeteaseClient, key: &str) -> ApiResult<Option<Toplist>> {
+        let lists = self.lists(client, false).await?;
+        let wanted = name_key(key);
+        Ok(lists
+            .into_iter()
+            .find(|list| list.id.to_string() == key.trim() || name_key(&list.name) == wanted))
+    }
+}
+
+/// Letters and digits only; bracketed text is kept since some charts differ only there,
+/// e.g. `(港台榜)` and `(内地榜)`.
+fn name_key(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// Lists the charts from `/toplist` and adds the track previews of `/toplist/detail`.
+pub async fn fetch_catalogue(client: &NeteaseClient) -> ApiResult<Vec<Toplist>> {
+    let body = client.call("/toplist", &[]).await?;
+    let detail = client.call("/toplist/detail", &[]).await?;
+    let previews: HashMap<u64, Vec<PreviewTrack>> = detail
+        .get("list")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|list| Some((list.get("id")?.as_u64()?, previews_of(list))))
+        .collect();
+
+    Ok(body
+        .get("list")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|list| {
+            let id = list.get("id")?.as_u64()?;
+            Some(Toplist {
+                id,
+                name: text(list, "name"),
+                update_frequency: text(list, "updateFrequency"),
+                update_time: list
+                    .get("updateTime")
+                    .and_then(Value::as_i64)
+                    .unwrap_or_default(),
+                cover_url: text(list, "coverImgUrl"),
+                description: text(list, "description"),
+                official: list
+                    .get("ToplistType")
+                    .map_or(false, |kind| !kind.is_null()),
+                preview: previews.get(&id).cloned().unwrap_or_default(),
+            })
+        })
+        .collect())
+}
+
+fn previews_of(list: &Value) -> Vec<PreviewTrack> {
+    list.get("tracks")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|track| PreviewTrack {
+            name: track
+                .get("first")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            artist: track
+                .get("second")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+        })
+        .collect()
+}
+
+/// The order of a chart on one day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartSnapshot {
+    pub toplist_id: u64,
+    /// Local date, `YYYY-MM-DD`.
+    pub date: String,
+    /// Track ids, first place first.
+    pub track_ids: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum Movement {
+    New,
+    /// Back on the chart after dropping off.
+    Reentry,
+    Up {
+        places: usize,
+    },
+    Down {
+        places: usize,
+    },
+    Same,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackMovement {
+    pub id: u64,
+    /// 1-based.
+    pub position: usize,
+    pub movement: Movement,
+    /// Distinct calendar weeks with at least one snapshot holding the track.
+    pub weeks_on_chart: usize,
+    pub peak: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartPoint {
+    pub toplist_id: u64,
+    pub date: String,
+    pub position: usize,
+}
+
+/// Daily chart snapshots, appended to a JSON Lines file.
+pub struct ToplistHistory {
+    store: Mutex<JsonLinesStore<ChartSnapshot>>,
+}
+
+impl ToplistHistory {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        ToplistHistory {
+            store: Mutex::new(JsonLinesStore::open(path)),
+        }
+    }
+
+    fn has(&self, toplist_id: u64, date: &str) -> bool {
+        has_snapshot(self.store.lock().unwrap().get(), toplist_id, date)
+    }
+
+    /// Stores `snapshot` unless that chart already has one for the day.
+    pub fn record(&self, snapshot: ChartSnapshot) -> io::Result<bool> {
+        // checked under the same lock as the append, so the scheduler and a manual
+        // refresh cannot both store the day
+        let mut store = self.store.lock().unwrap();
+        if has_snapshot(store.get(), snapshot.toplist_id, &snapshot.date) {
+            return Ok(false);
+        }
+        store.append(snapshot)?;
+        Ok(true)
+    }
+
+    /// Snapshots of one chart, oldest first.
+    fn of_chart(&self, toplist_id: u64) -> Vec<ChartSnapshot> {
+        let store = self.store.lock().unwrap();
+        let mut chart: Vec<ChartSnapshot> = store
+            .get()
+            .iter()
+            .filter(|snapshot| snapshot.toplist_id == toplist_id)
+            .cloned()
+            .collect();
+        chart.sort_by(|a, b| a.date.cmp(&b.date));
+        chart
+    }
+
+    /// The latest snapshot of a chart compared with its previous edition.
+    ///
+    /// Weekly charts are snapshotted daily too, so the comparison skips snapshots identical
+    /// to the latest one rather than reporting a week of no movement.
+    pub fn movement(&self, toplist_id: u64) -> Vec<TrackMovement> {
+        let chart = self.of_chart(toplist_id);
+        let latest = match chart.last() {
+            Some(latest) => latest,
+            None => return vec![],
+        };
+        let previous = chart
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.track_ids != latest.track_ids);
+
+        latest
+            .track_ids
+            .iter()
+            .enumerate()
+            .map(|(index, id)| {
+                let position = index + 1;
+                let appearances: Vec<(&str, usize)> = chart
+                    .iter()
+                    .filter_map(|snapshot| {
+                        let index = snapshot.track_ids.iter().position(|other| other == id)?;
+                        Some((snapshot.date.as_str(), index + 1))
+                    })
+                    .collect();
+                let before = previous
+                    .and_then(|previous| previous.track_ids.iter().position(|other| other == id))
+                    .map(|index| index + 1);
+                let charted_earlier = previous.map_or(false, |previous| {
+                    appearances
+                        .iter()
+                        .any(|(date, _)| *date < previous.date.as_str())
+                });
+                let movement = match before {
+                    Some(before) if before > position => Movement::Up {
+                        places: before - position,
+                    },
+                    Some(before) if before < position => Movement::Down {
+                        places: position - before,
+                    },
+                    Some(_) => Movement::Same,
+                    None if charted_earlier => Movement::Reentry,
+                    None => Movement::New,
+                };
+                let weeks: HashSet<(i32, u32)> = appearances
+                    .iter()
+                    .filter_map(|(date, _)| date.parse::<NaiveDate>().ok())
+                    .map(|date| (date.iso_week().year(), date.iso_week().week()))
+                    .collect();
+                TrackMovement {
+                    id: *id,
+                    position,
+                    movement,
+                    weeks_on_chart: weeks.len(),
+                    peak: appearances
+                        .iter()
+                        .map(|(_, position)| *position)
+                        .min()
+                        .unwrap_or(position),
+                }
+            })
+            .collect()
+    }
+
+    /// Every chart position a song held, oldest first.
+    pub fn timeline(&self, track_id: u64) -> Vec<ChartPoint> {
+        let store = self.store.lock().unwrap();
+        let mut points: Vec<ChartPoint> = store
+            .get()
+            .iter()
+            .filter_map(|snapshot| {
+                let index = snapshot.track_ids.iter().position(|id| *id == track_id)?;
+                Some(ChartPoint {
+                    toplist_id: snapshot.toplist_id,
+                    date: snapshot.date.clone(),
+                    position: index + 1,
+                })
+            })
+            .collect();
+        points.sort_by(|a, b| (&a.date, a.toplist_id).cmp(&(&b.date, b.toplist_id)));
+        points
+    }
+
+    /// Snapshots every chart of the catalogue that has none for `today` yet and returns how
+    /// many were stored. A chart that fails to load is retried on the next run.
+    pub async fn snapshot_all(
+        &self,
+        client: &NeteaseClient,
+        catalogue: &ToplistCatalogue,
+        today: NaiveDate,
+    ) -> ApiResult<usize> {
+        let date = today.to_string();
+        let mut stored = 0;
+        for list in catalogue.lists(client, false).await? {
+            if self.has(list.id, &date) {
+                continue;
+            }
+            let id = list.id.to_string();
+            let body = match client.call("/top/list", &[("id", &id)]).await {
+                Ok(body) => body,
+                Err(err) if err.is_transport() => return Err(err),
+                Err(_) => continue,
+            };
+            let track_ids = track_ids_of(&body);
+            if track_ids.is_empty() {
+                continue;
+            }
+            if self.record(ChartSnapshot {
+                toplist_id: list.id,
+                date: date.clone(),
+                track_ids,
+            })? {
+                stored += 1;
+            }
+        }
+        Ok(stored)
+    }
+}
+
+fn has_snapshot(snapshots: &[ChartSnapshot], toplist_id: u64, date: &str) -> bool {
+    snapshots
+        .iter()
+        .any(|snapshot| snapshot.toplist_id == toplist_id && snapshot.date == date)
+}
+
+/// `trackIds` holds the whole chart while `tracks` may be cut short.
+fn track_ids_of(body: &Value) -> Vec<u64> {
+    let playlist = body.get("playlist").unwrap_or(&Value::Null);
+    ["trackIds", "tracks"]
+        .iter()
+        .filter_map(|key| playlist.get(*key).and_then(Value::as_array))
+        .find(|tracks| !tracks.is_empty())
+        .into_iter()
+        .flatten()
+        .filter_map(|track| track.get("id").and_then(Value::as_u64))
+        .collect()
+}
+
+/// Checks every `SNAPSHOT_INTERVAL` and snapshots the charts once a day; charts need no login.
+pub async fn schedule(history: Arc<ToplistHistory>, catalogue: Arc<ToplistCatalogue>) {
+    let client = NeteaseClient::new("");
+    loop {
+        // charts that failed to load have no snapshot for today, so the next tick picks them up
+        let _ = history
+            .snapshot_all(&client, &catalogue, Local::now().date_naive())
+            .await;
+        tokio::time::sleep(SNAPSHOT_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChartSnapshot, Movement, ToplistCatalogue, ToplistHistory};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use crate::store::TempDir;
+    use chrono::NaiveDate;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_toplist_catalogue() {
+        let dir = TempDir::new("toplists");
+        let path = dir.join("toplists.json");
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/toplist", |_| {
+                    json!({ "code": 200, "list": [
+                        { "id": 19723756, "name": "飙升榜", "updateFrequency": "每天更新", "ToplistType": "S" },
+                        { "id": 60198, "name": "美国Billboard榜", "updateFrequency": "每周三更新", "ToplistType": null },
+                    ] })
+                })
+                .on("/toplist/detail", |_| {
+                    json!({ "code": 200, "list": [
+                        { "id": 19723756, "tracks": [{ "first": "Song", "second": "Singer" }] },
+                    ] })
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let catalogue = ToplistCatalogue::open(&path);
+
+        let lists = catalogue.lists(&client, false).await.unwrap();
+        assert_eq!(lists.len(), 2);
+        assert!(lists[0].official && !lists[1].official);
+        assert_eq!(lists[0].preview[0].artist, "Singer");
+        assert!(lists[1].preview.is_empty());
+
+        let reopened = ToplistCatalogue::open(&path);
+        let found = reopened.find(&client, "美国 billboard 榜").await.unwrap();
+        assert_eq!(found.unwrap().update_frequency, "每周三更新");
+        assert_eq!(
+            reopened
+                .find(&client, "19723756")
+                .await
+                .unwrap()
+                .unwrap()
+                .name,
+            "飙升榜"
+        );
+        assert!(reopened.find(&client, "missing").await.unwrap().is_none());
+        assert_eq!(transport.calls_to("/toplist").len(), 1);
+
+        reopened.lists(&client, true).await.unwrap();
+        assert_eq!(transport.calls_to("/toplist").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_toplist_movement() {
+        let dir = TempDir::new("charts");
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/toplist", |_| {
+                    json!({ "code": 200, "list": [{ "id": 1, "name": "A" }, { "id": 2, "name": "B" }] })
+                })
+                .on("/toplist/detail", |_| json!({ "code": 200, "list": [] }))
+                .on("/top/list", |params| match param(params, "id") {
+                    "1" => json!({ "code": 200, "playlist": { "trackIds": [{ "id": 30 }, { "id": 10 }] } }),
+                    _ => json!({ "code": 404, "message": "gone" }),
+                }),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let catalogue = ToplistCatalogue::open(dir.join("toplists.json"));
+        let history = ToplistHistory::open(dir.join("charts.jsonl"));
+        let snapshot = |date: &str, track_ids: Vec<u64>| ChartSnapshot {
+            toplist_id: 1,
+            date: date.to_string(),
+            track_ids,
+        };
+        history
+            .record(snapshot("2024-04-24", vec![40, 10]))
+            .unwrap();
+        history
+            .record(snapshot("2024-04-30", vec![10, 20, 40]))
+            .unwrap();
+        history
+            .record(snapshot("2024-05-01", vec![10, 20, 40]))
+            .unwrap();
+
+        let today = NaiveDate::from_ymd_opt(2024, 5, 2).unwrap();
+        assert_eq!(
+            history
+                .snapshot_all(&client, &catalogue, today)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            history
+                .snapshot_all(&client, &catalogue, today)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(transport.calls_to("/top/list").len(), 3);
+
+        let history = ToplistHistory::open(dir.join("charts.jsonl"));
+        let movement = history.movement(1);
+        assert_eq!(movement[0].movement, Movement::New);
+        assert_eq!(movement[1].movement, Movement::Down { places: 1 });
+        assert_eq!((movement[1].peak, movement[1].weeks_on_chart), (1, 2));
+        history
+            .record(snapshot("2024-05-03", vec![30, 40]))
+            .unwrap();
+        assert_eq!(history.movement(1)[0].movement, Movement::Same);
+        assert_eq!(history.movement(1)[1].movement, Movement::Reentry);
+        let timeline = history.timeline(40);
+        assert_eq!(timeline.len(), 4);
+        assert_eq!(
+            (timeline[1].date.as_str(), timeline[1].position),
+            ("2024-04-30", 3)
+        );
+    }
+}
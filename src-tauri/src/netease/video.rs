@@ -0,0 +1,576 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::history::Named;
+use crate::json::{named, text};
+use crate::netease::client::{ApiError, ApiResult, NeteaseClient};
+
+/// Used when `mv/detail` or `video/detail` lists no resolutions at all.
+const FALLBACK_RESOLUTION: u32 = 720;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum VideoKind {
+    Mv,
+    /// User videos, addressed by a string `vid`.
+    Video,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum VideoFormat {
+    Mp4,
+    Hls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Resolution {
+    /// Vertical resolution, e.g. 1080.
+    pub height: u32,
+    /// Bytes, 0 when unknown.
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoDetail {
+    pub kind: VideoKind,
+    pub id: String,
+    pub title: String,
+    pub cover_url: String,
+    pub duration_ms: u64,
+    pub artists: Vec<Named>,
+    /// For MVs, the album of the song the MV belongs to when it can be found.
+    pub album: Option<Named>,
+    /// Lowest first.
+    pub resolutions: Vec<Resolution>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoSource {
+    pub kind: VideoKind,
+    pub id: String,
+    pub resolution: u32,
+    /// The address after redirects.
+    pub url: String,
+    pub size: u64,
+    pub format: VideoFormat,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadProgress {
+    pub received: u64,
+    /// Bytes for MP4, segments for HLS; 0 when unknown.
+    pub total: u64,
+    pub format: VideoFormat,
+}
+
+/// The highest resolution not above `max`, or the lowest one when all of them are.
+pub fn pick_resolution(available: &[u32], max: Option<u32>) -> Option<u32> {
+    let max = max.unwrap_or(u32::MAX);
+    available
+        .iter()
+        .copied()
+        .filter(|height| *height <= max)
+        .max()
+        .or_else(|| available.iter().copied().min())
+}
+
+/// `brs` is a list of `{ br, size }` in current responses and a map keyed by resolution
+/// in older ones.
+fn mv_resolutions(data: &Value) -> Vec<Resolution> {
+    match data.get("brs") {
+        Some(Value::Array(brs)) => brs
+            .iter()
+            .filter_map(|br| {
+                Some(Resolution {
+                    height: br.get("br")?.as_u64()? as u32,
+                    size: br.get("size").and_then(Value::as_u64).unwrap_or_default(),
+                })
+            })
+            .collect(),
+        Some(Value::Object(brs)) => brs
+            .keys()
+            .filter_map(|height| height.parse().ok())
+            .map(|height| Resolution { height, size: 0 })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Title, linkage and available resolutions of an MV or video.
+pub async fn detail(client: &NeteaseClient, kind: VideoKind, id: &str) -> ApiResult<VideoDetail> {
+    let mut detail = match kind {
+        VideoKind::Mv => {
+            let body = client.call("/mv/detail", &[("mvid", id)]).await?;
+            let data = body.get("data").unwrap_or(&Value::Null);
+            let mut artists: Vec<Named> = data
+                .get("artists")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|artist| named(artist, "id", "name"))
+                .collect();
+            if artists.is_empty() {
+                artists.extend(named(data, "artistId", "artistName"));
+            }
+            VideoDetail {
+                kind,
+                id: id.to_string(),
+                title: text(data, "name"),
+                cover_url: text(data, "cover"),
+                duration_ms: data
+                    .get("duration")
+                    .and_then(Value::as_u64)
+                    .unwrap_or_default(),
+                artists,
+                album: None,
+                resolutions: mv_resolutions(data),
+            }
+        }
+        VideoKind::Video => {
+            let body = client.call("/video/detail", &[("id", id)]).await?;
+            let data = body.get("data").unwrap_or(&Value::Null);
+            let song = data.pointer("/relateSong/0").unwrap_or(&Value::Null);
+            VideoDetail {
+                kind,
+                id: id.to_string(),
+                title: text(data, "title"),
+                cover_url: text(data, "coverUrl"),
+                duration_ms: data
+                    .get("durationms")
+                    .and_then(Value::as_u64)
+                    .unwrap_or_default(),
+                artists: song
+                    .get("ar")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|artist| named(artist, "id", "name"))
+                    .collect(),
+                album: song.get("al").and_then(|album| named(album, "id", "name")),
+                resolutions: data
+                    .get("resolutions")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|resolution| {
+                        Some(Resolution {
+                            height: resolution.get("resolution")?.as_u64()? as u32,
+                            size: resolution
+                                .get("size")
+                                .and_then(Value::as_u64)
+                                .unwrap_or_default(),
+                        })
+                    })
+                    .collect(),
+            }
+        }
+    };
+    if kind == VideoKind::Mv {
+        if let Some(artist) = detail.artists.first() {
+            // a missing album is not worth failing the detail for
+            detail.album = mv_album(client, artist.id, id).await.unwrap_or(None);
+        }
+    }
+    detail
+        .resolutions
+        .sort_by_key(|resolution| resolution.height);
+    detail
+        .resolutions
+        .dedup_by_key(|resolution| resolution.height);
+    Ok(detail)
+}
+
+/// `mv/detail` has no album, so it is taken from the artist's song that links the MV.
+async fn mv_album(client: &NeteaseClient, artist_id: u64, mv_id: &str) -> ApiResult<Option<Named>> {
+    let body = client
+        .call("/artists", &[("id", &artist_id.to_string())])
+        .await?;
+    Ok(body
+        .get("hotSongs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .find(|song| {
+            song.get("mv")
+                .and_then(Value::as_u64)
+                .map_or(false, |mv| mv.to_string() == mv_id)
+        })
+        .and_then(|song| song.get("al"))
+        .and_then(|album| named(album, "id", "name")))
+}
+
+/// Resolves MVs and videos to a playable address and downloads them.
+pub struct VideoResolver {
+    http: reqwest::Client,
+}
+
+impl Default for VideoResolver {
+    fn default() -> Self {
+        VideoResolver {
+            http: reqwest::Client::new(),
+        }
+    }
+}
+
+impl VideoResolver {
+    /// Picks the best resolution up to `max` and returns where it can be fetched from.
+    pub async fn resolve(
+        &self,
+        client: &NeteaseClient,
+        kind: VideoKind,
+        id: &str,
+        max: Option<u32>,
+    ) -> ApiResult<VideoSource> {
+        let detail = detail(client, kind, id).await?;
+        let heights: Vec<u32> = detail.resolutions.iter().map(|r| r.height).collect();
+        let resolution = pick_resolution(&heights, max).unwrap_or(FALLBACK_RESOLUTION);
+        let res = resolution.to_string();
+
+        let (url, size) = match kind {
+            VideoKind::Mv => {
+                let body = client.call("/mv/url", &[("id", id), ("res", &res)]).await?;
+                let data = body.get("data").unwrap_or(&Value::Null);
+                (text(data, "url"), data.get("size").and_then(Value::as_u64))
+            }
+            VideoKind::Video => {
+                let body = client
+                    .call("/video/url", &[("id", id), ("res", &res)])
+                    .await?;
+                let data = body.pointer("/urls/0").unwrap_or(&Value::Null);
+                (text(data, "url"), data.get("size").and_then(Value::as_u64))
+            }
+        };
+        if url.is_empty() {
+            return Err(ApiError::Malformed(format!(
+                "no {}p address for {}",
+                resolution, id
+            )));
+        }
+
+        // only the first byte is asked for; the point is where the redirects end up
+        let response = self
+            .http
+            .get(&url)
+            .header("Range", "bytes=0-0")
+            .send()
+            .await?
+            .error_for_status()?;
+        let final_url = response.url().to_string();
+        let content_type = response
+            .headers()
+            .get("Content-Type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_lowercase();
+        let format = if content_type.contains("mpegurl") || response.url().path().ends_with(".m3u8")
+        {
+            VideoFormat::Hls
+        } else {
+            VideoFormat::Mp4
+        };
+
+        Ok(VideoSource {
+            kind,
+            id: id.to_string(),
+            resolution,
+            url: final_url,
+            size: size
+                .or_else(|| {
+                    detail
+                        .resolutions
+                        .iter()
+                        .find(|r| r.height == resolution)
+                        .map(|r| r.size)
+                })
+                .unwrap_or_default(),
+            format,
+        })
+    }
+
+    /// Saves `source` to `dest`; HLS segments are concatenated into one MPEG-TS file.
+    ///
+    /// Data goes to a `.part` file first so an interrupted download never looks complete.
+    pub async fn download(
+        &self,
+        source: &VideoSource,
+        dest: &Path,
+        on_progress: impl Fn(DownloadProgress),
+    ) -> ApiResult<u64> {
+        if let Some(dir) = dest.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let part = part_path(dest);
+        let mut file = File::create(&part)?;
+        let written = match source.format {
+            VideoFormat::Mp4 => self.fetch_mp4(&source.url, &mut file, &on_progress).await,
+            VideoFormat::Hls => {
+                self.fetch_hls(&source.url, source.resolution, &mut file, &on_progress)
+                    .await
+            }
+        };
+        drop(file);
+        match written {
+            Ok(written) => {
+                fs::rename(&part, dest)?;
+                Ok(written)
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&part);
+                Err(err)
+            }
+        }
+    }
+
+    async fn fetch_mp4(
+        &self,
+        url: &str,
+        file: &mut File,
+        on_progress: &impl Fn(DownloadProgress),
+    ) -> ApiResult<u64> {
+        let mut response = self.http.get(url).send().await?.error_for_status()?;
+        let total = response.content_length().unwrap_or_default();
+        let mut received = 0;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk)?;
+            received += chunk.len() as u64;
+            on_progress(DownloadProgress {
+                received,
+                total,
+                format: VideoFormat::Mp4,
+            });
+        }
+        Ok(received)
+    }
+
+    async fn fetch_hls(
+        &self,
+        url: &str,
+        resolution: u32,
+        file: &mut File,
+        on_progress: &impl Fn(DownloadProgress),
+    ) -> ApiResult<u64> {
+        let mut url = reqwest::Url::parse(url)
+            .map_err(|err| ApiError::Malformed(format!("playlist address: {}", err)))?;
+        let mut playlist = parse_playlist(
+            &self
+                .http
+                .get(url.clone())
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?,
+            &url,
+        );
+        // a master playlist only lists variants; follow the one matching the resolution
+        if playlist.segments.is_empty() {
+            url = pick_variant(&playlist.variants, resolution)
+                .ok_or_else(|| ApiError::Malformed("hls playlist without streams".to_string()))?;
+            let text = self
+                .http
+                .get(url.clone())
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            playlist = parse_playlist(&text, &url);
+        }
+        if playlist.encrypted {
+            return Err(ApiError::Malformed(
+                "encrypted hls streams are not supported".to_string(),
+            ));
+        }
+
+        let total = playlist.segments.len() as u64;
+        let mut written = 0;
+        for (index, segment) in playlist.segments.into_iter().enumerate() {
+            let bytes = self
+                .http
+                .get(segment)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+            file.write_all(&bytes)?;
+            written += bytes.len() as u64;
+            on_progress(DownloadProgress {
+                received: index as u64 + 1,
+                total,
+                format: VideoFormat::Hls,
+            });
+        }
+        Ok(written)
+    }
+}
+
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+#[derive(Debug, Default)]
+struct HlsPlaylist {
+    /// `(height, bandwidth, address)` of each stream of a master playlist.
+    variants: Vec<(u32, u64, reqwest::Url)>,
+    segments: Vec<reqwest::Url>,
+    encrypted: bool,
+}
+
+/// Reads the parts of an `m3u8` needed to fetch it; relative addresses are resolved
+/// against the playlist's own address.
+fn parse_playlist(text: &str, base: &reqwest::Url) -> HlsPlaylist {
+    let mut playlist = HlsPlaylist::default();
+    let mut stream: Option<(u32, u64)> = None;
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            let attribute = |name: &str| {
+                attributes
+                    .split(',')
+                    .find_map(|pair| pair.trim().strip_prefix(name)?.strip_prefix('='))
+            };
+            let height = attribute("RESOLUTION")
+                .and_then(|size| size.split('x').nth(1))
+                .and_then(|height| height.parse().ok())
+                .unwrap_or_default();
+            let bandwidth = attribute("BANDWIDTH")
+                .and_then(|bandwidth| bandwidth.parse().ok())
+                .unwrap_or_default();
+            stream = Some((height, bandwidth));
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-KEY:") {
+            playlist.encrypted |= !attributes.contains("METHOD=NONE");
+        } else if !line.starts_with('#') {
+            let url = match base.join(line) {
+                Ok(url) => url,
+                Err(_) => continue,
+            };
+            match stream.take() {
+                Some((height, bandwidth)) => playlist.variants.push((height, bandwidth, url)),
+                None => playlist.segments.push(url),
+            }
+        }
+    }
+    playlist
+}
+
+/// The variant whose height `pick_resolution` would choose, the widest band breaking ties.
+fn pick_variant(variants: &[(u32, u64, reqwest::Url)], resolution: u32) -> Option<reqwest::Url> {
+    let heights: Vec<u32> = variants.iter().map(|(height, _, _)| *height).collect();
+    let height = pick_resolution(&heights, Some(resolution))?;
+    variants
+        .iter()
+        .filter(|(other, _, _)| *other == height)
+        .max_by_key(|(_, bandwidth, _)| *bandwidth)
+        .map(|(_, _, url)| url.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pick_resolution, VideoFormat, VideoKind, VideoResolver};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use crate::store::TempDir;
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    /// Serves a redirect to a master playlist with a 720p and a 1080p stream.
+    fn cdn_stand_in() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                let (status, extra, body) = match path {
+                    "/mv/5.mp4" => ("302 Found", "Location: /hls/master.m3u8\r\n", String::new()),
+                    "/hls/master.m3u8" => ("200 OK", "", "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=1280x720\nv720/index.m3u8\n#EXT-X-STREAM-INF:BANDWIDTH=2000000,RESOLUTION=1920x1080\nv1080/index.m3u8\n".to_string()),
+                    "/hls/v720/index.m3u8" => ("200 OK", "", "#EXTM3U\n#EXTINF:4.0,\na.ts\n#EXTINF:4.0,\nb.ts\n#EXT-X-ENDLIST\n".to_string()),
+                    "/hls/v720/a.ts" => ("200 OK", "", "AAA".to_string()),
+                    "/hls/v720/b.ts" => ("200 OK", "", "BBB".to_string()),
+                    _ => ("404 Not Found", "", String::new()),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    extra,
+                    body.len(),
+                    body
+                );
+            }
+        });
+        host
+    }
+
+    #[tokio::test]
+    async fn test_resolve_and_download_hls() {
+        assert_eq!(pick_resolution(&[480, 720, 1080], Some(900)), Some(720));
+        assert_eq!(pick_resolution(&[720, 1080], Some(480)), Some(720));
+        assert_eq!(pick_resolution(&[], None), None);
+
+        let host = cdn_stand_in();
+        let url = format!("{}/mv/5.mp4", host);
+        let transport = Arc::new(
+            MockTransport::default()
+                .on("/mv/detail", |_| {
+                    json!({ "code": 200, "data": {
+                        "name": "MV", "artists": [{ "id": 3, "name": "A" }],
+                        "brs": [{ "br": 1080, "size": 9 }, { "br": 480 }, { "br": 720, "size": 6 }],
+                    } })
+                })
+                .on("/artists", |_| {
+                    json!({ "code": 200, "hotSongs": [
+                        { "id": 1, "mv": 0, "al": { "id": 7, "name": "Other" } },
+                        { "id": 2, "mv": 5, "al": { "id": 8, "name": "Album" } },
+                    ] })
+                })
+                .on(
+                    "/mv/url",
+                    move |_| json!({ "code": 200, "data": { "url": url } }),
+                ),
+        );
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let resolver = VideoResolver::default();
+
+        let detail = super::detail(&client, VideoKind::Mv, "5").await.unwrap();
+        assert_eq!(detail.album.unwrap().name, "Album");
+        assert_eq!(detail.resolutions[0].height, 480);
+
+        let source = resolver
+            .resolve(&client, VideoKind::Mv, "5", Some(720))
+            .await
+            .unwrap();
+        assert_eq!(param(&transport.calls_to("/mv/url")[0], "res"), "720");
+        assert_eq!((source.resolution, source.size), (720, 6));
+        assert_eq!(source.format, VideoFormat::Hls);
+        assert!(source.url.ends_with("/hls/master.m3u8"));
+
+        let dir = TempDir::new("video");
+        let dest = dir.join("mv.ts");
+        let written = resolver.download(&source, &dest, |_| {}).await.unwrap();
+        assert_eq!(written, 6);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"AAABBB");
+    }
+}
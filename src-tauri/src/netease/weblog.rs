@@ -0,0 +1,315 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::netease::client::{escape_json, ApiResult, NeteaseClient};
+use crate::store::JsonStore;
+
+/// Logs sent per `feedback/weblog` request.
+const WEBLOG_BATCH: usize = 10;
+/// A log that failed this often is dropped instead of retried again.
+const WEBLOG_MAX_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PlayAction {
+    StartPlay,
+    Play,
+}
+
+/// Why playback of a track ended, as the official client reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EndReason {
+    /// The track played to its end.
+    PlayEnd,
+    /// The user moved to another track.
+    Ui,
+    Interrupt,
+}
+
+impl EndReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EndReason::PlayEnd => "playend",
+            EndReason::Ui => "ui",
+            EndReason::Interrupt => "interrupt",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayLog {
+    pub action: PlayAction,
+    pub id: u64,
+    /// `song`, or `dj` for podcast programs.
+    #[serde(default = "default_kind")]
+    pub kind: String,
+    #[serde(default)]
+    pub wifi: bool,
+    /// Whether a downloaded copy was played.
+    #[serde(default)]
+    pub download: bool,
+    /// Seconds listened, for `play`.
+    #[serde(default)]
+    pub time: Option<u64>,
+    #[serde(default)]
+    pub end: Option<EndReason>,
+    /// Where the track was played from: `list`, `album`, `toplist`, `search`, `userfm`...
+    pub source: String,
+    #[serde(default)]
+    pub source_id: String,
+}
+
+fn default_kind() -> String {
+    "song".to_string()
+}
+
+impl PlayLog {
+    /// The entry of the `logs` array sent to `feedback/weblog`.
+    pub fn to_log(&self) -> Value {
+        let mut body = json!({
+            "id": self.id.to_string(),
+            "type": self.kind,
+            "wifi": u8::from(self.wifi),
+            "download": u8::from(self.download),
+            "source": self.source,
+            "sourceId": self.source_id,
+        });
+        match self.action {
+            PlayAction::StartPlay => {
+                body["mainsite"] = json!("1");
+                json!({ "action": "startplay", "json": body })
+            }
+            PlayAction::Play => {
+                body["time"] = json!(self.time.unwrap_or_default());
+                body["end"] = json!(self.end.unwrap_or(EndReason::PlayEnd).as_str());
+                json!({ "action": "play", "json": body })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QueuedLog {
+    /// Identifies the log while its batch is in flight.
+    seq: u64,
+    log: PlayLog,
+    attempts: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WeblogState {
+    enabled: bool,
+    queue: Vec<QueuedLog>,
+    next_seq: u64,
+}
+
+impl Default for WeblogState {
+    fn default() -> Self {
+        WeblogState {
+            enabled: true,
+            queue: vec![],
+            next_seq: 0,
+        }
+    }
+}
+
+impl WeblogState {
+    fn push(&mut self, log: PlayLog) {
+        self.next_seq += 1;
+        self.queue.push(QueuedLog {
+            seq: self.next_seq,
+            log,
+            attempts: 0,
+        });
+    }
+}
+
+/// Queues play logs and sends them to Netease in batches, keeping them across restarts
+/// until they are accepted.
+pub struct PlayReporter {
+    store: Mutex<JsonStore<WeblogState>>,
+    flushing: AtomicBool,
+}
+
+impl PlayReporter {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        PlayReporter {
+            store: Mutex::new(JsonStore::open(path)),
+            flushing: AtomicBool::new(false),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.store.lock().unwrap().get().enabled
+    }
+
+    /// Turning reporting off also discards whatever is still queued.
+    pub fn set_enabled(&self, enabled: bool) -> ApiResult<()> {
+        self.store.lock().unwrap().update(|state| {
+            state.enabled = enabled;
+            if !enabled {
+                state.queue.clear();
+            }
+        })?;
+        Ok(())
+    }
+
+    pub fn queued(&self) -> usize {
+        self.store.lock().unwrap().get().queue.len()
+    }
+
+    /// Queues `log` and sends the queue once a full batch is waiting.
+    pub async fn report(&self, client: &NeteaseClient, log: PlayLog) -> ApiResult<()> {
+        let queued = self.store.lock().unwrap().update(|state| {
+            if state.enabled {
+                state.push(log);
+            }
+            state.queue.len()
+        })?;
+        if queued >= WEBLOG_BATCH {
+            self.flush(client).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends everything queued, batch by batch. A failed batch stays queued for the next
+    /// flush until it has failed `WEBLOG_MAX_ATTEMPTS` times.
+    ///
+    /// A flush started while another is running returns right away, so no batch goes out
+    /// twice.
+    pub async fn flush(&self, client: &NeteaseClient) -> ApiResult<usize> {
+        if self.flushing.swap(true, Ordering::SeqCst) {
+            return Ok(0);
+        }
+        let result = self.send_queued(client).await;
+        self.flushing.store(false, Ordering::SeqCst);
+        result
+    }
+
+    async fn send_queued(&self, client: &NeteaseClient) -> ApiResult<usize> {
+        let mut sent = 0;
+        loop {
+            let batch: Vec<QueuedLog> = {
+                let store = self.store.lock().unwrap();
+                store
+                    .get()
+                    .queue
+                    .iter()
+                    .take(WEBLOG_BATCH)
+                    .cloned()
+                    .collect()
+            };
+            if batch.is_empty() {
+                return Ok(sent);
+            }
+
+            let logs: Vec<Value> = batch.iter().map(|queued| queued.log.to_log()).collect();
+            let logs = escape_json(&Value::Array(logs).to_string());
+            let result = client.call("/weblog", &[("logs", &logs)]).await;
+            // matched by seq, since `set_enabled` may have cleared and refilled the queue meanwhile
+            let seqs: HashSet<u64> = batch.iter().map(|queued| queued.seq).collect();
+            self.store.lock().unwrap().update(|state| {
+                if result.is_ok() {
+                    state.queue.retain(|queued| !seqs.contains(&queued.seq));
+                } else {
+                    for queued in &mut state.queue {
+                        if seqs.contains(&queued.seq) {
+                            queued.attempts += 1;
+                        }
+                    }
+                    state
+                        .queue
+                        .retain(|queued| queued.attempts < WEBLOG_MAX_ATTEMPTS);
+                }
+            })?;
+            result?;
+            sent += batch.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EndReason, PlayAction, PlayLog, PlayReporter, WEBLOG_MAX_ATTEMPTS};
+    use crate::netease::client::mock::{param, MockTransport};
+    use crate::netease::client::NeteaseClient;
+    use crate::store::TempDir;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    fn log(action: PlayAction, id: u64) -> PlayLog {
+        PlayLog {
+            action,
+            id,
+            kind: "song".to_string(),
+            wifi: true,
+            download: false,
+            time: Some(180),
+            end: Some(EndReason::Ui),
+            source: "list".to_string(),
+            source_id: "24381616".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_play_reporter() {
+        let dir = TempDir::new("weblog");
+        let path = dir.join("weblog.json");
+        let transport =
+            Arc::new(MockTransport::default().on("/weblog", |_| json!({ "code": 200 })));
+        let client = NeteaseClient::with_transport(transport.clone(), "");
+        let reporter = PlayReporter::open(&path);
+
+        reporter
+            .report(&client, log(PlayAction::StartPlay, 1))
+            .await
+            .unwrap();
+        reporter
+            .report(&client, log(PlayAction::Play, 1))
+            .await
+            .unwrap();
+        assert_eq!(reporter.queued(), 2);
+
+        // failed batches stay queued, also across restarts
+        transport.set_offline(true);
+        assert!(reporter.flush(&client).await.is_err());
+        let reporter = PlayReporter::open(&path);
+        assert_eq!(reporter.queued(), 2);
+
+        transport.set_offline(false);
+        assert_eq!(reporter.flush(&client).await.unwrap(), 2);
+        let calls = transport.calls_to("/weblog");
+        let logs: Value =
+            serde_json::from_str(&param(&calls[1], "logs").replace("\\\"", "\"")).unwrap();
+        assert_eq!(logs[0]["action"], "startplay");
+        assert_eq!(logs[1]["action"], "play");
+        assert_eq!(logs[1]["json"]["end"], "ui");
+        assert_eq!(logs[1]["json"]["sourceId"], "24381616");
+        assert_eq!(logs[1]["json"]["wifi"], 1);
+
+        transport.set_offline(true);
+        reporter
+            .report(&client, log(PlayAction::StartPlay, 2))
+            .await
+            .unwrap();
+        for _ in 0..WEBLOG_MAX_ATTEMPTS {
+            let _ = reporter.flush(&client).await;
+        }
+        assert_eq!(reporter.queued(), 0);
+
+        reporter.set_enabled(false).unwrap();
+        reporter
+            .report(&client, log(PlayAction::StartPlay, 3))
+            .await
+            .unwrap();
+        assert_eq!(reporter.queued(), 0);
+        assert!(!PlayReporter::open(&path).enabled());
+    }
+}
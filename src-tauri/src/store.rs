@@ -0,0 +1,163 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A value persisted as a JSON file in the app data directory.
+///
+/// A missing or unreadable file starts from `T::default()`, so a corrupted cache never
+/// keeps the app from starting.
+pub struct JsonStore<T> {
+    path: PathBuf,
+    value: T,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> JsonStore<T> {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let value = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        JsonStore { path, value }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Applies `f` and writes the result back to disk.
+    pub fn update<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> io::Result<R> {
+        let result = f(&mut self.value);
+        self.save()?;
+        Ok(result)
+    }
+
+    /// Writes to a sibling temp file first so a crash never leaves half a file behind.
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(&self.value)?)?;
+        fs::rename(tmp, &self.path)
+    }
+}
+
+/// Records appended to a JSON Lines file in the app data directory and kept in memory.
+///
+/// Lines that fail to parse, e.g. after a crash mid-write, are skipped.
+pub struct JsonLinesStore<T> {
+    path: PathBuf,
+    records: Vec<T>,
+}
+
+impl<T: Serialize + DeserializeOwned> JsonLinesStore<T> {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let records = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        JsonLinesStore { path, records }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self) -> &[T] {
+        &self.records
+    }
+
+    /// Writes `record` to the end of the file, and keeps it once that succeeded.
+    pub fn append(&mut self, record: T) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        self.records.push(record);
+        Ok(())
+    }
+}
+
+/// A fresh directory under the system temp dir for one test, removed on drop so a failed
+/// assert does not leave files behind.
+#[cfg(test)]
+pub(crate) struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("onelisten-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JsonLinesStore, JsonStore, TempDir};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_json_store_roundtrip() {
+        let dir = TempDir::new("store");
+        let path = dir.join("nested").join("store.json");
+
+        let mut store: JsonStore<HashMap<String, u64>> = JsonStore::open(&path);
+        assert!(store.get().is_empty());
+        store.update(|map| map.insert("a".to_string(), 1)).unwrap();
+        assert_eq!(JsonStore::<HashMap<String, u64>>::open(&path).get()["a"], 1);
+
+        std::fs::write(&path, b"{ broken").unwrap();
+        assert!(JsonStore::<HashMap<String, u64>>::open(&path)
+            .get()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_json_lines_store_append() {
+        let dir = TempDir::new("lines");
+        let path = dir.join("nested").join("lines.jsonl");
+
+        let mut store: JsonLinesStore<u64> = JsonLinesStore::open(&path);
+        store.append(1).unwrap();
+        store.append(2).unwrap();
+        // a line cut short by a crash is skipped, the ones after it still load
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| std::io::Write::write_all(&mut file, b"{ bro\n3\n"))
+            .unwrap();
+        assert_eq!(JsonLinesStore::<u64>::open(&path).get(), &[1, 2, 3]);
+    }
+}
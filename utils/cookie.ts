@@ -1,7 +1,27 @@
+import { listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/tauri";
+
 export function getCookie(): string {
     return localStorage.getItem("cookie") || "";
 }
 
 export function setCookie(cookie: string) {
     localStorage.setItem("cookie", cookie);
-}
\ No newline at end of file
+    syncSession();
+}
+
+// The app's commands and background jobs share one login held by the backend
+export function syncSession() {
+    if (window.__TAURI__) {
+        invoke("session_set_cookie", { cookie: getCookie() || null });
+    }
+}
+
+// A login refresh in the backend renews the cookie; keep it for the next start
+export function followSession() {
+    if (window.__TAURI__) {
+        listen<string>("session://cookie", (event) => {
+            localStorage.setItem("cookie", event.payload);
+        });
+    }
+}